@@ -0,0 +1,274 @@
+// 防刷屏：按用户的令牌桶限流、重复消息检测和慢速模式，状态只保存在内存中
+use std::collections::{HashMap, VecDeque};
+use std::sync::Mutex;
+use chrono::{DateTime, Utc};
+use lazy_static::lazy_static;
+use crate::models::User;
+
+// 令牌桶：最多连发 BUCKET_CAPACITY 条，之后每秒恢复 REFILL_PER_SECOND 条
+const BUCKET_CAPACITY: f64 = 5.0;
+const REFILL_PER_SECOND: f64 = 1.0;
+// FLOOD_WINDOW_SECONDS 内被限流 FLOOD_STRIKES 次视为持续刷屏，自动禁言
+const FLOOD_STRIKES: usize = 5;
+const FLOOD_WINDOW_SECONDS: i64 = 30;
+pub const AUTO_MUTE_MINUTES: i64 = 10;
+// DUPLICATE_WINDOW_SECONDS 内与最近 RECENT_CONTENTS 条消息相同视为重复
+const DUPLICATE_WINDOW_SECONDS: i64 = 60;
+const RECENT_CONTENTS: usize = 3;
+pub const MAX_SLOW_MODE_SECONDS: u32 = 6 * 60 * 60;
+
+struct UserActivity {
+    tokens: f64,
+    refilled_at: DateTime<Utc>,
+    strikes: VecDeque<DateTime<Utc>>,
+    last_sent_at: Option<DateTime<Utc>>,
+    recent_contents: VecDeque<(String, DateTime<Utc>)>,
+}
+
+impl UserActivity {
+    fn new(now: DateTime<Utc>) -> Self {
+        Self {
+            tokens: BUCKET_CAPACITY,
+            refilled_at: now,
+            strikes: VecDeque::new(),
+            last_sent_at: None,
+            recent_contents: VecDeque::new(),
+        }
+    }
+
+    fn check(&mut self, content: &str, slow_mode_seconds: u32, exempt: bool, now: DateTime<Utc>) -> Result<(), Rejection> {
+        // 慢速模式
+        if slow_mode_seconds > 0 && !exempt {
+            if let Some(last_sent_at) = self.last_sent_at {
+                let elapsed = now.signed_duration_since(last_sent_at).num_seconds();
+                if elapsed < slow_mode_seconds as i64 {
+                    return Err(Rejection::Reject(format!(
+                        "慢速模式已开启，请在{}秒后再发送",
+                        slow_mode_seconds as i64 - elapsed
+                    )));
+                }
+            }
+        }
+
+        // 令牌桶限流
+        let elapsed = now.signed_duration_since(self.refilled_at).num_milliseconds() as f64 / 1000.0;
+        self.tokens = (self.tokens + elapsed * REFILL_PER_SECOND).min(BUCKET_CAPACITY);
+        self.refilled_at = now;
+        if self.tokens < 1.0 {
+            self.strikes.retain(|t| now.signed_duration_since(*t).num_seconds() < FLOOD_WINDOW_SECONDS);
+            self.strikes.push_back(now);
+            // 豁免的用户只限流，不自动禁言
+            if self.strikes.len() >= FLOOD_STRIKES && !exempt {
+                self.strikes.clear();
+                return Err(Rejection::Flooding);
+            }
+            return Err(Rejection::Reject("发送过于频繁，请稍后再试".to_string()));
+        }
+        self.tokens -= 1.0;
+
+        // 重复消息，只有图片没有文字的消息不检查
+        let normalized = normalize(content);
+        if !normalized.is_empty() {
+            let duplicate = self.recent_contents.iter().any(|(recent, sent_at)| {
+                *recent == normalized && now.signed_duration_since(*sent_at).num_seconds() < DUPLICATE_WINDOW_SECONDS
+            });
+            if duplicate {
+                return Err(Rejection::Reject("请勿重复发送相同的消息".to_string()));
+            }
+        }
+
+        Ok(())
+    }
+
+    fn record_sent(&mut self, content: &str, now: DateTime<Utc>) {
+        self.last_sent_at = Some(now);
+
+        let normalized = normalize(content);
+        if !normalized.is_empty() {
+            self.recent_contents.push_back((normalized, now));
+            if self.recent_contents.len() > RECENT_CONTENTS {
+                self.recent_contents.pop_front();
+            }
+        }
+    }
+}
+
+lazy_static! {
+    // user_id -> 最近的发送情况
+    static ref ACTIVITY: Mutex<HashMap<String, UserActivity>> = Mutex::new(HashMap::new());
+}
+
+pub enum Rejection {
+    Reject(String),
+    // 持续刷屏，需要自动禁言
+    Flooding,
+}
+
+fn normalize(content: &str) -> String {
+    content.split_whitespace().collect::<Vec<_>>().join(" ").to_lowercase()
+}
+
+// 发送前检查，通过时消耗一个令牌；消息保存后需调用 record_sent。
+// 机器人账号由管理员创建，Webhook 另有自己的频率限制，这里不检查；
+// exempt 为拥有 bypass_slow_mode 权限的用户
+pub fn check(user: &User, content: &str, slow_mode_seconds: u32, exempt: bool) -> Result<(), Rejection> {
+    if user.is_bot {
+        return Ok(());
+    }
+    let now = Utc::now();
+    let mut activity = ACTIVITY.lock().unwrap();
+    activity.entry(user.id.clone())
+        .or_insert_with(|| UserActivity::new(now))
+        .check(content, slow_mode_seconds, exempt, now)
+}
+
+pub fn record_sent(user_id: &str, content: &str) {
+    let now = Utc::now();
+    let mut activity = ACTIVITY.lock().unwrap();
+    activity.entry(user_id.to_string())
+        .or_insert_with(|| UserActivity::new(now))
+        .record_sent(content, now);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Duration;
+
+    fn rejected(result: Result<(), Rejection>) -> Option<String> {
+        match result {
+            Ok(()) => None,
+            Err(Rejection::Reject(message)) => Some(message),
+            Err(Rejection::Flooding) => Some("flooding".to_string()),
+        }
+    }
+
+    fn drain(activity: &mut UserActivity, now: DateTime<Utc>) {
+        for i in 0..BUCKET_CAPACITY as usize {
+            assert_eq!(rejected(activity.check(&format!("消息{}", i), 0, false, now)), None);
+        }
+    }
+
+    #[test]
+    fn bucket_refills_over_time() {
+        let t0 = Utc::now();
+        let mut activity = UserActivity::new(t0);
+        drain(&mut activity, t0);
+        assert_eq!(rejected(activity.check("a", 0, false, t0)).as_deref(), Some("发送过于频繁，请稍后再试"));
+
+        // 一秒恢复一个令牌
+        let t1 = t0 + Duration::seconds(1);
+        assert_eq!(rejected(activity.check("b", 0, false, t1)), None);
+        assert!(rejected(activity.check("c", 0, false, t1)).is_some());
+
+        // 恢复不超过容量
+        let t2 = t1 + Duration::seconds(60);
+        drain(&mut activity, t2);
+        assert!(rejected(activity.check("d", 0, false, t2)).is_some());
+    }
+
+    #[test]
+    fn repeated_strikes_within_window_trigger_auto_mute() {
+        let t0 = Utc::now();
+        let mut activity = UserActivity::new(t0);
+        drain(&mut activity, t0);
+
+        // 每次间隔 100 毫秒，不足以恢复一个令牌
+        let at = |i: i64| t0 + Duration::milliseconds(100 * i);
+        for i in 1..FLOOD_STRIKES as i64 {
+            assert_eq!(rejected(activity.check("x", 0, false, at(i))).as_deref(), Some("发送过于频繁，请稍后再试"));
+        }
+        assert_eq!(rejected(activity.check("x", 0, false, at(FLOOD_STRIKES as i64))).as_deref(), Some("flooding"));
+
+        // 触发后重新计数
+        assert_eq!(rejected(activity.check("x", 0, false, at(FLOOD_STRIKES as i64 + 1))).as_deref(), Some("发送过于频繁，请稍后再试"));
+    }
+
+    #[test]
+    fn strikes_outside_window_are_forgotten() {
+        let t0 = Utc::now();
+        let mut activity = UserActivity::new(t0);
+        drain(&mut activity, t0);
+        for _ in 1..FLOOD_STRIKES {
+            assert!(rejected(activity.check("x", 0, false, t0)).is_some());
+        }
+
+        let later = t0 + Duration::seconds(FLOOD_WINDOW_SECONDS + 1);
+        drain(&mut activity, later);
+        assert_eq!(rejected(activity.check("x", 0, false, later)).as_deref(), Some("发送过于频繁，请稍后再试"));
+    }
+
+    #[test]
+    fn exempt_users_are_rate_limited_but_never_muted() {
+        let t0 = Utc::now();
+        let mut activity = UserActivity::new(t0);
+        drain(&mut activity, t0);
+        for _ in 0..FLOOD_STRIKES * 3 {
+            assert_eq!(rejected(activity.check("x", 0, true, t0)).as_deref(), Some("发送过于频繁，请稍后再试"));
+        }
+    }
+
+    #[test]
+    fn duplicates_are_rejected_within_window() {
+        let t0 = Utc::now();
+        let mut activity = UserActivity::new(t0);
+        activity.record_sent("Hello   World", t0);
+
+        let t1 = t0 + Duration::seconds(1);
+        assert_eq!(rejected(activity.check(" hello world ", 0, false, t1)).as_deref(), Some("请勿重复发送相同的消息"));
+        assert_eq!(rejected(activity.check("hello there", 0, false, t1)), None);
+
+        let t2 = t0 + Duration::seconds(DUPLICATE_WINDOW_SECONDS + 1);
+        assert_eq!(rejected(activity.check("hello world", 0, false, t2)), None);
+    }
+
+    #[test]
+    fn only_recent_contents_count_as_duplicates() {
+        let t0 = Utc::now();
+        let mut activity = UserActivity::new(t0);
+        for content in ["a", "b", "c", "d"] {
+            activity.record_sent(content, t0);
+        }
+        assert_eq!(rejected(activity.check("a", 0, false, t0)), None);
+        assert!(rejected(activity.check("d", 0, false, t0)).is_some());
+        // 只有图片的消息没有文字，不算重复
+        activity.record_sent("", t0);
+        assert_eq!(rejected(activity.check("", 0, false, t0)), None);
+    }
+
+    #[test]
+    fn slow_mode_applies_unless_exempt() {
+        let t0 = Utc::now();
+        let mut activity = UserActivity::new(t0);
+        activity.record_sent("a", t0);
+
+        let t1 = t0 + Duration::seconds(4);
+        assert_eq!(rejected(activity.check("b", 10, false, t1)).as_deref(), Some("慢速模式已开启，请在6秒后再发送"));
+        assert_eq!(rejected(activity.check("b", 10, true, t1)), None);
+        assert_eq!(rejected(activity.check("c", 10, false, t0 + Duration::seconds(10))), None);
+    }
+
+    #[test]
+    fn bots_are_not_checked() {
+        let bot = User {
+            id: "antispam-test-bot".to_string(),
+            username: "bot".to_string(),
+            email: String::new(),
+            password_hash: String::new(),
+            avatar: None,
+            display_name: None,
+            role: crate::models::ROLE_MEMBER.to_string(),
+            status: crate::models::UserStatus::Active,
+            created_at: Utc::now(),
+            last_ips: Vec::new(),
+            muted_until: None,
+            mute_reason: None,
+            is_bot: true,
+            ban: None,
+        };
+        for _ in 0..BUCKET_CAPACITY as usize * FLOOD_STRIKES * 2 {
+            assert_eq!(rejected(check(&bot, "same", 60, false)), None);
+        }
+        assert!(!ACTIVITY.lock().unwrap().contains_key(&bot.id));
+    }
+}
\ No newline at end of file
@@ -0,0 +1,166 @@
+// 审计日志：记录管理操作的执行者、操作、对象、参数和原因，只追加不修改
+use actix_web::{web, HttpRequest, HttpResponse, Result};
+use chrono::Utc;
+use uuid::Uuid;
+use crate::APP_STATE;
+use crate::models::*;
+
+const MAX_REASON_CHARS: usize = 500;
+const DEFAULT_PAGE_SIZE: usize = 50;
+const MAX_PAGE_SIZE: usize = 200;
+// 系统自动执行的操作（如自动禁言）使用的操作者 ID
+pub const SYSTEM_ACTOR: &str = "system";
+
+// 去掉首尾空白，空字符串视为未填写
+pub fn normalize_reason(reason: Option<&str>) -> Result<Option<String>, String> {
+    let reason = reason.map(str::trim).filter(|r| !r.is_empty());
+    if reason.map(|r| r.chars().count() > MAX_REASON_CHARS).unwrap_or(false) {
+        return Err(format!("原因不能超过{}个字符", MAX_REASON_CHARS));
+    }
+    Ok(reason.map(str::to_string))
+}
+
+fn user_name(user: &User) -> String {
+    user.display_name.clone()
+        .filter(|n| !n.is_empty())
+        .unwrap_or_else(|| user.username.clone())
+}
+
+// 调用方需持有 APP_STATE 的锁；target 为操作对象的快照，对象可能已被删除
+pub fn record(
+    state: &mut AppState,
+    actor_id: &str,
+    action: AuditAction,
+    target: Option<&User>,
+    params: serde_json::Value,
+    reason: Option<String>,
+) {
+    let entry = AuditEntry {
+        id: Uuid::new_v4().to_string(),
+        timestamp: Utc::now(),
+        actor_id: actor_id.to_string(),
+        actor_name: if actor_id == SYSTEM_ACTOR {
+            "系统".to_string()
+        } else {
+            state.users.get(actor_id).map(user_name).unwrap_or_default()
+        },
+        action,
+        target_id: target.map(|u| u.id.clone()),
+        target_name: target.map(user_name),
+        params,
+        reason,
+    };
+    state.append_audit_entry(entry);
+}
+
+fn matches(entry: &AuditEntry, query: &AuditLogQuery) -> bool {
+    query.action.map(|a| a == entry.action).unwrap_or(true)
+        && query.actor_id.as_ref().map(|id| *id == entry.actor_id).unwrap_or(true)
+        && query.target_id.as_ref().map(|id| Some(id) == entry.target_id.as_ref()).unwrap_or(true)
+        && query.from.map(|from| entry.timestamp >= from).unwrap_or(true)
+        && query.to.map(|to| entry.timestamp < to).unwrap_or(true)
+}
+
+// 按条件筛选，最新的在前
+fn filtered<'a>(state: &'a AppState, query: &AuditLogQuery) -> Vec<&'a AuditEntry> {
+    state.audit_log.iter()
+        .rev()
+        .filter(|entry| matches(entry, query))
+        .collect()
+}
+
+fn csv_field(value: &str) -> String {
+    // 防止在表格软件中被当作公式执行
+    let value = if value.starts_with(['=', '+', '-', '@']) {
+        format!("'{}", value)
+    } else {
+        value.to_string()
+    };
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value
+    }
+}
+
+fn to_csv(entries: &[&AuditEntry]) -> String {
+    let mut csv = String::from("id,timestamp,actor_id,actor_name,action,target_id,target_name,reason,params\r\n");
+    for entry in entries {
+        let action = serde_json::to_value(entry.action).unwrap();
+        let fields = [
+            entry.id.clone(),
+            entry.timestamp.to_rfc3339(),
+            entry.actor_id.clone(),
+            entry.actor_name.clone(),
+            action.as_str().unwrap_or_default().to_string(),
+            entry.target_id.clone().unwrap_or_default(),
+            entry.target_name.clone().unwrap_or_default(),
+            entry.reason.clone().unwrap_or_default(),
+            entry.params.to_string(),
+        ];
+        let row: Vec<String> = fields.iter().map(|f| csv_field(f)).collect();
+        csv.push_str(&row.join(","));
+        csv.push_str("\r\n");
+    }
+    csv
+}
+
+pub async fn get_audit_log(
+    req: HttpRequest,
+    query: web::Query<AuditLogQuery>,
+) -> Result<HttpResponse> {
+    if crate::auth::permitted_user_id(&req, Permission::ViewAuditLog).is_some() {
+        let page = query.page.unwrap_or(1).max(1);
+        let page_size = query.page_size.unwrap_or(DEFAULT_PAGE_SIZE).clamp(1, MAX_PAGE_SIZE);
+
+        let state = APP_STATE.lock().unwrap();
+        let entries = filtered(&state, &query);
+        let total = entries.len();
+        let entries = entries.into_iter()
+            .skip((page - 1).saturating_mul(page_size))
+            .take(page_size)
+            .collect();
+
+        return Ok(HttpResponse::Ok().json(ApiResponse::success(AuditLogPage {
+            total,
+            page,
+            page_size,
+            entries,
+        })));
+    }
+
+    Ok(HttpResponse::Unauthorized().json(ApiResponse::<()>::error(
+        "无权访问".to_string()
+    )))
+}
+
+// 导出全部符合条件的记录（不分页），format=csv 或 json
+pub async fn export_audit_log(
+    req: HttpRequest,
+    query: web::Query<AuditLogQuery>,
+) -> Result<HttpResponse> {
+    if crate::auth::permitted_user_id(&req, Permission::ViewAuditLog).is_some() {
+        let state = APP_STATE.lock().unwrap();
+        let entries = filtered(&state, &query);
+        let date = Utc::now().format("%Y%m%d");
+
+        return Ok(match query.format.as_deref().unwrap_or("json") {
+            "csv" => HttpResponse::Ok()
+                .content_type("text/csv; charset=utf-8")
+                .insert_header(("Content-Disposition", format!("attachment; filename=\"audit-log-{}.csv\"", date)))
+                // 带 BOM，Excel 才能正确识别 UTF-8 中文
+                .body(format!("\u{feff}{}", to_csv(&entries))),
+            "json" => HttpResponse::Ok()
+                .content_type("application/json")
+                .insert_header(("Content-Disposition", format!("attachment; filename=\"audit-log-{}.json\"", date)))
+                .body(serde_json::to_string_pretty(&entries).unwrap()),
+            _ => HttpResponse::Ok().json(ApiResponse::<()>::error(
+                "导出格式只支持 csv 或 json".to_string()
+            )),
+        });
+    }
+
+    Ok(HttpResponse::Unauthorized().json(ApiResponse::<()>::error(
+        "无权访问".to_string()
+    )))
+}
\ No newline at end of file
@@ -0,0 +1,241 @@
+// 请求身份：浏览器的 session_token cookie，或 Authorization: Bearer 个人访问令牌
+use actix_web::body::MessageBody;
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::http::Method;
+use actix_web::middleware::Next;
+use actix_web::{Error, HttpMessage, HttpRequest, HttpResponse};
+use std::sync::Mutex;
+use chrono::{DateTime, Duration, Utc};
+use lazy_static::lazy_static;
+use rand::{thread_rng, Rng};
+use sha2::{Digest, Sha256};
+use crate::APP_STATE;
+use crate::models::*;
+
+// 令牌明文的前缀，便于识别泄露的令牌
+const TOKEN_PREFIX: &str = "cpt_";
+// 最近使用时间的落盘间隔，避免每个请求都写文件
+const LAST_USED_SAVE_INTERVAL_SECONDS: i64 = 60;
+
+lazy_static! {
+    static ref LAST_USED_SAVED_AT: Mutex<Option<DateTime<Utc>>> = Mutex::new(None);
+}
+
+// 通过访问令牌认证的请求携带其权限范围；通过 cookie 认证的请求没有此扩展，不受限制
+#[derive(Clone)]
+pub struct TokenScopes(pub Vec<TokenScope>);
+
+pub fn generate_token() -> String {
+    let bytes: [u8; 24] = thread_rng().gen();
+    format!("{}{}", TOKEN_PREFIX, hex::encode(bytes))
+}
+
+pub fn hash_token(token: &str) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(token.as_bytes());
+    hex::encode(hasher.finalize())
+}
+
+fn bearer_token(req: &HttpRequest) -> Option<String> {
+    req.headers()
+        .get("Authorization")?
+        .to_str()
+        .ok()?
+        .strip_prefix("Bearer ")
+        .map(|token| token.trim().to_string())
+}
+
+// 请求携带的凭据，两种凭据分别查找，访问令牌不能当作会话 cookie 使用
+pub enum Credential {
+    Session(String),
+    ApiToken(String),
+}
+
+// 优先使用 Bearer 令牌，其次是会话 cookie；Bearer 令牌只在经过 token_guard 校验后使用
+pub fn request_token(req: &HttpRequest) -> Option<Credential> {
+    if let Some(token) = bearer_token(req) {
+        let checked = req.extensions().get::<TokenScopes>().is_some();
+        return checked.then_some(Credential::ApiToken(token));
+    }
+    req.cookie("session_token").map(|c| Credential::Session(c.value().to_string()))
+}
+
+// 请求者拥有指定权限时返回其用户ID
+pub fn permitted_user_id(req: &HttpRequest, permission: Permission) -> Option<String> {
+    let token = request_token(req)?;
+    let state = APP_STATE.lock().unwrap();
+    let user_id = state.token_user_id(&token)?;
+    crate::permissions::has_permission(&state, user_id, permission).then(|| user_id.clone())
+}
+
+// 本次请求的令牌权限；通过 cookie 认证时为 None
+pub fn request_scopes(req: &HttpRequest) -> Option<Vec<TokenScope>> {
+    req.extensions().get::<TokenScopes>().map(|TokenScopes(scopes)| scopes.clone())
+}
+
+// 本次请求是否具有指定权限
+pub fn allows(req: &HttpRequest, scope: TokenScope) -> bool {
+    match req.extensions().get::<TokenScopes>() {
+        Some(TokenScopes(scopes)) => scopes.contains(&scope),
+        None => true,
+    }
+}
+
+// 各接口通过访问令牌调用时所需的权限；返回 None 的接口只能在浏览器中登录后使用
+fn required_scope(method: &Method, path: &str) -> Option<TokenScope> {
+    let path = path.strip_prefix("/api").unwrap_or(path);
+    match path {
+        "/messages" | "/current-user" | "/mention-checks" | "/unread-mentions"
+        | "/online-users" | "/ws" | "/events" | "/poll-events" | "/ws-schema"
+        | "/public-settings" | "/commands" | "/roles" if method == Method::GET => Some(TokenScope::ReadMessages),
+        "/send-message" | "/upload-image" | "/upload-avatar" | "/recall-message" | "/react-message"
+        | "/mark-read" | "/mark-mentions-checked" | "/register-command"
+        | "/unregister-command" | "/command-reply" | "/report-message" => Some(TokenScope::SendMessages),
+        "/pending-users" | "/approve-user" | "/reject-user" | "/users" | "/add-user"
+        | "/delete-user" | "/settings" | "/update-settings" | "/set-deputy-admin"
+        | "/mute-user" | "/unmute-user" | "/ban-user" | "/unban-user" | "/update-user-display-name"
+        | "/audit-log" | "/export-audit-log" | "/blacklist" | "/add-ip-blacklist"
+        | "/remove-ip-blacklist" | "/add-email-blacklist" | "/remove-email-blacklist"
+        | "/update-allowed-email-domains" | "/moderation-config" | "/add-filter-rule"
+        | "/delete-filter-rule" | "/update-link-policy" | "/moderation-flags"
+        | "/review-moderation-flag" | "/moderation-queue" | "/resolve-report"
+        | "/purge-user-messages" | "/create-role" | "/update-role" | "/delete-role"
+        | "/set-user-role" => {
+            Some(TokenScope::Moderate)
+        }
+        _ => None,
+    }
+}
+
+// 校验请求中的访问令牌：令牌有效、用户正常、接口在令牌权限范围内，并记录最近使用时间
+fn check_bearer(req: &ServiceRequest) -> Result<(), HttpResponse> {
+    let token = match bearer_token(req.request()) {
+        Some(token) => token,
+        None => return Ok(()),
+    };
+
+    let mut state = APP_STATE.lock().unwrap();
+    let token_hash = hash_token(&token);
+    let api_token = match state.api_tokens.values().find(|t| t.token_hash == token_hash && t.is_active()) {
+        Some(api_token) => api_token.clone(),
+        None => {
+            return Err(HttpResponse::Unauthorized().json(ApiResponse::<()>::error(
+                "访问令牌无效或已过期".to_string()
+            )));
+        }
+    };
+
+    let user_active = state.users.get(&api_token.user_id)
+        .map(|u| u.status == UserStatus::Active)
+        .unwrap_or(false);
+    if !user_active {
+        return Err(HttpResponse::Unauthorized().json(ApiResponse::<()>::error(
+            "访问令牌无效或已过期".to_string()
+        )));
+    }
+
+    match required_scope(req.method(), req.path()) {
+        Some(scope) if api_token.scopes.contains(&scope) => {}
+        Some(scope) => {
+            return Err(HttpResponse::Forbidden().json(ApiResponse::<()>::error(
+                format!("访问令牌缺少权限：{:?}", scope)
+            )));
+        }
+        None => {
+            return Err(HttpResponse::Forbidden().json(ApiResponse::<()>::error(
+                "该接口不支持使用访问令牌".to_string()
+            )));
+        }
+    }
+
+    let now = Utc::now();
+    if let Some(stored) = state.api_tokens.get_mut(&api_token.id) {
+        stored.last_used_at = Some(now);
+    }
+    let mut saved_at = LAST_USED_SAVED_AT.lock().unwrap();
+    let should_save = saved_at
+        .map(|t| now.signed_duration_since(t) >= Duration::seconds(LAST_USED_SAVE_INTERVAL_SECONDS))
+        .unwrap_or(true);
+    if should_save {
+        state.save_api_tokens();
+        *saved_at = Some(now);
+    }
+    drop(saved_at);
+    drop(state);
+
+    req.extensions_mut().insert(TokenScopes(api_token.scopes));
+    Ok(())
+}
+
+pub async fn token_guard(
+    req: ServiceRequest,
+    next: Next<impl MessageBody + 'static>,
+) -> Result<ServiceResponse<impl MessageBody>, Error> {
+    if let Err(response) = check_bearer(&req) {
+        return Ok(req.into_response(response).map_into_right_body());
+    }
+
+    next.call(req).await.map(ServiceResponse::map_into_left_body)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::cookie::Cookie;
+    use actix_web::test::TestRequest;
+
+    fn state_with_token(token: &str) -> AppState {
+        let mut state = AppState::new();
+        state.sessions.insert("session-1".to_string(), "u1".to_string());
+        state.api_tokens.insert("t1".to_string(), ApiToken {
+            id: "t1".to_string(),
+            user_id: "u2".to_string(),
+            name: "ci".to_string(),
+            token_hash: hash_token(token),
+            scopes: vec![TokenScope::ReadMessages],
+            created_by: "u2".to_string(),
+            created_at: Utc::now(),
+            expires_at: None,
+            last_used_at: None,
+            revoked_at: None,
+        });
+        state
+    }
+
+    #[test]
+    fn access_token_in_cookie_is_rejected() {
+        let token = generate_token();
+        let state = state_with_token(&token);
+
+        let req = TestRequest::default().cookie(Cookie::new("session_token", token)).to_http_request();
+        let credential = request_token(&req).unwrap();
+        assert!(state.token_user_id(&credential).is_none());
+    }
+
+    #[test]
+    fn session_cookie_is_accepted() {
+        let state = state_with_token(&generate_token());
+
+        let req = TestRequest::default().cookie(Cookie::new("session_token", "session-1")).to_http_request();
+        let credential = request_token(&req).unwrap();
+        assert_eq!(state.token_user_id(&credential).map(String::as_str), Some("u1"));
+    }
+
+    #[test]
+    fn bearer_token_is_used_only_after_guard_check() {
+        let token = generate_token();
+        let state = state_with_token(&token);
+
+        let req = TestRequest::default()
+            .insert_header(("Authorization", format!("Bearer {}", token)))
+            .to_http_request();
+        assert!(request_token(&req).is_none());
+
+        req.extensions_mut().insert(TokenScopes(vec![TokenScope::ReadMessages]));
+        let credential = request_token(&req).unwrap();
+        assert_eq!(state.token_user_id(&credential).map(String::as_str), Some("u2"));
+
+        // 会话 token 不能作为 Bearer 令牌使用
+        assert!(state.token_user_id(&Credential::ApiToken("session-1".to_string())).is_none());
+    }
+}
\ No newline at end of file
@@ -0,0 +1,429 @@
+// 黑名单：IP（支持 CIDR 网段）和邮箱（支持域名通配），以及注册邮箱域名白名单
+use std::net::IpAddr;
+use actix_web::{web, HttpRequest, HttpResponse, Result};
+use chrono::{Duration, Utc};
+use ipnet::IpNet;
+use crate::APP_STATE;
+use crate::models::*;
+
+// 黑名单时长上限（分钟）
+const MAX_DURATION_MINUTES: i64 = 365 * 24 * 60;
+const MAX_REASON_CHARS: usize = 200;
+const MAX_ALLOWED_DOMAINS: usize = 100;
+
+impl IpBlacklist {
+    pub fn matches(&self, ip: &str) -> bool {
+        let ip: IpAddr = match ip.parse() {
+            Ok(ip) => ip,
+            Err(_) => return self.ip == ip,
+        };
+        match self.ip.parse::<IpNet>() {
+            Ok(net) => net.contains(&ip),
+            Err(_) => self.ip.parse::<IpAddr>().map(|entry| entry == ip).unwrap_or(false),
+        }
+    }
+}
+
+impl EmailBlacklist {
+    pub fn matches(&self, email: &str) -> bool {
+        let email = email.to_lowercase();
+        let (local, domain) = match email.rsplit_once('@') {
+            Some(parts) => parts,
+            None => return false,
+        };
+        match self.pattern.rsplit_once('@') {
+            Some((pattern_local, pattern_domain)) => {
+                (pattern_local == "*" || pattern_local == local) && domain_matches(pattern_domain, domain)
+            }
+            None => false,
+        }
+    }
+}
+
+// *.example.com 匹配所有子域名，不匹配 example.com 本身
+fn domain_matches(pattern: &str, domain: &str) -> bool {
+    match pattern.strip_prefix("*.") {
+        Some(suffix) => domain.strip_suffix(suffix).map(|rest| rest.ends_with('.')).unwrap_or(false),
+        None => pattern == domain,
+    }
+}
+
+fn is_valid_domain_pattern(pattern: &str) -> bool {
+    let domain = pattern.strip_prefix("*.").unwrap_or(pattern);
+    domain.contains('.')
+        && domain.split('.').all(|label| {
+            !label.is_empty() && label.chars().all(|c| c.is_ascii_alphanumeric() || c == '-')
+        })
+}
+
+// 规范化 IP 或 CIDR；CIDR 去掉主机位，如 10.0.0.5/8 -> 10.0.0.0/8
+fn normalize_ip(value: &str) -> Option<String> {
+    let value = value.trim();
+    if let Ok(net) = value.parse::<IpNet>() {
+        return Some(net.trunc().to_string());
+    }
+    value.parse::<IpAddr>().ok().map(|ip| ip.to_string())
+}
+
+fn normalize_email_pattern(value: &str) -> Option<String> {
+    let value = value.trim().to_lowercase();
+    let (local, domain) = value.rsplit_once('@')?;
+    let local_ok = local == "*" || (!local.is_empty() && !local.contains(['*', '@']) && !local.contains(char::is_whitespace));
+    (local_ok && is_valid_domain_pattern(domain)).then_some(value)
+}
+
+fn normalize_reason(reason: &str) -> Result<String, String> {
+    let reason = reason.trim();
+    if reason.chars().count() > MAX_REASON_CHARS {
+        return Err(format!("原因不能超过{}个字符", MAX_REASON_CHARS));
+    }
+    Ok(reason.to_string())
+}
+
+fn validate_duration(duration_minutes: Option<i64>) -> Result<(), String> {
+    if duration_minutes.map(|m| !(1..=MAX_DURATION_MINUTES).contains(&m)).unwrap_or(false) {
+        return Err("时长需在1分钟到365天之间".to_string());
+    }
+    Ok(())
+}
+
+// 解析 data/blacklist.json，返回 IP 黑名单、邮箱黑名单和域名白名单
+pub fn parse_saved(data: &str) -> Option<(Vec<IpBlacklist>, Vec<EmailBlacklist>, Vec<String>)> {
+    if let Ok(blacklist) = serde_json::from_str::<(Vec<IpBlacklist>, Vec<EmailBlacklist>, Vec<String>)>(data) {
+        return Some(blacklist);
+    }
+    // 旧格式：邮箱黑名单只有邮箱地址，没有域名白名单
+    let (ip, email) = serde_json::from_str::<(Vec<IpBlacklist>, Vec<String>)>(data).ok()?;
+    let email = email.into_iter()
+        .map(|email| EmailBlacklist {
+            pattern: email.to_lowercase(),
+            reason: String::new(),
+            until: None,
+            added_by: None,
+        })
+        .collect();
+    Some((ip, email, Vec::new()))
+}
+
+// 注册时检查 IP，被禁止时返回提示
+pub fn check_ip(state: &AppState, ip: &str) -> Result<(), String> {
+    let now = Utc::now();
+    match state.ip_blacklist.iter().find(|b| b.until.map(|until| until > now).unwrap_or(true) && b.matches(ip)) {
+        Some(entry) if entry.until.is_some() => Err("您的IP已被临时封禁".to_string()),
+        Some(_) => Err("您的IP已被禁止注册".to_string()),
+        None => Ok(()),
+    }
+}
+
+// 注册时检查邮箱黑名单和域名白名单
+pub fn check_email(state: &AppState, email: &str) -> Result<(), String> {
+    let now = Utc::now();
+    if state.email_blacklist.iter().any(|b| b.until.map(|until| until > now).unwrap_or(true) && b.matches(email)) {
+        return Err("该邮箱已被禁止注册".to_string());
+    }
+    if !state.allowed_email_domains.is_empty() {
+        let email = email.to_lowercase();
+        let domain = email.rsplit_once('@').map(|(_, domain)| domain).unwrap_or_default();
+        if !state.allowed_email_domains.iter().any(|pattern| domain_matches(pattern, domain)) {
+            return Err("不支持使用该邮箱域名注册".to_string());
+        }
+    }
+    Ok(())
+}
+
+pub async fn get_blacklist(req: HttpRequest) -> Result<HttpResponse> {
+    if crate::auth::permitted_user_id(&req, Permission::ManageModeration).is_some() {
+        let mut state = APP_STATE.lock().unwrap();
+        state.clean_expired_data();
+        return Ok(HttpResponse::Ok().json(ApiResponse::success(BlacklistResponse {
+            ip: &state.ip_blacklist,
+            email: &state.email_blacklist,
+            allowed_email_domains: &state.allowed_email_domains,
+        })));
+    }
+
+    Ok(HttpResponse::Unauthorized().json(ApiResponse::<()>::error(
+        "无权访问".to_string()
+    )))
+}
+
+pub async fn add_ip_blacklist(
+    req: HttpRequest,
+    body: web::Json<AddIpBlacklistRequest>,
+) -> Result<HttpResponse> {
+    if let Some(admin_id) = crate::auth::permitted_user_id(&req, Permission::ManageModeration) {
+        let ip = match normalize_ip(&body.ip) {
+            Some(ip) => ip,
+            None => {
+                return Ok(HttpResponse::Ok().json(ApiResponse::<()>::error(
+                    "IP 地址或网段格式不正确".to_string()
+                )));
+            }
+        };
+        let reason = match normalize_reason(&body.reason) {
+            Ok(reason) => reason,
+            Err(e) => return Ok(HttpResponse::Ok().json(ApiResponse::<()>::error(e))),
+        };
+        if let Err(e) = validate_duration(body.duration_minutes) {
+            return Ok(HttpResponse::Ok().json(ApiResponse::<()>::error(e)));
+        }
+        let until = body.duration_minutes.map(|m| Utc::now() + Duration::minutes(m));
+
+        let mut state = APP_STATE.lock().unwrap();
+        // 同一个 IP 或网段只保留一条，重复添加时更新
+        state.ip_blacklist.retain(|b| b.ip != ip);
+        state.ip_blacklist.push(IpBlacklist {
+            ip: ip.clone(),
+            reason: reason.clone(),
+            until,
+            added_by: Some(admin_id.clone()),
+        });
+        state.save_blacklist();
+        crate::audit::record(&mut state, &admin_id, AuditAction::AddIpBlacklist, None, serde_json::json!({
+            "ip": ip,
+            "until": until,
+        }), Some(reason).filter(|r| !r.is_empty()));
+
+        return Ok(HttpResponse::Ok().json(ApiResponse::success("已加入IP黑名单")));
+    }
+
+    Ok(HttpResponse::Unauthorized().json(ApiResponse::<()>::error(
+        "无权操作".to_string()
+    )))
+}
+
+pub async fn remove_ip_blacklist(
+    req: HttpRequest,
+    body: web::Json<RemoveIpBlacklistRequest>,
+) -> Result<HttpResponse> {
+    if let Some(admin_id) = crate::auth::permitted_user_id(&req, Permission::ManageModeration) {
+        let ip = normalize_ip(&body.ip).unwrap_or_else(|| body.ip.trim().to_string());
+
+        let mut state = APP_STATE.lock().unwrap();
+        let before = state.ip_blacklist.len();
+        state.ip_blacklist.retain(|b| b.ip != ip);
+        if state.ip_blacklist.len() == before {
+            return Ok(HttpResponse::Ok().json(ApiResponse::<()>::error(
+                "IP黑名单中没有该记录".to_string()
+            )));
+        }
+        state.save_blacklist();
+        crate::audit::record(&mut state, &admin_id, AuditAction::RemoveIpBlacklist, None, serde_json::json!({
+            "ip": ip,
+        }), None);
+
+        return Ok(HttpResponse::Ok().json(ApiResponse::success("已移出IP黑名单")));
+    }
+
+    Ok(HttpResponse::Unauthorized().json(ApiResponse::<()>::error(
+        "无权操作".to_string()
+    )))
+}
+
+pub async fn add_email_blacklist(
+    req: HttpRequest,
+    body: web::Json<AddEmailBlacklistRequest>,
+) -> Result<HttpResponse> {
+    if let Some(admin_id) = crate::auth::permitted_user_id(&req, Permission::ManageModeration) {
+        let pattern = match normalize_email_pattern(&body.pattern) {
+            Some(pattern) => pattern,
+            None => {
+                return Ok(HttpResponse::Ok().json(ApiResponse::<()>::error(
+                    "格式不正确，应为完整邮箱、*@域名 或 *@*.域名".to_string()
+                )));
+            }
+        };
+        let reason = match normalize_reason(&body.reason) {
+            Ok(reason) => reason,
+            Err(e) => return Ok(HttpResponse::Ok().json(ApiResponse::<()>::error(e))),
+        };
+        if let Err(e) = validate_duration(body.duration_minutes) {
+            return Ok(HttpResponse::Ok().json(ApiResponse::<()>::error(e)));
+        }
+        let until = body.duration_minutes.map(|m| Utc::now() + Duration::minutes(m));
+
+        let mut state = APP_STATE.lock().unwrap();
+        state.email_blacklist.retain(|b| b.pattern != pattern);
+        state.email_blacklist.push(EmailBlacklist {
+            pattern: pattern.clone(),
+            reason: reason.clone(),
+            until,
+            added_by: Some(admin_id.clone()),
+        });
+        state.save_blacklist();
+        crate::audit::record(&mut state, &admin_id, AuditAction::AddEmailBlacklist, None, serde_json::json!({
+            "pattern": pattern,
+            "until": until,
+        }), Some(reason).filter(|r| !r.is_empty()));
+
+        return Ok(HttpResponse::Ok().json(ApiResponse::success("已加入邮箱黑名单")));
+    }
+
+    Ok(HttpResponse::Unauthorized().json(ApiResponse::<()>::error(
+        "无权操作".to_string()
+    )))
+}
+
+pub async fn remove_email_blacklist(
+    req: HttpRequest,
+    body: web::Json<RemoveEmailBlacklistRequest>,
+) -> Result<HttpResponse> {
+    if let Some(admin_id) = crate::auth::permitted_user_id(&req, Permission::ManageModeration) {
+        let pattern = body.pattern.trim().to_lowercase();
+
+        let mut state = APP_STATE.lock().unwrap();
+        let before = state.email_blacklist.len();
+        state.email_blacklist.retain(|b| b.pattern.to_lowercase() != pattern);
+        if state.email_blacklist.len() == before {
+            return Ok(HttpResponse::Ok().json(ApiResponse::<()>::error(
+                "邮箱黑名单中没有该记录".to_string()
+            )));
+        }
+        state.save_blacklist();
+        crate::audit::record(&mut state, &admin_id, AuditAction::RemoveEmailBlacklist, None, serde_json::json!({
+            "pattern": pattern,
+        }), None);
+
+        return Ok(HttpResponse::Ok().json(ApiResponse::success("已移出邮箱黑名单")));
+    }
+
+    Ok(HttpResponse::Unauthorized().json(ApiResponse::<()>::error(
+        "无权操作".to_string()
+    )))
+}
+
+// 整体替换白名单，传空列表表示不限制
+pub async fn update_allowed_email_domains(
+    req: HttpRequest,
+    body: web::Json<UpdateAllowedEmailDomainsRequest>,
+) -> Result<HttpResponse> {
+    if let Some(admin_id) = crate::auth::permitted_user_id(&req, Permission::ManageModeration) {
+        if body.domains.len() > MAX_ALLOWED_DOMAINS {
+            return Ok(HttpResponse::Ok().json(ApiResponse::<()>::error(
+                format!("最多只能设置{}个域名", MAX_ALLOWED_DOMAINS)
+            )));
+        }
+        let mut domains: Vec<String> = Vec::new();
+        for domain in &body.domains {
+            let domain = domain.trim().trim_start_matches('@').to_lowercase();
+            if !is_valid_domain_pattern(&domain) {
+                return Ok(HttpResponse::Ok().json(ApiResponse::<()>::error(
+                    format!("域名格式不正确：{}", domain)
+                )));
+            }
+            if !domains.contains(&domain) {
+                domains.push(domain);
+            }
+        }
+
+        let mut state = APP_STATE.lock().unwrap();
+        let old_domains = std::mem::replace(&mut state.allowed_email_domains, domains.clone());
+        state.save_blacklist();
+        crate::audit::record(&mut state, &admin_id, AuditAction::UpdateAllowedEmailDomains, None, serde_json::json!({
+            "old": old_domains,
+            "new": domains,
+        }), None);
+
+        return Ok(HttpResponse::Ok().json(ApiResponse::success("邮箱域名白名单已更新")));
+    }
+
+    Ok(HttpResponse::Unauthorized().json(ApiResponse::<()>::error(
+        "无权操作".to_string()
+    )))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ip_entry(ip: &str) -> IpBlacklist {
+        IpBlacklist { ip: ip.to_string(), reason: String::new(), until: None, added_by: None }
+    }
+
+    fn email_entry(pattern: &str) -> EmailBlacklist {
+        EmailBlacklist { pattern: pattern.to_string(), reason: String::new(), until: None, added_by: None }
+    }
+
+    #[test]
+    fn ip_matches_single_address_and_cidr() {
+        assert!(ip_entry("1.2.3.4").matches("1.2.3.4"));
+        assert!(!ip_entry("1.2.3.4").matches("1.2.3.5"));
+        assert!(ip_entry("10.0.0.0/8").matches("10.255.1.2"));
+        assert!(!ip_entry("10.0.0.0/8").matches("11.0.0.1"));
+        // 无法解析的 IP 只做字符串比较
+        assert!(!ip_entry("10.0.0.0/8").matches("unknown"));
+        assert!(ip_entry("unknown").matches("unknown"));
+    }
+
+    #[test]
+    fn ip_matches_ipv6_ranges() {
+        assert!(ip_entry("2001:db8::/32").matches("2001:db8:1234::1"));
+        assert!(!ip_entry("2001:db8::/32").matches("2001:db9::1"));
+        assert!(ip_entry("::1").matches("::1"));
+        assert!(!ip_entry("2001:db8::/32").matches("10.0.0.1"));
+    }
+
+    #[test]
+    fn normalize_ip_truncates_host_bits() {
+        assert_eq!(normalize_ip("10.0.0.5/8").as_deref(), Some("10.0.0.0/8"));
+        assert_eq!(normalize_ip(" 192.168.1.7/24 ").as_deref(), Some("192.168.1.0/24"));
+        assert_eq!(normalize_ip("2001:db8::1/32").as_deref(), Some("2001:db8::/32"));
+        assert_eq!(normalize_ip("2001:0db8:0000::0001").as_deref(), Some("2001:db8::1"));
+        assert_eq!(normalize_ip("10.0.0.300"), None);
+        assert_eq!(normalize_ip("10.0.0.0/33"), None);
+    }
+
+    #[test]
+    fn email_matches_address_and_domain_patterns() {
+        assert!(email_entry("spam@example.com").matches("Spam@Example.com"));
+        assert!(!email_entry("spam@example.com").matches("other@example.com"));
+        assert!(email_entry("*@example.com").matches("anyone@example.com"));
+        assert!(!email_entry("*@example.com").matches("anyone@mail.example.com"));
+        assert!(email_entry("*@*.example.com").matches("anyone@mail.example.com"));
+        assert!(!email_entry("*@*.example.com").matches("anyone@example.com"));
+        assert!(!email_entry("*@*.example.com").matches("anyone@badexample.com"));
+        assert!(!email_entry("*@example.com").matches("not-an-email"));
+    }
+
+    #[test]
+    fn domain_pattern_matching() {
+        assert!(domain_matches("example.com", "example.com"));
+        assert!(!domain_matches("example.com", "a.example.com"));
+        assert!(domain_matches("*.example.com", "a.b.example.com"));
+        assert!(!domain_matches("*.example.com", "example.com"));
+        assert!(!domain_matches("*.example.com", "notexample.com"));
+    }
+
+    #[test]
+    fn loads_current_format() {
+        let data = r#"[
+            [{"ip": "10.0.0.0/8", "reason": "spam", "until": null, "added_by": "a1"}],
+            [{"pattern": "*@example.com", "reason": "", "until": null, "added_by": null}],
+            ["example.org"]
+        ]"#;
+        let (ip, email, allowed_domains) = parse_saved(data).unwrap();
+        assert_eq!(ip[0].ip, "10.0.0.0/8");
+        assert_eq!(ip[0].added_by.as_deref(), Some("a1"));
+        assert_eq!(email[0].pattern, "*@example.com");
+        assert_eq!(allowed_domains, vec!["example.org"]);
+    }
+
+    #[test]
+    fn loads_legacy_format() {
+        let data = r#"[
+            [{"ip": "1.2.3.4", "reason": "注册过于频繁", "until": "2030-01-01T00:00:00Z"}],
+            ["Spam@Example.com"]
+        ]"#;
+        let (ip, email, allowed_domains) = parse_saved(data).unwrap();
+        assert_eq!(ip.len(), 1);
+        assert_eq!(ip[0].ip, "1.2.3.4");
+        assert!(ip[0].until.is_some());
+        assert_eq!(ip[0].added_by, None);
+        assert_eq!(email.len(), 1);
+        assert_eq!(email[0].pattern, "spam@example.com");
+        assert!(email[0].matches("spam@example.com"));
+        assert!(allowed_domains.is_empty());
+
+        assert!(parse_saved("not json").is_none());
+    }
+}
\ No newline at end of file
@@ -0,0 +1,692 @@
+// 聊天操作的公共逻辑，HTTP 接口和 WebSocket 共用，保证两条路径的校验完全一致
+use std::collections::HashMap;
+use std::sync::MutexGuard;
+use std::time::Duration as StdDuration;
+use chrono::{DateTime, Duration, Utc};
+use uuid::Uuid;
+use crate::APP_STATE;
+use crate::antispam::Rejection;
+use crate::models::*;
+
+// 每条消息最多可附带的图片数量
+const MAX_ATTACHMENTS_PER_MESSAGE: usize = 9;
+// 表情回应的最大长度（字符数）
+const MAX_REACTION_CHARS: usize = 16;
+// 话题的最大长度（字符数）
+const MAX_TOPIC_CHARS: usize = 200;
+// 最多置顶的消息数量
+const MAX_PINNED_MESSAGES: usize = 50;
+// 禁言时长上限（分钟）
+pub const MAX_MUTE_MINUTES: i64 = 365 * 24 * 60;
+// 封禁时长上限（分钟）
+const MAX_BAN_MINUTES: i64 = 365 * 24 * 60;
+// 检查禁言、封禁是否到期的间隔
+const EXPIRY_CHECK_INTERVAL_SECONDS: u64 = 10;
+
+// 发送前的禁言和防刷屏检查，普通消息和转发给机器人的命令共用；
+// 持续刷屏时自动禁言，释放锁后再通知，因此需要传入并交还锁
+pub fn check_sender<'a>(
+    mut state: MutexGuard<'a, AppState>,
+    user_id: &str,
+    content: &str,
+) -> Result<MutexGuard<'a, AppState>, String> {
+    let user = state.users.get(user_id).ok_or_else(|| "未登录".to_string())?;
+
+    // 检查是否被禁言
+    if let Some(muted_until) = user.muted_until {
+        if muted_until > Utc::now() {
+            let remaining = muted_until.signed_duration_since(Utc::now());
+            return Err(match &user.mute_reason {
+                Some(reason) => format!("您已被禁言，原因：{}，剩余时间：{}分钟", reason, remaining.num_minutes()),
+                None => format!("您已被禁言，剩余时间：{}分钟", remaining.num_minutes()),
+            });
+        }
+    }
+
+    // 防刷屏：慢速模式、限流和重复消息；持续刷屏时自动禁言
+    let exempt = crate::permissions::has_permission(&state, user_id, Permission::BypassSlowMode);
+    match crate::antispam::check(user, content, state.settings.slow_mode_seconds, exempt) {
+        Ok(()) => {}
+        Err(Rejection::Reject(message)) => return Err(message),
+        Err(Rejection::Flooding) => {
+            let minutes = crate::antispam::AUTO_MUTE_MINUTES;
+            let reason = Some("刷屏，系统自动禁言".to_string());
+            let (muted_until, recipients) = apply_mute(&mut state, crate::audit::SYSTEM_ACTOR, user_id, minutes, reason.clone());
+            drop(state);
+            crate::websocket::send_user_muted(recipients, user_id, muted_until, &reason, crate::audit::SYSTEM_ACTOR);
+            return Err(format!("发送过于频繁，已被自动禁言{}分钟", minutes));
+        }
+    }
+
+    Ok(state)
+}
+
+pub fn send_message(user_id: &str, content: &str, attachment_ids: &[String]) -> Result<MessageWithUser, String> {
+    crate::presence::touch(user_id);
+
+    let state = APP_STATE.lock().unwrap();
+    let mut state = check_sender(state, user_id, content)?;
+    let user = state.users.get(user_id).ok_or_else(|| "未登录".to_string())?;
+
+    // 检查附件：只能引用自己上传的图片
+    if attachment_ids.len() > MAX_ATTACHMENTS_PER_MESSAGE {
+        return Err(format!("每条消息最多附带{}张图片", MAX_ATTACHMENTS_PER_MESSAGE));
+    }
+    for attachment_id in attachment_ids {
+        match state.attachments.get(attachment_id) {
+            Some(attachment) if attachment.uploader_id == user_id
+                && attachment.status != AttachmentStatus::Failed => {}
+            _ => return Err("图片不存在或处理失败".to_string()),
+        }
+    }
+
+    // 内容过滤：拒绝时不保存消息，只记录命中
+    let screened = crate::moderation::screen(&state.moderation, user, content);
+    if screened.action == Some(FilterAction::Reject) {
+        crate::moderation::record_flag(&mut state, user_id, None, content, &screened);
+        return Err(screened.rejection_message());
+    }
+    let user = state.users.get(user_id).ok_or_else(|| "未登录".to_string())?;
+
+    // 解析@提及
+    let can_mention_all = crate::permissions::has_permission(&state, user_id, Permission::MentionAll);
+    let mentions = crate::mentions::parse_mentions(&screened.content, user, can_mention_all, &state.users);
+
+    let message = Message {
+        id: Uuid::new_v4().to_string(),
+        user_id: user_id.to_string(),
+        content: screened.content.clone(),
+        timestamp: Utc::now(),
+        recalled: false,
+        original_content: None,
+        attachment_ids: attachment_ids.to_vec(),
+        mentions,
+        reactions: Default::default(),
+    };
+
+    state.messages.push(message.clone());
+    state.save_messages();
+    crate::antispam::record_sent(user_id, content);
+    crate::moderation::record_flag(&mut state, user_id, Some(&message.id), content, &screened);
+
+    let sender = state.users.get(user_id).map(crate::outgoing_webhooks::user_data);
+    crate::outgoing_webhooks::enqueue(&mut state, WebhookEvent::NewMessage, serde_json::json!({
+        "message": message,
+        "user": sender,
+    }));
+
+    // 创建包含用户信息的消息
+    let message_with_user = state.message_with_user(&message);
+
+    // 释放锁后再进行广播
+    drop(state);
+
+    // 广播消息给所有在线用户
+    crate::websocket::broadcast_message_with_user(&message_with_user);
+
+    // 单独提醒被@的用户
+    if !message_with_user.mentions.is_empty() {
+        crate::websocket::send_mentioned(&message_with_user);
+    }
+
+    Ok(message_with_user)
+}
+
+// can_moderate 为 false 时只能撤回自己的消息（访问令牌没有管理权限）；
+// 撤回他人消息时记入审计日志
+pub fn recall_message(user_id: &str, message_id: &str, can_moderate: bool, reason: Option<&str>) -> Result<(), String> {
+    let reason = crate::audit::normalize_reason(reason)?;
+    let mut state = APP_STATE.lock().unwrap();
+    if !state.users.contains_key(user_id) {
+        return Err("未登录".to_string());
+    }
+
+    let index = state.messages.iter()
+        .position(|m| m.id == message_id && !m.recalled)
+        .ok_or_else(|| "消息不存在或已被撤回".to_string())?;
+    let msg_user_id = state.messages[index].user_id.clone();
+
+    if !can_moderate && msg_user_id != user_id {
+        return Err("访问令牌缺少权限：Moderate".to_string());
+    }
+
+    // 撤回他人的消息需要 recall_any 权限，且作者等级低于自己
+    let can_recall = msg_user_id == user_id
+        || crate::permissions::can_act_on(&state, user_id, &msg_user_id, Permission::RecallAny);
+
+    if !can_recall {
+        return Err("无权撤回此消息".to_string());
+    }
+
+    // 保存原始内容
+    let message = &mut state.messages[index];
+    message.recalled = true;
+    message.original_content = Some(message.content.clone());
+    let recalled_message = message.clone();
+
+    state.save_messages();
+
+    if msg_user_id != user_id {
+        let author = state.users.get(&msg_user_id).cloned();
+        crate::audit::record(&mut state, user_id, AuditAction::RecallMessage, author.as_ref(), serde_json::json!({
+            "message_id": recalled_message.id,
+            "content": recalled_message.original_content,
+        }), reason);
+    }
+
+    crate::outgoing_webhooks::enqueue(&mut state, WebhookEvent::MessageRecalled, serde_json::json!({
+        "message": recalled_message,
+        "recalled_by": user_id,
+    }));
+
+    // 广播完整的消息数据而不是只广播ID
+    let message_with_user = state.message_with_user(&recalled_message);
+    drop(state);
+
+    crate::websocket::broadcast_recall_with_message(&message_with_user);
+
+    Ok(())
+}
+
+// 批量撤回或彻底删除某用户在时间范围内的消息，返回被清理的消息ID；
+// 只广播一条事件，避免逐条撤回刷屏
+pub fn purge_user_messages(
+    operator_id: &str,
+    target_id: &str,
+    since: Option<DateTime<Utc>>,
+    until: Option<DateTime<Utc>>,
+    mode: PurgeMode,
+    reason: Option<&str>,
+) -> Result<Vec<String>, String> {
+    if let (Some(since), Some(until)) = (since, until) {
+        if since > until {
+            return Err("开始时间不能晚于结束时间".to_string());
+        }
+    }
+    let reason = crate::audit::normalize_reason(reason)?;
+
+    let mut state = APP_STATE.lock().unwrap();
+    if !crate::permissions::can_act_on(&state, operator_id, target_id, Permission::RecallAny) {
+        return Err("无权操作".to_string());
+    }
+
+    let in_range = |m: &Message| {
+        m.user_id == target_id
+            && since.map(|t| m.timestamp >= t).unwrap_or(true)
+            && until.map(|t| m.timestamp <= t).unwrap_or(true)
+    };
+    let message_ids = match mode {
+        PurgeMode::Recall => {
+            let mut ids = Vec::new();
+            for message in state.messages.iter_mut().filter(|m| in_range(m) && !m.recalled) {
+                message.recalled = true;
+                message.original_content = Some(message.content.clone());
+                ids.push(message.id.clone());
+            }
+            ids
+        }
+        PurgeMode::Delete => delete_messages(&mut state, in_range),
+    };
+    if message_ids.is_empty() {
+        return Ok(message_ids);
+    }
+    state.save_messages();
+
+    let target = state.users.get(target_id).cloned();
+    crate::audit::record(&mut state, operator_id, AuditAction::PurgeMessages, target.as_ref(), serde_json::json!({
+        "mode": mode,
+        "since": since,
+        "until": until,
+        "count": message_ids.len(),
+        "message_ids": message_ids,
+    }), reason);
+
+    crate::outgoing_webhooks::enqueue(&mut state, WebhookEvent::MessagesPurged, serde_json::json!({
+        "user_id": target_id,
+        "message_ids": message_ids,
+        "mode": mode,
+        "purged_by": operator_id,
+    }));
+    drop(state);
+
+    crate::websocket::broadcast_messages_purged(target_id, &message_ids, mode, operator_id);
+
+    Ok(message_ids)
+}
+
+// 从消息列表中移除，并清理置顶、已读位置和附件文件；调用方负责保存消息
+fn delete_messages(state: &mut AppState, matches: impl Fn(&Message) -> bool) -> Vec<String> {
+    // 被删除的消息 -> 它之前最近一条保留的消息，用于移动已读位置
+    let mut replacements: HashMap<String, Option<String>> = HashMap::new();
+    let mut message_ids = Vec::new();
+    let mut attachment_ids = Vec::new();
+    let mut last_kept: Option<String> = None;
+    for message in &state.messages {
+        if matches(message) {
+            replacements.insert(message.id.clone(), last_kept.clone());
+            message_ids.push(message.id.clone());
+            attachment_ids.extend(message.attachment_ids.iter().cloned());
+        } else {
+            last_kept = Some(message.id.clone());
+        }
+    }
+    if message_ids.is_empty() {
+        return message_ids;
+    }
+    state.messages.retain(|m| !matches(m));
+
+    let mut markers_changed = false;
+    state.read_markers.retain(|_, marker| match replacements.get(&marker.last_read_message_id) {
+        Some(Some(previous_id)) => {
+            marker.last_read_message_id = previous_id.clone();
+            markers_changed = true;
+            true
+        }
+        Some(None) => {
+            markers_changed = true;
+            false
+        }
+        None => true,
+    });
+    if markers_changed {
+        state.save_read_markers();
+    }
+
+    let pinned_count = state.settings.pinned_message_ids.len();
+    state.settings.pinned_message_ids.retain(|id| !replacements.contains_key(id));
+    if state.settings.pinned_message_ids.len() != pinned_count {
+        state.save_settings();
+    }
+
+    if !attachment_ids.is_empty() {
+        for id in &attachment_ids {
+            if let Some(attachment) = state.attachments.remove(id) {
+                crate::images::remove_attachment_files(&attachment);
+            }
+        }
+        state.save_attachments();
+    }
+
+    message_ids
+}
+
+// 新增：添加或取消表情回应，返回该消息当前的全部回应
+pub fn react_message(user_id: &str, message_id: &str, emoji: &str) -> Result<Reactions, String> {
+    let emoji = emoji.trim();
+    if emoji.is_empty() || emoji.chars().count() > MAX_REACTION_CHARS {
+        return Err("表情不合法".to_string());
+    }
+
+    let mut state = APP_STATE.lock().unwrap();
+    if !state.users.contains_key(user_id) {
+        return Err("未登录".to_string());
+    }
+
+    let message = state.messages.iter_mut()
+        .find(|m| m.id == message_id && !m.recalled)
+        .ok_or_else(|| "消息不存在或已被撤回".to_string())?;
+
+    let users = message.reactions.entry(emoji.to_string()).or_default();
+    if let Some(pos) = users.iter().position(|id| id == user_id) {
+        users.remove(pos);
+        if users.is_empty() {
+            message.reactions.remove(emoji);
+        }
+    } else {
+        users.push(user_id.to_string());
+    }
+    let reactions = message.reactions.clone();
+
+    state.save_messages();
+    drop(state);
+
+    crate::websocket::broadcast_message_reacted(message_id, &reactions);
+
+    Ok(reactions)
+}
+
+// 推进已读位置，只能向后移动
+pub fn mark_read(user_id: &str, message_id: &str) -> Result<UnreadSummary, String> {
+    let mut state = APP_STATE.lock().unwrap();
+
+    let new_index = state.message_index(message_id)
+        .ok_or_else(|| "消息不存在".to_string())?;
+
+    let current_index = state.read_markers.get(user_id)
+        .and_then(|m| state.message_index(&m.last_read_message_id));
+
+    if current_index.map(|index| new_index > index).unwrap_or(true) {
+        state.read_markers.insert(user_id.to_string(), ReadMarker {
+            user_id: user_id.to_string(),
+            last_read_message_id: message_id.to_string(),
+            last_read_at: Utc::now(),
+        });
+        state.save_read_markers();
+    }
+
+    let unread = state.unread_summary(user_id);
+    drop(state);
+
+    // 同步给该用户的其他标签页
+    crate::websocket::send_read_marker_updated(user_id, &unread);
+
+    Ok(unread)
+}
+
+// 被禁言的用户和所有拥有禁言权限的用户，禁言事件只发给他们
+fn mute_event_recipients(state: &AppState, target_id: &str) -> Vec<String> {
+    let mut recipients = vec![target_id.to_string()];
+    recipients.extend(
+        crate::permissions::users_with_permission(state, Permission::Mute)
+            .into_iter()
+            .filter(|id| id != target_id)
+    );
+    recipients
+}
+
+// 禁言到指定时间，返回解除时间
+pub fn mute_user(operator_id: &str, target_id: &str, duration_minutes: i64, reason: Option<&str>) -> Result<DateTime<Utc>, String> {
+    if !(1..=MAX_MUTE_MINUTES).contains(&duration_minutes) {
+        return Err("禁言时长需在1分钟到365天之间".to_string());
+    }
+    let reason = crate::audit::normalize_reason(reason)?;
+    let mut state = APP_STATE.lock().unwrap();
+    if !crate::permissions::can_act_on(&state, operator_id, target_id, Permission::Mute) {
+        return Err("无权操作".to_string());
+    }
+    if !state.users.contains_key(target_id) {
+        return Err("用户不存在".to_string());
+    }
+
+    let (muted_until, recipients) = apply_mute(&mut state, operator_id, target_id, duration_minutes, reason.clone());
+    drop(state);
+
+    crate::websocket::send_user_muted(recipients, target_id, muted_until, &reason, operator_id);
+
+    Ok(muted_until)
+}
+
+// 调用方需持有 APP_STATE 的锁，并在释放锁后向返回的用户发送禁言通知
+fn apply_mute(
+    state: &mut AppState,
+    operator_id: &str,
+    target_id: &str,
+    duration_minutes: i64,
+    reason: Option<String>,
+) -> (DateTime<Utc>, Vec<String>) {
+    let muted_until = Utc::now() + Duration::minutes(duration_minutes);
+    if let Some(user) = state.users.get_mut(target_id) {
+        user.muted_until = Some(muted_until);
+        user.mute_reason = reason.clone();
+    }
+    state.save_users();
+
+    let target = state.users.get(target_id).cloned();
+    crate::audit::record(state, operator_id, AuditAction::MuteUser, target.as_ref(), serde_json::json!({
+        "duration_minutes": duration_minutes,
+        "muted_until": muted_until,
+    }), reason);
+
+    (muted_until, mute_event_recipients(state, target_id))
+}
+
+pub fn unmute_user(operator_id: &str, target_id: &str, reason: Option<&str>) -> Result<(), String> {
+    let reason = crate::audit::normalize_reason(reason)?;
+    let mut state = APP_STATE.lock().unwrap();
+    if !crate::permissions::can_act_on(&state, operator_id, target_id, Permission::Mute) {
+        return Err("无权操作".to_string());
+    }
+
+    match state.users.get_mut(target_id) {
+        Some(user) if user.muted_until.map(|until| until > Utc::now()).unwrap_or(false) => {
+            user.muted_until = None;
+            user.mute_reason = None;
+        }
+        _ => return Err("该用户未被禁言".to_string()),
+    }
+    state.save_users();
+
+    let target = state.users.get(target_id).cloned();
+    crate::audit::record(&mut state, operator_id, AuditAction::UnmuteUser, target.as_ref(), serde_json::json!({}), reason);
+    let recipients = mute_event_recipients(&state, target_id);
+    drop(state);
+
+    crate::websocket::send_user_unmuted(recipients, target_id, Some(operator_id));
+
+    Ok(())
+}
+
+// 封禁用户：保留账号和历史消息，踢下线；duration_minutes 为 None 时永久封禁，返回解封时间
+// 检查能否封禁；批量清理消息时在清理前调用，避免清理完成后才发现无法封禁
+pub fn check_ban(state: &AppState, operator_id: &str, target_id: &str, duration_minutes: Option<i64>) -> Result<(), String> {
+    if operator_id == target_id {
+        return Err("不能封禁自己".to_string());
+    }
+    if duration_minutes.map(|m| !(1..=MAX_BAN_MINUTES).contains(&m)).unwrap_or(false) {
+        return Err("封禁时长需在1分钟到365天之间".to_string());
+    }
+    if !crate::permissions::can_act_on(state, operator_id, target_id, Permission::Ban) {
+        return Err("无权操作".to_string());
+    }
+    if !state.users.contains_key(target_id) {
+        return Err("用户不存在".to_string());
+    }
+    Ok(())
+}
+
+pub fn ban_user(
+    operator_id: &str,
+    target_id: &str,
+    duration_minutes: Option<i64>,
+    reason: Option<&str>,
+) -> Result<Option<DateTime<Utc>>, String> {
+    let reason = crate::audit::normalize_reason(reason)?;
+
+    let mut state = APP_STATE.lock().unwrap();
+    check_ban(&state, operator_id, target_id, duration_minutes)?;
+
+    let now = Utc::now();
+    let until = duration_minutes.map(|m| now + Duration::minutes(m));
+    let user = match state.users.get_mut(target_id) {
+        Some(user) => {
+            user.status = UserStatus::Banned;
+            user.ban = Some(BanInfo {
+                reason: reason.clone(),
+                banned_by: operator_id.to_string(),
+                banned_at: now,
+                until,
+            });
+            user.clone()
+        }
+        None => return Err("用户不存在".to_string()),
+    };
+
+    // 移除用户的所有会话
+    if let Some(sessions) = state.user_sessions.remove(target_id) {
+        for session in sessions {
+            state.sessions.remove(&session.token);
+        }
+    }
+
+    state.save_users();
+    state.save_sessions();
+
+    crate::audit::record(&mut state, operator_id, AuditAction::BanUser, Some(&user), serde_json::json!({
+        "duration_minutes": duration_minutes,
+        "until": until,
+    }), reason.clone());
+
+    crate::outgoing_webhooks::enqueue(&mut state, WebhookEvent::UserBanned, serde_json::json!({
+        "user": crate::outgoing_webhooks::user_data(&user),
+        "banned_by": operator_id,
+        "reason": reason,
+        "until": until,
+    }));
+    drop(state);
+
+    // 广播用户被封禁
+    crate::websocket::broadcast_user_banned(target_id);
+
+    Ok(until)
+}
+
+pub fn unban_user(operator_id: &str, target_id: &str, reason: Option<&str>) -> Result<(), String> {
+    let reason = crate::audit::normalize_reason(reason)?;
+    let mut state = APP_STATE.lock().unwrap();
+    if !crate::permissions::can_act_on(&state, operator_id, target_id, Permission::Ban) {
+        return Err("无权操作".to_string());
+    }
+
+    let user = match state.users.get_mut(target_id) {
+        Some(user) if user.status == UserStatus::Banned => {
+            lift_ban(user);
+            user.clone()
+        }
+        Some(_) => return Err("该用户未被封禁".to_string()),
+        None => return Err("用户不存在".to_string()),
+    };
+    state.save_users();
+
+    crate::audit::record(&mut state, operator_id, AuditAction::UnbanUser, Some(&user), serde_json::json!({}), reason);
+    crate::outgoing_webhooks::enqueue(&mut state, WebhookEvent::UserUnbanned, serde_json::json!({
+        "user": crate::outgoing_webhooks::user_data(&user),
+        "unbanned_by": operator_id,
+    }));
+    drop(state);
+
+    crate::websocket::broadcast_user_unbanned(target_id);
+
+    Ok(())
+}
+
+fn lift_ban(user: &mut User) {
+    user.status = UserStatus::Active;
+    user.ban = None;
+}
+
+// 解除所有已到期的封禁
+pub fn lift_expired_bans() {
+    let now = Utc::now();
+    let mut state = APP_STATE.lock().unwrap();
+    let lifted: Vec<User> = state.users.values_mut()
+        .filter(|user| {
+            user.status == UserStatus::Banned
+                && user.ban.as_ref().and_then(|b| b.until).map(|until| until <= now).unwrap_or(false)
+        })
+        .map(|user| {
+            lift_ban(user);
+            user.clone()
+        })
+        .collect();
+    if lifted.is_empty() {
+        return;
+    }
+
+    state.save_users();
+    for user in &lifted {
+        crate::outgoing_webhooks::enqueue(&mut state, WebhookEvent::UserUnbanned, serde_json::json!({
+            "user": crate::outgoing_webhooks::user_data(user),
+            "unbanned_by": null,
+        }));
+    }
+    drop(state);
+
+    for user in &lifted {
+        crate::websocket::broadcast_user_unbanned(&user.id);
+    }
+}
+
+// 清除到期的禁言，并通知被禁言的用户和管理员
+pub fn lift_expired_mutes() {
+    let now = Utc::now();
+    let mut state = APP_STATE.lock().unwrap();
+    let lifted: Vec<String> = state.users.values_mut()
+        .filter(|user| user.muted_until.map(|until| until <= now).unwrap_or(false))
+        .map(|user| {
+            user.muted_until = None;
+            user.mute_reason = None;
+            user.id.clone()
+        })
+        .collect();
+    if lifted.is_empty() {
+        return;
+    }
+
+    state.save_users();
+    let notifications: Vec<(String, Vec<String>)> = lifted.into_iter()
+        .map(|user_id| {
+            let recipients = mute_event_recipients(&state, &user_id);
+            (user_id, recipients)
+        })
+        .collect();
+    drop(state);
+
+    for (user_id, recipients) in notifications {
+        crate::websocket::send_user_unmuted(recipients, &user_id, None);
+    }
+}
+
+// 定期解除到期的禁言和封禁
+pub async fn run_expiry_worker() {
+    let mut interval = tokio::time::interval(StdDuration::from_secs(EXPIRY_CHECK_INTERVAL_SECONDS));
+    loop {
+        interval.tick().await;
+        lift_expired_mutes();
+        lift_expired_bans();
+    }
+}
+
+// 设置或清除话题，需要 pin_messages 权限
+pub fn set_topic(user_id: &str, topic: Option<&str>) -> Result<Option<String>, String> {
+    let topic = topic.map(str::trim).filter(|t| !t.is_empty()).map(str::to_string);
+    if topic.as_ref().map(|t| t.chars().count() > MAX_TOPIC_CHARS).unwrap_or(false) {
+        return Err(format!("话题不能超过{}个字符", MAX_TOPIC_CHARS));
+    }
+
+    let mut state = APP_STATE.lock().unwrap();
+    if !crate::permissions::has_permission(&state, user_id, Permission::PinMessages) {
+        return Err("无权操作".to_string());
+    }
+
+    state.settings.topic = topic.clone();
+    state.save_settings();
+    drop(state);
+
+    crate::websocket::broadcast_topic_changed(&topic, user_id);
+
+    Ok(topic)
+}
+
+// 置顶或取消置顶消息，需要 pin_messages 权限
+pub fn pin_message(user_id: &str, message_id: &str, pinned: bool) -> Result<(), String> {
+    let mut state = APP_STATE.lock().unwrap();
+    if !crate::permissions::has_permission(&state, user_id, Permission::PinMessages) {
+        return Err("无权操作".to_string());
+    }
+    if !state.messages.iter().any(|m| m.id == message_id && !m.recalled) {
+        return Err("消息不存在或已被撤回".to_string());
+    }
+
+    let pinned_ids = &mut state.settings.pinned_message_ids;
+    let already_pinned = pinned_ids.iter().any(|id| id == message_id);
+    if pinned {
+        if already_pinned {
+            return Err("消息已置顶".to_string());
+        }
+        if pinned_ids.len() >= MAX_PINNED_MESSAGES {
+            return Err(format!("最多置顶{}条消息", MAX_PINNED_MESSAGES));
+        }
+        pinned_ids.push(message_id.to_string());
+    } else {
+        if !already_pinned {
+            return Err("消息未置顶".to_string());
+        }
+        pinned_ids.retain(|id| id != message_id);
+    }
+    state.save_settings();
+    drop(state);
+
+    crate::websocket::broadcast_message_pinned(message_id, pinned, user_id);
+
+    Ok(())
+}
\ No newline at end of file
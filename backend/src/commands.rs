@@ -0,0 +1,435 @@
+// 斜杠命令：以 /命令 开头的消息不作为普通消息发送，而是交给对应的命令处理，
+// 回复只发给调用者本人。机器人可以注册自己的命令，调用时以事件的形式转发给机器人
+use std::collections::HashMap;
+use std::sync::Mutex;
+use actix_web::{web, HttpRequest, HttpResponse, Result};
+use chrono::{DateTime, Duration, Utc};
+use lazy_static::lazy_static;
+use regex::Regex;
+use uuid::Uuid;
+use crate::APP_STATE;
+use crate::models::*;
+
+// 机器人可以在调用后多长时间内回复（分钟）
+const INVOCATION_TTL_MINUTES: i64 = 15;
+const MAX_REPLY_CHARS: usize = 2000;
+const MAX_DESCRIPTION_CHARS: usize = 100;
+const MAX_COMMANDS_PER_BOT: usize = 25;
+
+struct BuiltinCommand {
+    name: &'static str,
+    usage: &'static str,
+    description: &'static str,
+    permission: Option<Permission>, // 需要的权限，None 表示所有人可用
+}
+
+const BUILTIN_COMMANDS: &[BuiltinCommand] = &[
+    BuiltinCommand { name: "help", usage: "/help", description: "显示可用的命令", permission: None },
+    BuiltinCommand { name: "me", usage: "/me <动作>", description: "以第三人称发送一条动作消息", permission: None },
+    BuiltinCommand { name: "topic", usage: "/topic [话题]", description: "查看话题；管理员可以设置话题，/topic - 清除话题", permission: None },
+    BuiltinCommand { name: "pin", usage: "/pin <消息ID>", description: "置顶消息", permission: Some(Permission::PinMessages) },
+    BuiltinCommand { name: "unpin", usage: "/unpin <消息ID>", description: "取消置顶消息", permission: Some(Permission::PinMessages) },
+    BuiltinCommand { name: "mute", usage: "/mute @用户 <时长> [原因]", description: "禁言用户，时长如 30、10m、2h、1d（不带单位为分钟）", permission: Some(Permission::Mute) },
+    BuiltinCommand { name: "unmute", usage: "/unmute @用户 [原因]", description: "解除禁言", permission: Some(Permission::Mute) },
+    BuiltinCommand { name: "ban", usage: "/ban @用户 [时长] [原因]", description: "封禁用户，不填时长为永久封禁", permission: Some(Permission::Ban) },
+    BuiltinCommand { name: "unban", usage: "/unban @用户 [原因]", description: "解除封禁", permission: Some(Permission::Ban) },
+];
+
+struct Invocation {
+    bot_user_id: String,
+    user_id: String,
+    command: String,
+    created_at: DateTime<Utc>,
+}
+
+lazy_static! {
+    static ref COMMAND_NAME_REGEX: Regex = Regex::new(r"^[a-z0-9][a-z0-9_-]{0,31}$").unwrap();
+    // invocation_id -> 等待机器人回复的调用
+    static ref INVOCATIONS: Mutex<HashMap<String, Invocation>> = Mutex::new(HashMap::new());
+}
+
+enum Outcome {
+    Reply(String),
+    Message(serde_json::Value),
+    Forwarded,
+}
+
+// 以 / 开头且命令名合法时返回命令名和参数；"/path/to" 这类内容不是命令
+fn parse(content: &str) -> Option<(String, &str)> {
+    let rest = content.strip_prefix('/')?;
+    let end = rest.find(char::is_whitespace).unwrap_or(rest.len());
+    let name = rest[..end].to_lowercase();
+    if !COMMAND_NAME_REGEX.is_match(&name) {
+        return None;
+    }
+    Some((name, rest[end..].trim()))
+}
+
+// 时长：纯数字为分钟，也可以带 m/h/d 单位
+fn parse_duration_minutes(value: &str) -> Option<i64> {
+    let value = value.trim().to_lowercase();
+    let (number, unit) = match value.char_indices().find(|(_, c)| !c.is_ascii_digit()) {
+        Some((index, _)) => value.split_at(index),
+        None => (value.as_str(), "m"),
+    };
+    let number: i64 = number.parse().ok()?;
+    let minutes = match unit {
+        "m" | "min" => number,
+        "h" => number.checked_mul(60)?,
+        "d" => number.checked_mul(24 * 60)?,
+        _ => return None,
+    };
+    (1..=crate::chat::MAX_MUTE_MINUTES).contains(&minutes).then_some(minutes)
+}
+
+fn user_name(state: &AppState, user_id: &str) -> String {
+    state.users.get(user_id)
+        .map(|u| u.display_name.clone().filter(|n| !n.is_empty()).unwrap_or_else(|| u.username.clone()))
+        .unwrap_or_default()
+}
+
+// 调用者可用的命令：管理命令只对拥有相应权限的用户列出
+fn available_commands(user_id: &str) -> Vec<CommandInfo> {
+    let state = APP_STATE.lock().unwrap();
+    let mut commands: Vec<CommandInfo> = BUILTIN_COMMANDS.iter()
+        .filter(|c| c.permission.map(|p| crate::permissions::has_permission(&state, user_id, p)).unwrap_or(true))
+        .map(|c| CommandInfo {
+            name: c.name.to_string(),
+            usage: c.usage.to_string(),
+            description: c.description.to_string(),
+            bot_user_id: None,
+        })
+        .collect();
+
+    let mut bot_commands: Vec<&BotCommand> = state.bot_commands.values().collect();
+    bot_commands.sort_by(|a, b| a.name.cmp(&b.name));
+    commands.extend(bot_commands.into_iter().map(|c| CommandInfo {
+        name: c.name.clone(),
+        usage: c.usage.clone().unwrap_or_else(|| format!("/{}", c.name)),
+        description: c.description.clone(),
+        bot_user_id: Some(c.bot_user_id.clone()),
+    }));
+
+    commands
+}
+
+fn help(user_id: &str) -> String {
+    available_commands(user_id).iter()
+        .map(|c| format!("{} — {}", c.usage, c.description))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+fn resolve_target(args: &str) -> Result<(String, String), String> {
+    let state = APP_STATE.lock().unwrap();
+    crate::mentions::resolve_user(args, &state.users)
+        .map(|(id, rest)| (id, rest.trim().to_string()))
+        .ok_or_else(|| "找不到该用户".to_string())
+}
+
+fn execute(user_id: &str, name: &str, args: &str, can_moderate: bool) -> Result<Outcome, String> {
+    if let Some(builtin) = BUILTIN_COMMANDS.iter().find(|c| c.name == name) {
+        // 查看话题不需要管理权限
+        let needs_moderate = builtin.permission.is_some() || (name == "topic" && !args.is_empty());
+        if needs_moderate && !can_moderate {
+            return Err("访问令牌缺少权限：Moderate".to_string());
+        }
+    }
+
+    match name {
+        "help" => Ok(Outcome::Reply(help(user_id))),
+        "me" => {
+            if args.is_empty() {
+                return Err("用法：/me <动作>".to_string());
+            }
+            let name = user_name(&APP_STATE.lock().unwrap(), user_id);
+            crate::chat::send_message(user_id, &format!("*{} {}*", name, args), &[])
+                .map(|message| Outcome::Message(serde_json::to_value(message).unwrap()))
+        }
+        "topic" => {
+            if args.is_empty() {
+                let topic = APP_STATE.lock().unwrap().settings.topic.clone();
+                return Ok(Outcome::Reply(match topic {
+                    Some(topic) => format!("当前话题：{}", topic),
+                    None => "当前没有设置话题".to_string(),
+                }));
+            }
+            let topic = if args == "-" { None } else { Some(args) };
+            match crate::chat::set_topic(user_id, topic)? {
+                Some(topic) => Ok(Outcome::Reply(format!("话题已设置为：{}", topic))),
+                None => Ok(Outcome::Reply("话题已清除".to_string())),
+            }
+        }
+        "pin" | "unpin" => {
+            if args.is_empty() || args.contains(char::is_whitespace) {
+                return Err(format!("用法：/{} <消息ID>", name));
+            }
+            let pinned = name == "pin";
+            crate::chat::pin_message(user_id, args, pinned)?;
+            Ok(Outcome::Reply(if pinned { "消息已置顶" } else { "已取消置顶" }.to_string()))
+        }
+        "mute" => {
+            let (target_id, rest) = resolve_target(args)?;
+            let (duration, reason) = rest.split_once(char::is_whitespace).unwrap_or((&rest, ""));
+            let minutes = parse_duration_minutes(duration)
+                .ok_or_else(|| "用法：/mute @用户 <时长> [原因]，时长如 30、10m、2h、1d，最长365天".to_string())?;
+            let muted_until = crate::chat::mute_user(user_id, &target_id, minutes, Some(reason))?;
+            let target_name = user_name(&APP_STATE.lock().unwrap(), &target_id);
+            Ok(Outcome::Reply(format!(
+                "已禁言 {}，到 {} 解除",
+                target_name,
+                muted_until.format("%Y-%m-%d %H:%M UTC")
+            )))
+        }
+        "unmute" => {
+            let (target_id, reason) = resolve_target(args)?;
+            crate::chat::unmute_user(user_id, &target_id, Some(&reason))?;
+            let target_name = user_name(&APP_STATE.lock().unwrap(), &target_id);
+            Ok(Outcome::Reply(format!("已解除 {} 的禁言", target_name)))
+        }
+        "ban" => {
+            let (target_id, rest) = resolve_target(args)?;
+            // 第一个参数是合法时长时作为封禁时长，否则整段都是原因
+            let (first, remainder) = rest.split_once(char::is_whitespace).unwrap_or((&rest, ""));
+            let (minutes, reason) = match parse_duration_minutes(first) {
+                Some(minutes) => (Some(minutes), remainder),
+                None => (None, rest.as_str()),
+            };
+            let target_name = user_name(&APP_STATE.lock().unwrap(), &target_id);
+            let until = crate::chat::ban_user(user_id, &target_id, minutes, Some(reason))?;
+            Ok(Outcome::Reply(match until {
+                Some(until) => format!("已封禁 {}，到 {} 解封", target_name, until.format("%Y-%m-%d %H:%M UTC")),
+                None => format!("已永久封禁 {}", target_name),
+            }))
+        }
+        "unban" => {
+            let (target_id, reason) = resolve_target(args)?;
+            crate::chat::unban_user(user_id, &target_id, Some(&reason))?;
+            let target_name = user_name(&APP_STATE.lock().unwrap(), &target_id);
+            Ok(Outcome::Reply(format!("已解除 {} 的封禁", target_name)))
+        }
+        _ => invoke_bot_command(user_id, name, args),
+    }
+}
+
+// 把调用转发给注册该命令的机器人，机器人之后可以回复调用者
+fn invoke_bot_command(user_id: &str, name: &str, args: &str) -> Result<Outcome, String> {
+    let (bot_user_id, args) = {
+        let state = APP_STATE.lock().unwrap();
+        let command = state.bot_commands.get(name)
+            .ok_or_else(|| format!("未知命令：/{}，输入 /help 查看可用的命令", name))?;
+        let bot_active = state.users.get(&command.bot_user_id)
+            .map(|u| u.status == UserStatus::Active)
+            .unwrap_or(false);
+        if !bot_active {
+            return Err("该命令暂不可用".to_string());
+        }
+        let bot_user_id = command.bot_user_id.clone();
+
+        // 参数会交给机器人处理，和普通消息一样检查禁言、刷屏并过滤内容
+        let content = format!("/{} {}", name, args);
+        let mut state = crate::chat::check_sender(state, user_id, &content)?;
+        let user = state.users.get(user_id).ok_or_else(|| "未登录".to_string())?;
+        let screened = crate::moderation::screen(&state.moderation, user, args);
+        crate::moderation::record_flag(&mut state, user_id, None, args, &screened);
+        if screened.action == Some(FilterAction::Reject) {
+            return Err(screened.rejection_message());
+        }
+        crate::antispam::record_sent(user_id, &content);
+        (bot_user_id, screened.content)
+    };
+
+    let invocation_id = Uuid::new_v4().to_string();
+    let now = Utc::now();
+    let mut invocations = INVOCATIONS.lock().unwrap();
+    invocations.retain(|_, i| now.signed_duration_since(i.created_at) < Duration::minutes(INVOCATION_TTL_MINUTES));
+    invocations.insert(invocation_id.clone(), Invocation {
+        bot_user_id: bot_user_id.clone(),
+        user_id: user_id.to_string(),
+        command: name.to_string(),
+        created_at: now,
+    });
+    drop(invocations);
+
+    crate::websocket::send_command_invoked(&bot_user_id, &invocation_id, name, &args, user_id);
+
+    Ok(Outcome::Forwarded)
+}
+
+// 发送消息前调用：不是命令时返回 None，按普通消息发送；
+// can_moderate 为 false 时（访问令牌没有管理权限）不能使用管理命令
+pub fn dispatch(
+    user_id: &str,
+    content: &str,
+    attachment_ids: &[String],
+    can_moderate: bool,
+) -> Option<Result<serde_json::Value, String>> {
+    let (name, args) = parse(content)?;
+    if !attachment_ids.is_empty() {
+        return Some(Err("命令不能附带图片".to_string()));
+    }
+    crate::presence::touch(user_id);
+
+    Some(execute(user_id, &name, args, can_moderate).map(|outcome| match outcome {
+        Outcome::Reply(text) => {
+            crate::websocket::send_command_reply(user_id, &name, &text);
+            serde_json::json!({ "command": name, "reply": text })
+        }
+        Outcome::Message(message) => message,
+        Outcome::Forwarded => serde_json::json!({ "command": name, "forwarded": true }),
+    }))
+}
+
+fn session_user_id(req: &HttpRequest) -> Option<String> {
+    let token = crate::auth::request_token(req)?;
+    APP_STATE.lock().unwrap().token_user_id(&token).cloned()
+}
+
+pub async fn get_commands(req: HttpRequest) -> Result<HttpResponse> {
+    if let Some(user_id) = session_user_id(&req) {
+        return Ok(HttpResponse::Ok().json(ApiResponse::success(available_commands(&user_id))));
+    }
+
+    Ok(HttpResponse::Unauthorized().json(ApiResponse::<()>::error(
+        "未登录".to_string()
+    )))
+}
+
+// 机器人注册命令；同一机器人重复注册时更新说明
+pub async fn register_command(
+    req: HttpRequest,
+    body: web::Json<RegisterCommandRequest>,
+) -> Result<HttpResponse> {
+    if let Some(user_id) = session_user_id(&req) {
+        let mut state = APP_STATE.lock().unwrap();
+        if !state.users.get(&user_id).map(|u| u.is_bot).unwrap_or(false) {
+            return Ok(HttpResponse::Ok().json(ApiResponse::<()>::error(
+                "只有机器人账号可以注册命令".to_string()
+            )));
+        }
+
+        let name = body.name.trim().trim_start_matches('/').to_lowercase();
+        if !COMMAND_NAME_REGEX.is_match(&name) {
+            return Ok(HttpResponse::Ok().json(ApiResponse::<()>::error(
+                "命令名只能包含小写字母、数字、下划线和连字符，最长32个字符".to_string()
+            )));
+        }
+        if BUILTIN_COMMANDS.iter().any(|c| c.name == name) {
+            return Ok(HttpResponse::Ok().json(ApiResponse::<()>::error(
+                "不能覆盖内置命令".to_string()
+            )));
+        }
+        let description = body.description.trim();
+        let usage = body.usage.as_deref().map(str::trim).filter(|u| !u.is_empty());
+        if description.is_empty()
+            || description.chars().count() > MAX_DESCRIPTION_CHARS
+            || usage.map(|u| u.chars().count() > MAX_DESCRIPTION_CHARS).unwrap_or(false)
+        {
+            return Ok(HttpResponse::Ok().json(ApiResponse::<()>::error(
+                format!("说明不能为空，说明和用法都不能超过{}个字符", MAX_DESCRIPTION_CHARS)
+            )));
+        }
+
+        match state.bot_commands.get(&name) {
+            Some(existing) if existing.bot_user_id != user_id => {
+                return Ok(HttpResponse::Ok().json(ApiResponse::<()>::error(
+                    "该命令已被其他机器人注册".to_string()
+                )));
+            }
+            Some(_) => {}
+            None => {
+                let count = state.bot_commands.values().filter(|c| c.bot_user_id == user_id).count();
+                if count >= MAX_COMMANDS_PER_BOT {
+                    return Ok(HttpResponse::Ok().json(ApiResponse::<()>::error(
+                        format!("每个机器人最多注册{}个命令", MAX_COMMANDS_PER_BOT)
+                    )));
+                }
+            }
+        }
+
+        state.bot_commands.insert(name.clone(), BotCommand {
+            name,
+            description: description.to_string(),
+            usage: usage.map(str::to_string),
+            bot_user_id: user_id,
+            created_at: Utc::now(),
+        });
+        state.save_bot_commands();
+
+        return Ok(HttpResponse::Ok().json(ApiResponse::success("命令已注册")));
+    }
+
+    Ok(HttpResponse::Unauthorized().json(ApiResponse::<()>::error(
+        "未登录".to_string()
+    )))
+}
+
+// 注销命令：注册该命令的机器人或拥有 manage_integrations 权限的用户
+pub async fn unregister_command(
+    req: HttpRequest,
+    body: web::Json<UnregisterCommandRequest>,
+) -> Result<HttpResponse> {
+    if let Some(user_id) = session_user_id(&req) {
+        let mut state = APP_STATE.lock().unwrap();
+        let name = body.name.trim().trim_start_matches('/').to_lowercase();
+        let can_manage = crate::permissions::has_permission(&state, &user_id, Permission::ManageIntegrations);
+
+        match state.bot_commands.get(&name) {
+            Some(command) if command.bot_user_id == user_id || can_manage => {
+                state.bot_commands.remove(&name);
+                state.save_bot_commands();
+                return Ok(HttpResponse::Ok().json(ApiResponse::success("命令已注销")));
+            }
+            Some(_) => {
+                return Ok(HttpResponse::Ok().json(ApiResponse::<()>::error(
+                    "无权操作".to_string()
+                )));
+            }
+            None => {
+                return Ok(HttpResponse::Ok().json(ApiResponse::<()>::error(
+                    "命令不存在".to_string()
+                )));
+            }
+        }
+    }
+
+    Ok(HttpResponse::Unauthorized().json(ApiResponse::<()>::error(
+        "未登录".to_string()
+    )))
+}
+
+// 机器人回复命令调用，回复只发给调用者；有效期内可以多次回复
+pub async fn reply_to_command(
+    req: HttpRequest,
+    body: web::Json<CommandReplyRequest>,
+) -> Result<HttpResponse> {
+    if let Some(user_id) = session_user_id(&req) {
+        let text = body.text.trim();
+        if text.is_empty() || text.chars().count() > MAX_REPLY_CHARS {
+            return Ok(HttpResponse::Ok().json(ApiResponse::<()>::error(
+                format!("回复不能为空且不超过{}个字符", MAX_REPLY_CHARS)
+            )));
+        }
+
+        let invocations = INVOCATIONS.lock().unwrap();
+        let target = invocations.get(&body.invocation_id)
+            .filter(|i| i.bot_user_id == user_id)
+            .filter(|i| Utc::now().signed_duration_since(i.created_at) < Duration::minutes(INVOCATION_TTL_MINUTES))
+            .map(|i| (i.user_id.clone(), i.command.clone()));
+        drop(invocations);
+
+        return match target {
+            Some((caller_id, command)) => {
+                crate::websocket::send_command_reply(&caller_id, &command, text);
+                Ok(HttpResponse::Ok().json(ApiResponse::success("已回复")))
+            }
+            None => Ok(HttpResponse::Ok().json(ApiResponse::<()>::error(
+                "调用不存在或已过期".to_string()
+            ))),
+        };
+    }
+
+    Ok(HttpResponse::Unauthorized().json(ApiResponse::<()>::error(
+        "未登录".to_string()
+    )))
+}
\ No newline at end of file
@@ -0,0 +1,182 @@
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+use lazy_static::lazy_static;
+use uuid::Uuid;
+use crate::protocol::{ServerEvent, ServerFrame};
+
+// 保留最近的事件数量，断线重连时据此补发
+const EVENT_LOG_CAPACITY: usize = 1000;
+
+// 事件的接收范围
+#[derive(Clone, PartialEq)]
+pub enum Audience {
+    All,
+    User(String),
+    Users(Vec<String>), // 新增：多个指定用户
+}
+
+struct LoggedEvent {
+    seq: u64,
+    audience: Audience,
+    payload: String,
+}
+
+struct EventLog {
+    last_seq: u64,
+    events: VecDeque<LoggedEvent>,
+}
+
+pub enum Replay {
+    Events(Vec<String>),
+    ResyncRequired,
+}
+
+lazy_static! {
+    // 每次启动生成新的标识，序号只在同一次运行内有意义
+    static ref EPOCH: String = Uuid::new_v4().to_string();
+    static ref EVENT_LOG: Arc<Mutex<EventLog>> = Arc::new(Mutex::new(EventLog::new()));
+}
+
+impl EventLog {
+    fn new() -> Self {
+        EventLog {
+            last_seq: 0,
+            events: VecDeque::with_capacity(EVENT_LOG_CAPACITY),
+        }
+    }
+
+    fn record(&mut self, event: &ServerEvent, audience: Audience) -> String {
+        self.last_seq += 1;
+        let seq = self.last_seq;
+        let payload = serde_json::to_string(&ServerFrame { seq: Some(seq), event }).unwrap();
+
+        if self.events.len() >= EVENT_LOG_CAPACITY {
+            self.events.pop_front();
+        }
+        self.events.push_back(LoggedEvent {
+            seq,
+            audience,
+            payload: payload.clone(),
+        });
+
+        payload
+    }
+
+    fn replay_since(&self, last_seq: u64, user_id: &str) -> Replay {
+        if last_seq > self.last_seq {
+            return Replay::ResyncRequired;
+        }
+        let oldest = self.events.front().map(|e| e.seq).unwrap_or(self.last_seq + 1);
+        if last_seq + 1 < oldest {
+            return Replay::ResyncRequired;
+        }
+
+        Replay::Events(
+            self.events.iter()
+                .filter(|e| e.seq > last_seq)
+                .filter(|e| match &e.audience {
+                    Audience::All => true,
+                    Audience::User(id) => id == user_id,
+                    Audience::Users(ids) => ids.iter().any(|id| id == user_id),
+                })
+                .map(|e| e.payload.clone())
+                .collect(),
+        )
+    }
+}
+
+// 为事件分配递增的序号并记录，返回带序号的JSON
+pub fn record(event: &ServerEvent, audience: Audience) -> String {
+    EVENT_LOG.lock().unwrap().record(event, audience)
+}
+
+pub fn epoch() -> &'static str {
+    &EPOCH
+}
+
+pub fn last_seq() -> u64 {
+    EVENT_LOG.lock().unwrap().last_seq
+}
+
+// 取出 last_seq 之后该用户应收到的事件；缺口已被淘汰或序号来自上一次运行时需要全量同步
+pub fn replay_since(epoch: Option<&str>, last_seq: u64, user_id: &str) -> Replay {
+    if epoch.map(|e| e != EPOCH.as_str()).unwrap_or(false) {
+        return Replay::ResyncRequired;
+    }
+    EVENT_LOG.lock().unwrap().replay_since(last_seq, user_id)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn event() -> ServerEvent {
+        ServerEvent::UserBanned { user_id: "x".to_string() }
+    }
+
+    fn seqs(replay: Replay) -> Option<Vec<u64>> {
+        match replay {
+            Replay::Events(events) => Some(events.iter()
+                .map(|e| serde_json::from_str::<serde_json::Value>(e).unwrap()["seq"].as_u64().unwrap())
+                .collect()),
+            Replay::ResyncRequired => None,
+        }
+    }
+
+    #[test]
+    fn cursor_inside_window_replays_following_events() {
+        let mut log = EventLog::new();
+        for _ in 0..5 {
+            log.record(&event(), Audience::All);
+        }
+
+        assert_eq!(seqs(log.replay_since(2, "u1")), Some(vec![3, 4, 5]));
+        assert_eq!(seqs(log.replay_since(0, "u1")), Some(vec![1, 2, 3, 4, 5]));
+        assert_eq!(seqs(log.replay_since(5, "u1")), Some(vec![]));
+        // 序号超过当前位置（例如来自其他实例）时要求全量同步
+        assert_eq!(seqs(log.replay_since(6, "u1")), None);
+    }
+
+    #[test]
+    fn cursor_older_than_window_requires_resync() {
+        let mut log = EventLog::new();
+        for _ in 0..EVENT_LOG_CAPACITY + 10 {
+            log.record(&event(), Audience::All);
+        }
+        let oldest = log.events.front().unwrap().seq;
+        assert_eq!(oldest, 11);
+
+        // 紧挨着最早一条的位置仍可补发，再早一条就有缺口
+        assert_eq!(seqs(log.replay_since(oldest - 1, "u1")).map(|s| s.len()), Some(EVENT_LOG_CAPACITY));
+        assert_eq!(seqs(log.replay_since(oldest - 2, "u1")), None);
+        assert_eq!(seqs(log.replay_since(0, "u1")), None);
+    }
+
+    #[test]
+    fn empty_log_accepts_current_position_only() {
+        let log = EventLog::new();
+        assert_eq!(seqs(log.replay_since(0, "u1")), Some(vec![]));
+        assert_eq!(seqs(log.replay_since(1, "u1")), None);
+    }
+
+    #[test]
+    fn cursor_from_previous_epoch_requires_resync() {
+        assert_eq!(seqs(replay_since(Some("previous-run"), 0, "u1")), None);
+        assert!(seqs(replay_since(Some(epoch()), last_seq(), "u1")).is_some());
+    }
+
+    #[test]
+    fn replay_filters_by_audience() {
+        let mut log = EventLog::new();
+        log.record(&event(), Audience::All);
+        log.record(&event(), Audience::User("u1".to_string()));
+        log.record(&event(), Audience::User("u2".to_string()));
+        log.record(&event(), Audience::Users(vec!["u2".to_string(), "u3".to_string()]));
+        log.record(&event(), Audience::Users(vec!["u1".to_string(), "u3".to_string()]));
+
+        assert_eq!(seqs(log.replay_since(0, "u1")), Some(vec![1, 2, 5]));
+        assert_eq!(seqs(log.replay_since(0, "u2")), Some(vec![1, 3, 4]));
+        assert_eq!(seqs(log.replay_since(0, "u3")), Some(vec![1, 4, 5]));
+        assert_eq!(seqs(log.replay_since(0, "u4")), Some(vec![1]));
+    }
+}
\ No newline at end of file
@@ -16,16 +16,29 @@ const SMTP_PASSWORD: &str = "{{自己去填写}}"; // 请替换为实际的 SMTP
 const SENDER_NAME: &str = "{{自己去填写 示例：Cloud-PE}}"; // 请替换为实际的发件人名称
 const SENDER_EMAIL: &str = "{{自己去填写 示例：service@cloud-pe.cn}}"; // 请替换为实际的发件人邮箱
 
+// 头像只能通过上传接口设置，不接受客户端直接提交的地址
+pub(crate) fn is_valid_avatar(avatar: &Option<String>) -> bool {
+    avatar.is_none()
+}
+
 pub async fn get_public_settings() -> Result<HttpResponse> {
     let state = APP_STATE.lock().unwrap();
     let public_settings = PublicSettings {
         registration_open: state.settings.registration_open,
         require_approval: state.settings.require_approval,
+        topic: state.settings.topic.clone(),
+        pinned_message_ids: state.settings.pinned_message_ids.clone(),
+        slow_mode_seconds: state.settings.slow_mode_seconds,
     };
     
     Ok(HttpResponse::Ok().json(ApiResponse::success(public_settings)))
 }
 
+// 新增：WebSocket 事件的 JSON Schema
+pub async fn get_ws_schema() -> Result<HttpResponse> {
+    Ok(HttpResponse::Ok().json(crate::protocol::schema()))
+}
+
 pub async fn register(
     req: HttpRequest,
     body: web::Json<RegisterRequest>,
@@ -38,19 +51,15 @@ pub async fn register(
     state.clean_expired_data();
 
     // 检查IP黑名单
-    for blacklist_item in &state.ip_blacklist {
-        if blacklist_item.ip == client_ip && blacklist_item.until > Utc::now() {
-            return Ok(HttpResponse::Ok().json(ApiResponse::<()>::error(
-                "您的IP已被临时封禁".to_string()
-            )));
-        }
+    if let Err(e) = crate::blacklist::check_ip(&state, &client_ip) {
+        return Ok(HttpResponse::Ok().json(ApiResponse::<()>::error(e)));
     }
 
     // 检查注册频率
     let recent_attempts = {
         let attempts = state.registration_attempts
             .entry(client_ip.clone())
-            .or_insert_with(Vec::new);
+            .or_default();
         
         attempts.iter()
             .filter(|time| Utc::now().signed_duration_since(**time).num_hours() < 1)
@@ -62,7 +71,8 @@ pub async fn register(
         state.ip_blacklist.push(IpBlacklist {
             ip: client_ip.clone(),
             reason: "注册频率过高".to_string(),
-            until: Utc::now() + Duration::days(1),
+            until: Some(Utc::now() + Duration::days(1)),
+            added_by: None,
         });
         state.save_blacklist();
         
@@ -78,10 +88,14 @@ pub async fn register(
         )));
     }
 
-    // 检查邮箱黑名单
-    if state.email_blacklist.contains(&body.email) {
+    // 检查邮箱黑名单和域名白名单
+    if let Err(e) = crate::blacklist::check_email(&state, &body.email) {
+        return Ok(HttpResponse::Ok().json(ApiResponse::<()>::error(e)));
+    }
+
+    if !is_valid_avatar(&body.avatar) {
         return Ok(HttpResponse::Ok().json(ApiResponse::<()>::error(
-            "该邮箱已被禁止注册".to_string()
+            "头像只能通过上传设置".to_string()
         )));
     }
 
@@ -119,7 +133,7 @@ pub async fn register(
     // 记录注册尝试
     state.registration_attempts
         .entry(client_ip.clone())
-        .or_insert_with(Vec::new)
+        .or_default()
         .push(Utc::now());
 
     // 创建用户
@@ -133,9 +147,9 @@ pub async fn register(
         username: body.username.clone(),
         email: body.email.clone(),
         password_hash,
-        avatar: body.avatar.clone(),
+        avatar: None,
         display_name: None, // 新增：初始没有群聊昵称
-        role: if is_first_user { UserRole::Admin } else { UserRole::Member },
+        role: if is_first_user { ROLE_ADMIN } else { ROLE_MEMBER }.to_string(),
         status: if is_first_user { 
             UserStatus::Active 
         } else if state.settings.require_approval && state.settings.registration_open { 
@@ -146,6 +160,9 @@ pub async fn register(
         created_at: Utc::now(),
         last_ips: vec![client_ip.clone()],
         muted_until: None,
+        mute_reason: None,
+        is_bot: false,
+        ban: None,
     };
 
     let user_id = user.id.clone();
@@ -165,7 +182,7 @@ pub async fn register(
         state.sessions.insert(session_token.clone(), user_id.clone());
         state.user_sessions
             .entry(user_id)
-            .or_insert_with(Vec::new)
+            .or_default()
             .push(session);
         state.save_sessions();
         
@@ -201,6 +218,9 @@ pub async fn login(
         .map(|addr| addr.ip().to_string())
         .unwrap_or_else(|| "unknown".to_string());
 
+    // 先解除已到期的封禁，不必等定时任务
+    crate::chat::lift_expired_bans();
+
     let mut state = APP_STATE.lock().unwrap();
     
     // 查找用户
@@ -232,8 +252,21 @@ pub async fn login(
         }
         
         if user.status == UserStatus::Banned {
+            let ban = user.ban.as_ref();
+            let mut message = "账号已被封禁".to_string();
+            if let Some(reason) = ban.and_then(|b| b.reason.as_ref()) {
+                message.push_str(&format!("，原因：{}", reason));
+            }
+            match ban.and_then(|b| b.until) {
+                Some(until) => message.push_str(&format!("，解封时间：{}", until.format("%Y-%m-%d %H:%M UTC"))),
+                None => message.push_str("，永久封禁"),
+            }
+            return Ok(HttpResponse::Ok().json(ApiResponse::<()>::error(message)));
+        }
+        
+        if user.is_bot {
             return Ok(HttpResponse::Ok().json(ApiResponse::<()>::error(
-                "账号已被封禁".to_string()
+                "机器人账号只能通过访问令牌使用".to_string()
             )));
         }
         
@@ -256,7 +289,7 @@ pub async fn login(
         let token_to_remove = {
             let user_sessions = state.user_sessions
                 .entry(user_id.clone())
-                .or_insert_with(Vec::new);
+                .or_default();
                 
             if user_sessions.len() >= 5 {
                 // 获取要删除的 token
@@ -283,6 +316,7 @@ pub async fn login(
         state.save_users();
         state.save_sessions();
         
+        let unread = state.unread_summary(&user_id);
         drop(state);
         
         Ok(HttpResponse::Ok()
@@ -292,7 +326,10 @@ pub async fn login(
                     .http_only(true)
                     .finish()
             )
-            .json(ApiResponse::success("登录成功")))
+            .json(ApiResponse::success(LoginResponse {
+                message: "登录成功".to_string(),
+                unread,
+            })))
     } else {
         Ok(HttpResponse::Ok().json(ApiResponse::<()>::error(
             "邮箱或密码错误".to_string()
@@ -342,6 +379,10 @@ pub async fn send_verification_code(
         )));
     }
     
+    if let Err(e) = crate::blacklist::check_ip(&state, &client_ip) {
+        return Ok(HttpResponse::Ok().json(ApiResponse::<()>::error(e)));
+    }
+    
     // 检查邮箱格式
     let email_regex = Regex::new(r"^[^\s@]+@[^\s@]+\.[^\s@]+$").unwrap();
     if !email_regex.is_match(&body.email) {
@@ -350,11 +391,9 @@ pub async fn send_verification_code(
         )));
     }
     
-    // 检查邮箱黑名单
-    if state.email_blacklist.contains(&body.email) {
-        return Ok(HttpResponse::Ok().json(ApiResponse::<()>::error(
-            "该邮箱已被禁止注册".to_string()
-        )));
+    // 检查邮箱黑名单和域名白名单
+    if let Err(e) = crate::blacklist::check_email(&state, &body.email) {
+        return Ok(HttpResponse::Ok().json(ApiResponse::<()>::error(e)));
     }
     
     // 检查邮箱是否已存在（新增）
@@ -369,7 +408,7 @@ pub async fn send_verification_code(
     // 获取或创建该IP的发送记录
     let attempts = state.verification_attempts
         .entry(client_ip.clone())
-        .or_insert_with(Vec::new);
+        .or_default();
     
     let now = Utc::now();
     
@@ -557,20 +596,16 @@ pub async fn verify_email(req: HttpRequest) -> Result<HttpResponse> {
 }
 
 pub async fn get_pending_users(req: HttpRequest) -> Result<HttpResponse> {
-    let cookie = req.cookie("session_token");
-    if let Some(cookie) = cookie {
-        let token = cookie.value();
+    if let Some(token) = crate::auth::request_token(&req) {
         let state = APP_STATE.lock().unwrap();
         
-        if let Some(user_id) = state.sessions.get(token) {
-            if let Some(user) = state.users.get(user_id) {
-                if user.role == UserRole::Admin {
-                    let pending_users: Vec<&User> = state.users.values()
-                        .filter(|u| u.status == UserStatus::Pending)
-                        .collect();
-                    
-                    return Ok(HttpResponse::Ok().json(ApiResponse::success(pending_users)));
-                }
+        if let Some(user_id) = state.token_user_id(&token) {
+            if crate::permissions::has_permission(&state, user_id, Permission::Approve) {
+                let pending_users: Vec<&User> = state.users.values()
+                    .filter(|u| u.status == UserStatus::Pending)
+                    .collect();
+                
+                return Ok(HttpResponse::Ok().json(ApiResponse::success(pending_users)));
             }
         }
     }
@@ -584,42 +619,171 @@ pub async fn approve_user(
     req: HttpRequest,
     body: web::Json<ApproveRejectRequest>,
 ) -> Result<HttpResponse> {
-    let cookie = req.cookie("session_token");
-    if let Some(cookie) = cookie {
-        let token = cookie.value();
+    if let Some(token) = crate::auth::request_token(&req) {
         let mut state = APP_STATE.lock().unwrap();
         
-        if let Some(admin_id) = state.sessions.get(token) {
-            if let Some(admin) = state.users.get(admin_id) {
-                if admin.role == UserRole::Admin {
-                    // 获取用户邮箱和用户名（避免借用问题）
-                    let (user_email, user_name) = {
-                        if let Some(user) = state.users.get_mut(&body.user_id) {
-                            if user.status == UserStatus::Pending {
-                                user.status = UserStatus::Active;
-                                (user.email.clone(), user.username.clone())
-                            } else {
-                                return Ok(HttpResponse::Ok().json(ApiResponse::<()>::error(
-                                    "用户状态不正确".to_string()
-                                )));
-                            }
+        if let Some(admin_id) = state.token_user_id(&token) {
+            if crate::permissions::has_permission(&state, admin_id, Permission::Approve) {
+                let admin_id = admin_id.clone();
+                let reason = match crate::audit::normalize_reason(body.reason.as_deref()) {
+                    Ok(reason) => reason,
+                    Err(e) => return Ok(HttpResponse::Ok().json(ApiResponse::<()>::error(e))),
+                };
+                // 获取用户邮箱和用户名（避免借用问题）
+                let (user_email, user_name) = {
+                    if let Some(user) = state.users.get_mut(&body.user_id) {
+                        if user.status == UserStatus::Pending {
+                            user.status = UserStatus::Active;
+                            (user.email.clone(), user.username.clone())
                         } else {
                             return Ok(HttpResponse::Ok().json(ApiResponse::<()>::error(
-                                "用户不存在".to_string()
+                                "用户状态不正确".to_string()
                             )));
                         }
-                    };
-                    
-                    state.save_users();
-                    
-                    // 发送通知邮件
-                    let email = EmailMessage::builder()
-                        .from(format!("{} <{}>", SENDER_NAME, SENDER_EMAIL).parse().unwrap())
-                        .to(user_email.parse().unwrap())
-                        .subject("Cloud-PE 注册审核通过")
-                        .header(ContentType::TEXT_HTML)
-                        .body(format!(
-                            r#"
+                    } else {
+                        return Ok(HttpResponse::Ok().json(ApiResponse::<()>::error(
+                            "用户不存在".to_string()
+                        )));
+                    }
+                };
+                
+                state.save_users();
+
+                let approved = state.users.get(&body.user_id).cloned();
+                crate::audit::record(&mut state, &admin_id, AuditAction::ApproveUser, approved.as_ref(), serde_json::json!({}), reason);
+
+                let approved_user = approved.as_ref().map(crate::outgoing_webhooks::user_data);
+                crate::outgoing_webhooks::enqueue(&mut state, WebhookEvent::UserApproved, serde_json::json!({
+                    "user": approved_user,
+                    "approved_by": admin_id,
+                }));
+                
+                // 发送通知邮件
+                let email = EmailMessage::builder()
+                    .from(format!("{} <{}>", SENDER_NAME, SENDER_EMAIL).parse().unwrap())
+                    .to(user_email.parse().unwrap())
+                    .subject("Cloud-PE 注册审核通过")
+                    .header(ContentType::TEXT_HTML)
+                    .body(format!(
+                        r#"
+                        <!DOCTYPE html>
+                        <html>
+                        <head>
+                            <meta charset="UTF-8">
+                            <meta name="viewport" content="width=device-width, initial-scale=1.0">
+                        </head>
+                        <body style="margin: 0; padding: 0; background-color: #f5f5f5; font-family: -apple-system, BlinkMacSystemFont, 'Segoe UI', Roboto, 'Helvetica Neue', Arial, sans-serif;">
+                            <table width="100%" cellpadding="0" cellspacing="0" style="background-color: #f5f5f5; padding: 40px 0;">
+                                <tr>
+                                    <td align="center">
+                                        <table width="600" cellpadding="0" cellspacing="0" style="background-color: #ffffff; border-radius: 8px; box-shadow: 0 2px 8px rgba(0,0,0,0.1); overflow: hidden;">
+                                            <!-- Header -->
+                                            <tr>
+                                                <td style="background: linear-gradient(135deg, #42e695 0%, #3bb2b8 100%); padding: 40px 40px 30px 40px; text-align: center;">
+                                                    <img src="https://p1.cloud-pe.cn/cloud-pe.png" alt="Cloud-PE" style="width: 80px; height: 80px; margin-bottom: 20px;">
+                                                    <h1 style="color: #ffffff; margin: 0; font-size: 28px; font-weight: 600;">欢迎加入 Cloud-PE</h1>
+                                                </td>
+                                            </tr>
+                                            
+                                            <!-- Content -->
+                                            <tr>
+                                                <td style="padding: 40px;">
+                                                    <h2 style="color: #333333; font-size: 24px; margin: 0 0 20px 0;">恭喜您，{}！</h2>
+                                                    
+                                                    <p style="color: #666666; font-size: 16px; line-height: 24px; margin: 0 0 30px 0;">
+                                                        您的 Cloud-PE 项目交流群注册申请已经通过审核。现在您可以登录并开始使用了。
+                                                    </p>
+                                                    
+                                                    <div style="background-color: #f0fdf4; border: 1px solid #86efac; border-radius: 6px; padding: 20px; margin: 0 0 30px 0;">
+                                                        <p style="color: #166534; font-size: 16px; margin: 0;">
+                                                            <strong>✅ 审核已通过</strong><br>
+                                                            您现在可以使用注册时的邮箱和密码登录系统。
+                                                        </p>
+                                                    </div>
+                                                    
+                                                    <div style="text-align: center;">
+                                                        <a href="http://127.0.0.1:7675" style="display: inline-block; background-color: #42e695; color: #ffffff; text-decoration: none; padding: 14px 40px; border-radius: 6px; font-size: 16px; font-weight: 500;">
+                                                            立即登录
+                                                        </a>
+                                                    </div>
+                                                </td>
+                                            </tr>
+                                            
+                                            <!-- Footer -->
+                                            <tr>
+                                                <td style="background-color: #f8f9fa; padding: 30px 40px; text-align: center; border-top: 1px solid #e9ecef;">
+                                                    <p style="color: #999999; font-size: 14px; margin: 0 0 10px 0;">
+                                                        © 2025 Cloud-PE Team. All rights reserved.
+                                                    </p>
+                                                    <p style="color: #999999; font-size: 12px; margin: 0;">
+                                                        <a href="https://beian.miit.gov.cn/#/Integrated/index" style="color: #999999; text-decoration: none;">陇ICP备2023028944号</a>
+                                                    </p>
+                                                </td>
+                                            </tr>
+                                        </table>
+                                    </td>
+                                </tr>
+                            </table>
+                        </body>
+                        </html>
+                        "#,
+                        user_name
+                    ))
+                    .unwrap();
+                
+                let creds = Credentials::new(
+                    SMTP_USERNAME.to_string(),
+                    SMTP_PASSWORD.to_string(),
+                );
+                
+                let mailer = SmtpTransport::relay(SMTP_SERVER)
+                    .unwrap()
+                    .credentials(creds)
+                    .build();
+                
+                let _ = mailer.send(&email);
+                
+                return Ok(HttpResponse::Ok().json(ApiResponse::success("审核通过")));
+            }
+        }
+    }
+    
+    Ok(HttpResponse::Unauthorized().json(ApiResponse::<()>::error(
+        "无权操作".to_string()
+    )))
+}
+
+pub async fn reject_user(
+    req: HttpRequest,
+    body: web::Json<ApproveRejectRequest>,
+) -> Result<HttpResponse> {
+    if let Some(token) = crate::auth::request_token(&req) {
+        let mut state = APP_STATE.lock().unwrap();
+        
+        if let Some(admin_id) = state.token_user_id(&token) {
+            if crate::permissions::has_permission(&state, admin_id, Permission::Approve) {
+                let admin_id = admin_id.clone();
+                let reason = match crate::audit::normalize_reason(body.reason.as_deref()) {
+                    Ok(reason) => reason,
+                    Err(e) => return Ok(HttpResponse::Ok().json(ApiResponse::<()>::error(e))),
+                };
+                if let Some(user) = state.users.remove(&body.user_id) {
+                    if user.status == UserStatus::Pending {
+                        // 加入邮箱黑名单
+                        state.email_blacklist.push(EmailBlacklist {
+                            pattern: user.email.to_lowercase(),
+                            reason: "注册审核未通过".to_string(),
+                            until: None,
+                            added_by: Some(admin_id.clone()),
+                        });
+                        state.save_users();
+                        state.save_blacklist();
+                        crate::audit::record(&mut state, &admin_id, AuditAction::RejectUser, Some(&user), serde_json::json!({
+                            "email": user.email,
+                        }), reason);
+                        
+                        // 发送拒绝邮件
+                        let email_body = r#"
                             <!DOCTYPE html>
                             <html>
                             <head>
@@ -633,33 +797,25 @@ pub async fn approve_user(
                                             <table width="600" cellpadding="0" cellspacing="0" style="background-color: #ffffff; border-radius: 8px; box-shadow: 0 2px 8px rgba(0,0,0,0.1); overflow: hidden;">
                                                 <!-- Header -->
                                                 <tr>
-                                                    <td style="background: linear-gradient(135deg, #42e695 0%, #3bb2b8 100%); padding: 40px 40px 30px 40px; text-align: center;">
+                                                    <td style="background: linear-gradient(135deg, #f093fb 0%, #f5576c 100%); padding: 40px 40px 30px 40px; text-align: center;">
                                                         <img src="https://p1.cloud-pe.cn/cloud-pe.png" alt="Cloud-PE" style="width: 80px; height: 80px; margin-bottom: 20px;">
-                                                        <h1 style="color: #ffffff; margin: 0; font-size: 28px; font-weight: 600;">欢迎加入 Cloud-PE</h1>
+                                                        <h1 style="color: #ffffff; margin: 0; font-size: 28px; font-weight: 600;">注册审核结果</h1>
                                                     </td>
                                                 </tr>
                                                 
                                                 <!-- Content -->
                                                 <tr>
                                                     <td style="padding: 40px;">
-                                                        <h2 style="color: #333333; font-size: 24px; margin: 0 0 20px 0;">恭喜您，{}！</h2>
-                                                        
-                                                        <p style="color: #666666; font-size: 16px; line-height: 24px; margin: 0 0 30px 0;">
-                                                            您的 Cloud-PE 项目交流群注册申请已经通过审核。现在您可以登录并开始使用了。
-                                                        </p>
-                                                        
-                                                        <div style="background-color: #f0fdf4; border: 1px solid #86efac; border-radius: 6px; padding: 20px; margin: 0 0 30px 0;">
-                                                            <p style="color: #166534; font-size: 16px; margin: 0;">
-                                                                <strong>✅ 审核已通过</strong><br>
-                                                                您现在可以使用注册时的邮箱和密码登录系统。
+                                                        <div style="background-color: #fef2f2; border: 1px solid #fecaca; border-radius: 6px; padding: 20px; margin: 0 0 30px 0;">
+                                                            <p style="color: #991b1b; font-size: 16px; margin: 0;">
+                                                                <strong>❌ 审核未通过</strong><br>
+                                                                很抱歉，您的注册申请未能通过管理员审核。
                                                             </p>
                                                         </div>
                                                         
-                                                        <div style="text-align: center;">
-                                                            <a href="http://127.0.0.1:7675" style="display: inline-block; background-color: #42e695; color: #ffffff; text-decoration: none; padding: 14px 40px; border-radius: 6px; font-size: 16px; font-weight: 500;">
-                                                                立即登录
-                                                            </a>
-                                                        </div>
+                                                        <p style="color: #666666; font-size: 16px; line-height: 24px; margin: 0;">
+                                                            感谢您对 Cloud-PE 项目交流群的关注。
+                                                        </p>
                                                     </td>
                                                 </tr>
                                                 
@@ -680,131 +836,29 @@ pub async fn approve_user(
                                 </table>
                             </body>
                             </html>
-                            "#,
-                            user_name
-                        ))
-                        .unwrap();
-                    
-                    let creds = Credentials::new(
-                        SMTP_USERNAME.to_string(),
-                        SMTP_PASSWORD.to_string(),
-                    );
-                    
-                    let mailer = SmtpTransport::relay(SMTP_SERVER)
-                        .unwrap()
-                        .credentials(creds)
-                        .build();
-                    
-                    let _ = mailer.send(&email);
-                    
-                    return Ok(HttpResponse::Ok().json(ApiResponse::success("审核通过")));
-                }
-            }
-        }
-    }
-    
-    Ok(HttpResponse::Unauthorized().json(ApiResponse::<()>::error(
-        "无权操作".to_string()
-    )))
-}
-
-pub async fn reject_user(
-    req: HttpRequest,
-    body: web::Json<ApproveRejectRequest>,
-) -> Result<HttpResponse> {
-    let cookie = req.cookie("session_token");
-    if let Some(cookie) = cookie {
-        let token = cookie.value();
-        let mut state = APP_STATE.lock().unwrap();
-        
-        if let Some(admin_id) = state.sessions.get(token) {
-            if let Some(admin) = state.users.get(admin_id) {
-                if admin.role == UserRole::Admin {
-                    if let Some(user) = state.users.remove(&body.user_id) {
-                        if user.status == UserStatus::Pending {
-                            // 加入邮箱黑名单
-                            state.email_blacklist.push(user.email.clone());
-                            state.save_users();
-                            state.save_blacklist();
-                            
-                            // 发送拒绝邮件
-                            let email_body = format!(r#"
-                                <!DOCTYPE html>
-                                <html>
-                                <head>
-                                    <meta charset="UTF-8">
-                                    <meta name="viewport" content="width=device-width, initial-scale=1.0">
-                                </head>
-                                <body style="margin: 0; padding: 0; background-color: #f5f5f5; font-family: -apple-system, BlinkMacSystemFont, 'Segoe UI', Roboto, 'Helvetica Neue', Arial, sans-serif;">
-                                    <table width="100%" cellpadding="0" cellspacing="0" style="background-color: #f5f5f5; padding: 40px 0;">
-                                        <tr>
-                                            <td align="center">
-                                                <table width="600" cellpadding="0" cellspacing="0" style="background-color: #ffffff; border-radius: 8px; box-shadow: 0 2px 8px rgba(0,0,0,0.1); overflow: hidden;">
-                                                    <!-- Header -->
-                                                    <tr>
-                                                        <td style="background: linear-gradient(135deg, #f093fb 0%, #f5576c 100%); padding: 40px 40px 30px 40px; text-align: center;">
-                                                            <img src="https://p1.cloud-pe.cn/cloud-pe.png" alt="Cloud-PE" style="width: 80px; height: 80px; margin-bottom: 20px;">
-                                                            <h1 style="color: #ffffff; margin: 0; font-size: 28px; font-weight: 600;">注册审核结果</h1>
-                                                        </td>
-                                                    </tr>
-                                                    
-                                                    <!-- Content -->
-                                                    <tr>
-                                                        <td style="padding: 40px;">
-                                                            <div style="background-color: #fef2f2; border: 1px solid #fecaca; border-radius: 6px; padding: 20px; margin: 0 0 30px 0;">
-                                                                <p style="color: #991b1b; font-size: 16px; margin: 0;">
-                                                                    <strong>❌ 审核未通过</strong><br>
-                                                                    很抱歉，您的注册申请未能通过管理员审核。
-                                                                </p>
-                                                            </div>
-                                                            
-                                                            <p style="color: #666666; font-size: 16px; line-height: 24px; margin: 0;">
-                                                                感谢您对 Cloud-PE 项目交流群的关注。
-                                                            </p>
-                                                        </td>
-                                                    </tr>
-                                                    
-                                                    <!-- Footer -->
-                                                    <tr>
-                                                        <td style="background-color: #f8f9fa; padding: 30px 40px; text-align: center; border-top: 1px solid #e9ecef;">
-                                                            <p style="color: #999999; font-size: 14px; margin: 0 0 10px 0;">
-                                                                © 2025 Cloud-PE Team. All rights reserved.
-                                                            </p>
-                                                            <p style="color: #999999; font-size: 12px; margin: 0;">
-                                                                <a href="https://beian.miit.gov.cn/#/Integrated/index" style="color: #999999; text-decoration: none;">陇ICP备2023028944号</a>
-                                                            </p>
-                                                        </td>
-                                                    </tr>
-                                                </table>
-                                            </td>
-                                        </tr>
-                                    </table>
-                                </body>
-                                </html>
-                                "#);
-                            
-                            let email = EmailMessage::builder()
-                                .from(format!("{} <{}>", SENDER_NAME, SENDER_EMAIL).parse().unwrap())
-                                .to(user.email.parse().unwrap())
-                                .subject("Cloud-PE 注册审核结果")
-                                .header(ContentType::TEXT_HTML)
-                                .body(email_body)
-                                .unwrap();
-                            
-                            let creds = Credentials::new(
-                                SMTP_USERNAME.to_string(),
-                                SMTP_PASSWORD.to_string(),
-                            );
-                            
-                            let mailer = SmtpTransport::relay(SMTP_SERVER)
-                                .unwrap()
-                                .credentials(creds)
-                                .build();
-                            
-                            let _ = mailer.send(&email);
-                            
-                            return Ok(HttpResponse::Ok().json(ApiResponse::success("已拒绝")));
-                        }
+                            "#.to_string();
+                        
+                        let email = EmailMessage::builder()
+                            .from(format!("{} <{}>", SENDER_NAME, SENDER_EMAIL).parse().unwrap())
+                            .to(user.email.parse().unwrap())
+                            .subject("Cloud-PE 注册审核结果")
+                            .header(ContentType::TEXT_HTML)
+                            .body(email_body)
+                            .unwrap();
+                        
+                        let creds = Credentials::new(
+                            SMTP_USERNAME.to_string(),
+                            SMTP_PASSWORD.to_string(),
+                        );
+                        
+                        let mailer = SmtpTransport::relay(SMTP_SERVER)
+                            .unwrap()
+                            .credentials(creds)
+                            .build();
+                        
+                        let _ = mailer.send(&email);
+                        
+                        return Ok(HttpResponse::Ok().json(ApiResponse::success("已拒绝")));
                     }
                 }
             }
@@ -817,20 +871,16 @@ pub async fn reject_user(
 }
 
 pub async fn get_users(req: HttpRequest) -> Result<HttpResponse> {
-    let cookie = req.cookie("session_token");
-    if let Some(cookie) = cookie {
-        let token = cookie.value();
+    if let Some(token) = crate::auth::request_token(&req) {
         let state = APP_STATE.lock().unwrap();
         
-        if let Some(user_id) = state.sessions.get(token) {
-            if let Some(user) = state.users.get(user_id) {
-                if user.role == UserRole::Admin {
-                    let users: Vec<&User> = state.users.values()
-                        .filter(|u| u.status == UserStatus::Active)
-                        .collect();
-                    
-                    return Ok(HttpResponse::Ok().json(ApiResponse::success(users)));
-                }
+        if let Some(user_id) = state.token_user_id(&token) {
+            if crate::permissions::has_permission(&state, user_id, Permission::ManageUsers) {
+                let users: Vec<&User> = state.users.values()
+                    .filter(|u| u.status == UserStatus::Active)
+                    .collect();
+                
+                return Ok(HttpResponse::Ok().json(ApiResponse::success(users)));
             }
         }
     }
@@ -844,56 +894,61 @@ pub async fn add_user(
     req: HttpRequest,
     body: web::Json<AddUserRequest>,
 ) -> Result<HttpResponse> {
-    let cookie = req.cookie("session_token");
-    if let Some(cookie) = cookie {
-        let token = cookie.value();
+    if let Some(token) = crate::auth::request_token(&req) {
         let mut state = APP_STATE.lock().unwrap();
         
-        if let Some(admin_id) = state.sessions.get(token) {
-            if let Some(admin) = state.users.get(admin_id) {
-                if admin.role == UserRole::Admin {
-                    // 检查邮箱是否已存在
-                    for user in state.users.values() {
-                        if user.email == body.email {
-                            return Ok(HttpResponse::Ok().json(ApiResponse::<()>::error(
-                                "该邮箱已被注册".to_string()
-                            )));
-                        }
+        if let Some(admin_id) = state.token_user_id(&token) {
+            if crate::permissions::has_permission(&state, admin_id, Permission::ManageUsers) {
+                if !is_valid_avatar(&body.avatar) {
+                    return Ok(HttpResponse::Ok().json(ApiResponse::<()>::error(
+                        "头像只能通过上传设置".to_string()
+                    )));
+                }
+                
+                // 检查邮箱是否已存在
+                for user in state.users.values() {
+                    if user.email == body.email {
+                        return Ok(HttpResponse::Ok().json(ApiResponse::<()>::error(
+                            "该邮箱已被注册".to_string()
+                        )));
                     }
-                    
-                    // 检查用户名是否已存在
-                    for user in state.users.values() {
-                        if user.username == body.username {
-                            return Ok(HttpResponse::Ok().json(ApiResponse::<()>::error(
-                                "该用户名已被使用".to_string()
-                            )));
-                        }
+                }
+                
+                // 检查用户名是否已存在
+                for user in state.users.values() {
+                    if user.username == body.username {
+                        return Ok(HttpResponse::Ok().json(ApiResponse::<()>::error(
+                            "该用户名已被使用".to_string()
+                        )));
                     }
-                    
-                    // 创建用户
-                    let mut hasher = Sha256::new();
-                    hasher.update(body.password.as_bytes());
-                    let password_hash = hex::encode(hasher.finalize());
-                    
-                    let user = User {
-                        id: Uuid::new_v4().to_string(),
-                        username: body.username.clone(),
-                        email: body.email.clone(),
-                        password_hash,
-                        avatar: body.avatar.clone(),
-                        display_name: None,
-                        role: UserRole::Member,
-                        status: UserStatus::Active,
-                        created_at: Utc::now(),
-                        last_ips: Vec::new(),
-                        muted_until: None,
-                    };
-                    
-                    state.users.insert(user.id.clone(), user);
-                    state.save_users();
-                    
-                    return Ok(HttpResponse::Ok().json(ApiResponse::success("用户添加成功")));
                 }
+                
+                // 创建用户
+                let mut hasher = Sha256::new();
+                hasher.update(body.password.as_bytes());
+                let password_hash = hex::encode(hasher.finalize());
+                
+                let user = User {
+                    id: Uuid::new_v4().to_string(),
+                    username: body.username.clone(),
+                    email: body.email.clone(),
+                    password_hash,
+                    avatar: None,
+                    display_name: None,
+                    role: ROLE_MEMBER.to_string(),
+                    status: UserStatus::Active,
+                    created_at: Utc::now(),
+                    last_ips: Vec::new(),
+                    muted_until: None,
+                    mute_reason: None,
+                    is_bot: false,
+                    ban: None,
+                };
+                
+                state.users.insert(user.id.clone(), user);
+                state.save_users();
+                
+                return Ok(HttpResponse::Ok().json(ApiResponse::success("用户添加成功")));
             }
         }
     }
@@ -907,12 +962,10 @@ pub async fn delete_user(
     req: HttpRequest,
     body: web::Json<DeleteUserRequest>,
 ) -> Result<HttpResponse> {
-    let cookie = req.cookie("session_token");
-    if let Some(cookie) = cookie {
-        let token = cookie.value();
+    if let Some(token) = crate::auth::request_token(&req) {
         let mut state = APP_STATE.lock().unwrap();
         
-        if let Some(admin_id) = state.sessions.get(token) {
+        if let Some(admin_id) = state.token_user_id(&token) {
             // 防止管理员删除自己
             if admin_id == &body.user_id {
                 return Ok(HttpResponse::Ok().json(ApiResponse::<()>::error(
@@ -920,21 +973,36 @@ pub async fn delete_user(
                 )));
             }
             
-            if let Some(admin) = state.users.get(admin_id) {
-                if admin.role == UserRole::Admin {
-                    if let Some(_user) = state.users.remove(&body.user_id) {
-                        // 移除用户的所有会话
-                        if let Some(sessions) = state.user_sessions.remove(&body.user_id) {
-                            for session in sessions {
-                                state.sessions.remove(&session.token);
-                            }
+            if crate::permissions::can_act_on(&state, admin_id, &body.user_id, Permission::ManageUsers) {
+                let admin_id = admin_id.clone();
+                let reason = match crate::audit::normalize_reason(body.reason.as_deref()) {
+                    Ok(reason) => reason,
+                    Err(e) => return Ok(HttpResponse::Ok().json(ApiResponse::<()>::error(e))),
+                };
+                if let Some(user) = state.users.remove(&body.user_id) {
+                    // 移除用户的所有会话
+                    if let Some(sessions) = state.user_sessions.remove(&body.user_id) {
+                        for session in sessions {
+                            state.sessions.remove(&session.token);
                         }
-                        
-                        state.save_users();
-                        state.save_sessions();
-                        
-                        return Ok(HttpResponse::Ok().json(ApiResponse::success("用户已删除")));
                     }
+                    state.api_tokens.retain(|_, t| t.user_id != body.user_id);
+                    state.bot_commands.retain(|_, c| c.bot_user_id != body.user_id);
+                    
+                    state.save_users();
+                    state.save_sessions();
+                    state.save_api_tokens();
+                    state.save_bot_commands();
+                    crate::audit::record(&mut state, &admin_id, AuditAction::DeleteUser, Some(&user), serde_json::json!({
+                        "email": user.email,
+                        "role": user.role,
+                    }), reason);
+                    drop(state);
+                    
+                    // 广播用户被删除，并关闭其所有连接
+                    crate::websocket::broadcast_user_deleted(&body.user_id);
+                    
+                    return Ok(HttpResponse::Ok().json(ApiResponse::success("用户已删除")));
                 }
             }
         }
@@ -946,16 +1014,12 @@ pub async fn delete_user(
 }
 
 pub async fn get_settings(req: HttpRequest) -> Result<HttpResponse> {
-    let cookie = req.cookie("session_token");
-    if let Some(cookie) = cookie {
-        let token = cookie.value();
+    if let Some(token) = crate::auth::request_token(&req) {
         let state = APP_STATE.lock().unwrap();
         
-        if let Some(user_id) = state.sessions.get(token) {
-            if let Some(user) = state.users.get(user_id) {
-                if user.role == UserRole::Admin {
-                    return Ok(HttpResponse::Ok().json(ApiResponse::success(&state.settings)));
-                }
+        if let Some(user_id) = state.token_user_id(&token) {
+            if crate::permissions::has_permission(&state, user_id, Permission::ManageSettings) {
+                return Ok(HttpResponse::Ok().json(ApiResponse::success(&state.settings)));
             }
         }
     }
@@ -969,20 +1033,39 @@ pub async fn update_settings(
     req: HttpRequest,
     body: web::Json<UpdateSettingsRequest>,
 ) -> Result<HttpResponse> {
-    let cookie = req.cookie("session_token");
-    if let Some(cookie) = cookie {
-        let token = cookie.value();
+    if let Some(token) = crate::auth::request_token(&req) {
         let mut state = APP_STATE.lock().unwrap();
         
-        if let Some(user_id) = state.sessions.get(token) {
-            if let Some(user) = state.users.get(user_id) {
-                if user.role == UserRole::Admin {
-                    state.settings.registration_open = body.registration_open;
-                    state.settings.require_approval = body.require_approval;
-                    state.save_settings();
-                    
-                    return Ok(HttpResponse::Ok().json(ApiResponse::success("设置已更新")));
+        if let Some(user_id) = state.token_user_id(&token) {
+            if crate::permissions::has_permission(&state, user_id, Permission::ManageSettings) {
+                let user_id = user_id.clone();
+                let reason = match crate::audit::normalize_reason(body.reason.as_deref()) {
+                    Ok(reason) => reason,
+                    Err(e) => return Ok(HttpResponse::Ok().json(ApiResponse::<()>::error(e))),
+                };
+                if body.slow_mode_seconds.map(|s| s > crate::antispam::MAX_SLOW_MODE_SECONDS).unwrap_or(false) {
+                    return Ok(HttpResponse::Ok().json(ApiResponse::<()>::error(
+                        format!("慢速模式间隔不能超过{}秒", crate::antispam::MAX_SLOW_MODE_SECONDS)
+                    )));
                 }
+                let old_settings = state.settings.clone();
+                state.settings.registration_open = body.registration_open;
+                state.settings.require_approval = body.require_approval;
+                if let Some(policy) = &body.slow_consumer_policy {
+                    state.settings.slow_consumer_policy = policy.clone();
+                    crate::hub::set_policy(policy.clone());
+                }
+                if let Some(seconds) = body.slow_mode_seconds {
+                    state.settings.slow_mode_seconds = seconds;
+                }
+                state.save_settings();
+                let params = serde_json::json!({
+                    "old": old_settings,
+                    "new": state.settings,
+                });
+                crate::audit::record(&mut state, &user_id, AuditAction::UpdateSettings, None, params, reason);
+                
+                return Ok(HttpResponse::Ok().json(ApiResponse::success("设置已更新")));
             }
         }
     }
@@ -993,29 +1076,16 @@ pub async fn update_settings(
 }
 
 pub async fn get_messages(req: HttpRequest) -> Result<HttpResponse> {
-    let cookie = req.cookie("session_token");
-    if let Some(cookie) = cookie {
-        let token = cookie.value();
+    if let Some(token) = crate::auth::request_token(&req) {
         let state = APP_STATE.lock().unwrap();
         
-        if let Some(_user_id) = state.sessions.get(token) {
+        if let Some(_user_id) = state.token_user_id(&token) {
             // 返回最近100条消息，并包含用户信息
             let messages: Vec<MessageWithUser> = state.messages.iter()
                 .rev()
                 .take(100)
                 .rev()
-                .map(|msg| {
-                    let user = state.users.get(&msg.user_id).cloned();
-                    MessageWithUser {
-                        id: msg.id.clone(),
-                        user_id: msg.user_id.clone(),
-                        content: msg.content.clone(),
-                        timestamp: msg.timestamp,
-                        recalled: msg.recalled,
-                        user,
-                        original_content: msg.original_content.clone(),
-                    }
-                })
+                .map(|msg| state.message_with_user(msg))
                 .collect();
             
             return Ok(HttpResponse::Ok().json(ApiResponse::success(messages)));
@@ -1031,59 +1101,23 @@ pub async fn send_message(
     req: HttpRequest,
     body: web::Json<SendMessageRequest>,
 ) -> Result<HttpResponse> {
-    let cookie = req.cookie("session_token");
-    if let Some(cookie) = cookie {
-        let token = cookie.value();
-        let mut state = APP_STATE.lock().unwrap();
+    if let Some(token) = crate::auth::request_token(&req) {
+        let user_id = APP_STATE.lock().unwrap().token_user_id(&token).cloned();
         
-        if let Some(user_id) = state.sessions.get(token) {
-            let user_id = user_id.clone(); // 克隆 user_id
-            
-            if let Some(user) = state.users.get(&user_id) {
-                // 检查是否被禁言
-                if let Some(muted_until) = user.muted_until {
-                    if muted_until > Utc::now() {
-                        let remaining = muted_until.signed_duration_since(Utc::now());
-                        return Ok(HttpResponse::Ok().json(ApiResponse::<()>::error(
-                            format!("您已被禁言，剩余时间：{}分钟", remaining.num_minutes())
-                        )));
-                    }
-                }
-                
-                // 克隆用户信息
-                let user_info = user.clone();
-                
-                let message = Message {
-                    id: Uuid::new_v4().to_string(),
-                    user_id: user_id.clone(),
-                    content: body.content.clone(),
-                    timestamp: Utc::now(),
-                    recalled: false,
-                    original_content: None,
-                };
-                
-                state.messages.push(message.clone());
-                state.save_messages();
-                
-                // 释放锁后再进行广播
-                drop(state);
-                
-                // 创建包含用户信息的消息
-                let message_with_user = MessageWithUser {
-                    id: message.id.clone(),
-                    user_id: message.user_id.clone(),
-                    content: message.content.clone(),
-                    timestamp: message.timestamp,
-                    recalled: message.recalled,
-                    user: Some(user_info),
-                    original_content: None,
+        if let Some(user_id) = user_id {
+            // 斜杠命令
+            let can_moderate = crate::auth::allows(&req, TokenScope::Moderate);
+            if let Some(result) = crate::commands::dispatch(&user_id, &body.content, &body.attachment_ids, can_moderate) {
+                return match result {
+                    Ok(data) => Ok(HttpResponse::Ok().json(ApiResponse::success(data))),
+                    Err(e) => Ok(HttpResponse::Ok().json(ApiResponse::<()>::error(e))),
                 };
-                
-                // 广播消息给所有在线用户
-                crate::websocket::broadcast_message_with_user(&message_with_user);
-                
-                return Ok(HttpResponse::Ok().json(ApiResponse::success(message_with_user)));
             }
+
+            return match crate::chat::send_message(&user_id, &body.content, &body.attachment_ids) {
+                Ok(message_with_user) => Ok(HttpResponse::Ok().json(ApiResponse::success(message_with_user))),
+                Err(e) => Ok(HttpResponse::Ok().json(ApiResponse::<()>::error(e))),
+            };
         }
     }
     
@@ -1092,95 +1126,53 @@ pub async fn send_message(
     )))
 }
 
-pub async fn recall_message(
+// 新增：上传图片，请求体为图片原始字节，处理在后台进行
+pub async fn upload_image(
     req: HttpRequest,
-    body: web::Json<RecallMessageRequest>,
+    body: web::Bytes,
 ) -> Result<HttpResponse> {
-    let cookie = req.cookie("session_token");
-    if let Some(cookie) = cookie {
-        let token = cookie.value();
+    if let Some(token) = crate::auth::request_token(&req) {
         let mut state = APP_STATE.lock().unwrap();
         
-        if let Some(user_id) = state.sessions.get(token) {
+        if let Some(user_id) = state.token_user_id(&token) {
             let user_id = user_id.clone();
             
-            if let Some(user) = state.users.get(&user_id) {
-                let user_role = user.role.clone();
-                
-                // 先收集需要的信息，避免借用冲突
-                let mut message_info = None;
-                for message in &state.messages {
-                    if message.id == body.message_id && !message.recalled {
-                        message_info = Some((message.user_id.clone(), message.id.clone(), message.content.clone()));
-                        break;
-                    }
-                }
-                
-                if let Some((msg_user_id, msg_id, original_content)) = message_info {
-                    // 获取消息发送者的角色
-                    let msg_user_role = state.users.get(&msg_user_id)
-                        .map(|u| u.role.clone());
-                    
-                    // 检查权限
-                    let can_recall = match user_role {
-                        UserRole::Admin => true,
-                        UserRole::DeputyAdmin => {
-                            match msg_user_role {
-                                Some(UserRole::Admin) => false,
-                                _ => true,
-                            }
-                        }
-                        UserRole::Member => msg_user_id == user_id,
-                    };
-                    
-                    if can_recall {
-                        // 保存原始内容
-                        let mut recalled_message = None;
-                        for message in &mut state.messages {
-                            if message.id == msg_id {
-                                message.recalled = true;
-                                message.original_content = Some(original_content.clone());
-                                recalled_message = Some(message.clone());
-                                break;
-                            }
-                        }
-                        
-                        state.save_messages();
-                                            
-                        // 广播完整的消息数据而不是只广播ID
-                        if let Some(msg) = recalled_message {
-                            // 获取用户信息
-                            let user = state.users.get(&msg.user_id).cloned();
-                            drop(state);
-                            
-                            let message_with_user = MessageWithUser {
-                                id: msg.id,
-                                user_id: msg.user_id,
-                                content: msg.content,
-                                timestamp: msg.timestamp,
-                                recalled: msg.recalled,
-                                user: user,
-                                original_content: msg.original_content,
-                            };
-                            
-                            crate::websocket::broadcast_recall_with_message(&message_with_user);
-                        } else {
-                            drop(state);
-                            crate::websocket::broadcast_recall(&body.message_id);
-                        }
-    
-                        return Ok(HttpResponse::Ok().json(ApiResponse::success("消息已撤回")));
-                    } else {
-                        return Ok(HttpResponse::Ok().json(ApiResponse::<()>::error(
-                            "无权撤回此消息".to_string()
-                        )));
-                    }
-                } else {
+            if body.is_empty() || body.len() > crate::images::MAX_IMAGE_BYTES {
+                return Ok(HttpResponse::Ok().json(ApiResponse::<()>::error(
+                    "图片大小不能超过10MB".to_string()
+                )));
+            }
+            
+            let format = match crate::images::sniff_format(&body) {
+                Some(format) => format,
+                None => {
                     return Ok(HttpResponse::Ok().json(ApiResponse::<()>::error(
-                        "消息不存在或已被撤回".to_string()
+                        "不支持的图片格式".to_string()
                     )));
                 }
-            }
+            };
+            
+            let attachment = Attachment {
+                id: Uuid::new_v4().to_string(),
+                uploader_id: user_id,
+                content_type: format.to_mime_type().to_string(),
+                status: AttachmentStatus::Processing,
+                url: None,
+                width: None,
+                height: None,
+                size_bytes: body.len() as u64,
+                thumbnails: Vec::new(),
+                created_at: Utc::now(),
+            };
+            
+            state.attachments.insert(attachment.id.clone(), attachment.clone());
+            state.save_attachments();
+            drop(state);
+            
+            // 解码、去除EXIF和生成缩略图在后台完成
+            crate::images::spawn_processing(attachment.id.clone(), body.to_vec());
+            
+            return Ok(HttpResponse::Ok().json(ApiResponse::success(attachment)));
         }
     }
     
@@ -1189,80 +1181,103 @@ pub async fn recall_message(
     )))
 }
 
-pub async fn set_deputy_admin(
+pub async fn recall_message(
     req: HttpRequest,
-    body: web::Json<SetDeputyAdminRequest>,
+    body: web::Json<RecallMessageRequest>,
 ) -> Result<HttpResponse> {
-    let cookie = req.cookie("session_token");
-    if let Some(cookie) = cookie {
-        let token = cookie.value();
-        let mut state = APP_STATE.lock().unwrap();
+    if let Some(token) = crate::auth::request_token(&req) {
+        let user_id = APP_STATE.lock().unwrap().token_user_id(&token).cloned();
         
-        if let Some(admin_id) = state.sessions.get(token) {
-            if let Some(admin) = state.users.get(admin_id) {
-                if admin.role == UserRole::Admin {
-                    if let Some(user) = state.users.get_mut(&body.user_id) {
-                        if user.role != UserRole::Admin {
-                            let old_role = user.role.clone();
-                            user.role = if body.is_deputy {
-                                UserRole::DeputyAdmin
-                            } else {
-                                UserRole::Member
-                            };
-                            let new_role = user.role.clone();
-                            let user_id = user.id.clone();
-                            state.save_users();
-                            
-                            // 广播权限变更
-                            drop(state);
-                            crate::websocket::broadcast_role_changed(&user_id, &old_role, &new_role);
-                            
-                            return Ok(HttpResponse::Ok().json(ApiResponse::success("权限已更新")));
-                        }
-                    }
-                }
-            }
+        if let Some(user_id) = user_id {
+            // 访问令牌需要管理权限才能撤回他人的消息
+            let can_moderate = crate::auth::allows(&req, TokenScope::Moderate);
+            return match crate::chat::recall_message(&user_id, &body.message_id, can_moderate, body.reason.as_deref()) {
+                Ok(()) => Ok(HttpResponse::Ok().json(ApiResponse::success("消息已撤回"))),
+                Err(e) => Ok(HttpResponse::Ok().json(ApiResponse::<()>::error(e))),
+            };
         }
     }
     
     Ok(HttpResponse::Unauthorized().json(ApiResponse::<()>::error(
-        "无权操作".to_string()
+        "未登录".to_string()
     )))
 }
 
-pub async fn mute_user(
+// 新增：表情回应，重复回应同一表情即取消
+pub async fn react_message(
     req: HttpRequest,
-    body: web::Json<MuteUserRequest>,
+    body: web::Json<ReactMessageRequest>,
 ) -> Result<HttpResponse> {
-    let cookie = req.cookie("session_token");
-    if let Some(cookie) = cookie {
-        let token = cookie.value();
-        let mut state = APP_STATE.lock().unwrap();
+    if let Some(token) = crate::auth::request_token(&req) {
+        let user_id = APP_STATE.lock().unwrap().token_user_id(&token).cloned();
         
-        if let Some(operator_id) = state.sessions.get(token) {
-            if let Some(operator) = state.users.get(operator_id) {
-                let can_mute = match operator.role {
-                    UserRole::Admin => true,
-                    UserRole::DeputyAdmin => {
-                        // 次管理员只能禁言普通成员
-                        if let Some(target_user) = state.users.get(&body.user_id) {
-                            target_user.role == UserRole::Member
-                        } else {
-                            false
-                        }
-                    }
-                    UserRole::Member => false,
-                };
-                
-                if can_mute {
-                    if let Some(user) = state.users.get_mut(&body.user_id) {
-                        user.muted_until = Some(Utc::now() + Duration::minutes(body.duration_minutes));
-                        state.save_users();
-                        
-                        return Ok(HttpResponse::Ok().json(ApiResponse::success("已禁言")));
-                    }
-                }
-            }
+        if let Some(user_id) = user_id {
+            return match crate::chat::react_message(&user_id, &body.message_id, &body.emoji) {
+                Ok(reactions) => Ok(HttpResponse::Ok().json(ApiResponse::success(reactions))),
+                Err(e) => Ok(HttpResponse::Ok().json(ApiResponse::<()>::error(e))),
+            };
+        }
+    }
+    
+    Ok(HttpResponse::Unauthorized().json(ApiResponse::<()>::error(
+        "未登录".to_string()
+    )))
+}
+
+// 兼容旧接口：在次管理员和普通成员之间切换
+pub async fn set_deputy_admin(
+    req: HttpRequest,
+    body: web::Json<SetDeputyAdminRequest>,
+) -> Result<HttpResponse> {
+    if let Some(token) = crate::auth::request_token(&req) {
+        let operator_id = APP_STATE.lock().unwrap().token_user_id(&token).cloned();
+        
+        if let Some(operator_id) = operator_id {
+            let role_id = if body.is_deputy { ROLE_DEPUTY_ADMIN } else { ROLE_MEMBER };
+            return match crate::permissions::set_user_role(&operator_id, &body.user_id, role_id, body.reason.as_deref()) {
+                Ok(()) => Ok(HttpResponse::Ok().json(ApiResponse::success("权限已更新"))),
+                Err(e) => Ok(HttpResponse::Ok().json(ApiResponse::<()>::error(e))),
+            };
+        }
+    }
+    
+    Ok(HttpResponse::Unauthorized().json(ApiResponse::<()>::error(
+        "无权操作".to_string()
+    )))
+}
+
+pub async fn set_user_role(
+    req: HttpRequest,
+    body: web::Json<SetUserRoleRequest>,
+) -> Result<HttpResponse> {
+    if let Some(token) = crate::auth::request_token(&req) {
+        let operator_id = APP_STATE.lock().unwrap().token_user_id(&token).cloned();
+        
+        if let Some(operator_id) = operator_id {
+            return match crate::permissions::set_user_role(&operator_id, &body.user_id, &body.role_id, body.reason.as_deref()) {
+                Ok(()) => Ok(HttpResponse::Ok().json(ApiResponse::success("角色已更新"))),
+                Err(e) => Ok(HttpResponse::Ok().json(ApiResponse::<()>::error(e))),
+            };
+        }
+    }
+    
+    Ok(HttpResponse::Unauthorized().json(ApiResponse::<()>::error(
+        "无权操作".to_string()
+    )))
+}
+
+pub async fn mute_user(
+    req: HttpRequest,
+    body: web::Json<MuteUserRequest>,
+) -> Result<HttpResponse> {
+    if let Some(token) = crate::auth::request_token(&req) {
+        let operator_id = APP_STATE.lock().unwrap().token_user_id(&token).cloned();
+        
+        if let Some(operator_id) = operator_id {
+            return match crate::chat::mute_user(&operator_id, &body.user_id, body.duration_minutes, body.reason.as_deref()) {
+                Ok(_) => Ok(HttpResponse::Ok().json(ApiResponse::success("已禁言"))),
+                Err(e) => Ok(HttpResponse::Ok().json(ApiResponse::<()>::error(e))),
+            };
         }
     }
     
@@ -1275,56 +1290,84 @@ pub async fn ban_user(
     req: HttpRequest,
     body: web::Json<BanUserRequest>,
 ) -> Result<HttpResponse> {
-    let cookie = req.cookie("session_token");
-    if let Some(cookie) = cookie {
-        let token = cookie.value();
-        let mut state = APP_STATE.lock().unwrap();
+    if let Some(token) = crate::auth::request_token(&req) {
+        let operator_id = APP_STATE.lock().unwrap().token_user_id(&token).cloned();
         
-        if let Some(operator_id) = state.sessions.get(token) {
-            // 防止封禁自己
-            if operator_id == &body.user_id {
-                return Ok(HttpResponse::Ok().json(ApiResponse::<()>::error(
-                    "不能封禁自己".to_string()
-                )));
-            }
-            
-            if let Some(operator) = state.users.get(operator_id) {
-                let can_ban = match operator.role {
-                    UserRole::Admin => true,
-                    UserRole::DeputyAdmin => {
-                        // 次管理员只能封禁普通成员
-                        if let Some(target_user) = state.users.get(&body.user_id) {
-                            target_user.role == UserRole::Member
-                        } else {
-                            false
-                        }
-                    }
-                    UserRole::Member => false,
+        if let Some(operator_id) = operator_id {
+            return match crate::chat::ban_user(&operator_id, &body.user_id, body.duration_minutes, body.reason.as_deref()) {
+                Ok(_) => Ok(HttpResponse::Ok().json(ApiResponse::success("用户已封禁"))),
+                Err(e) => Ok(HttpResponse::Ok().json(ApiResponse::<()>::error(e))),
+            };
+        }
+    }
+    
+    Ok(HttpResponse::Unauthorized().json(ApiResponse::<()>::error(
+        "无权操作".to_string()
+    )))
+}
+
+pub async fn unban_user(
+    req: HttpRequest,
+    body: web::Json<UnbanUserRequest>,
+) -> Result<HttpResponse> {
+    if let Some(token) = crate::auth::request_token(&req) {
+        let operator_id = APP_STATE.lock().unwrap().token_user_id(&token).cloned();
+        
+        if let Some(operator_id) = operator_id {
+            return match crate::chat::unban_user(&operator_id, &body.user_id, body.reason.as_deref()) {
+                Ok(_) => Ok(HttpResponse::Ok().json(ApiResponse::success("已解除封禁"))),
+                Err(e) => Ok(HttpResponse::Ok().json(ApiResponse::<()>::error(e))),
+            };
+        }
+    }
+    
+    Ok(HttpResponse::Unauthorized().json(ApiResponse::<()>::error(
+        "无权操作".to_string()
+    )))
+}
+
+// 批量清理某用户的消息，可同时封禁；清理成功后再封禁，封禁失败时仍返回清理结果
+pub async fn purge_user_messages(
+    req: HttpRequest,
+    body: web::Json<PurgeUserMessagesRequest>,
+) -> Result<HttpResponse> {
+    if let Some(token) = crate::auth::request_token(&req) {
+        let operator_id = APP_STATE.lock().unwrap().token_user_id(&token).cloned();
+        
+        if let Some(operator_id) = operator_id {
+            // 清理的参数和权限由 chat::purge_user_messages 检查；需要封禁时先检查封禁权限，避免只完成清理
+            if body.ban {
+                let allowed = {
+                    let state = APP_STATE.lock().unwrap();
+                    crate::chat::check_ban(&state, &operator_id, &body.user_id, body.ban_duration_minutes)
                 };
-                
-                if can_ban {
-                    if let Some(user) = state.users.remove(&body.user_id) {
-                        // 加入邮箱黑名单
-                        state.email_blacklist.push(user.email.clone());
-                        
-                        // 移除用户的所有会话
-                        if let Some(sessions) = state.user_sessions.remove(&body.user_id) {
-                            for session in sessions {
-                                state.sessions.remove(&session.token);
-                            }
-                        }
-                        
-                        state.save_users();
-                        state.save_sessions();
-                        state.save_blacklist();
-                        
-                        // 广播用户被封禁
-                        crate::websocket::broadcast_user_banned(&body.user_id);
-                        
-                        return Ok(HttpResponse::Ok().json(ApiResponse::success("用户已封禁")));
-                    }
+                if let Err(e) = allowed {
+                    return Ok(HttpResponse::Ok().json(ApiResponse::<()>::error(e)));
                 }
             }
+            
+            let message_ids = match crate::chat::purge_user_messages(&operator_id, &body.user_id, body.since, body.until, body.mode, body.reason.as_deref()) {
+                Ok(message_ids) => message_ids,
+                Err(e) => return Ok(HttpResponse::Ok().json(ApiResponse::<()>::error(e))),
+            };
+            
+            // 封禁失败时附上失败原因
+            let (banned, ban_until, ban_error) = if body.ban {
+                match crate::chat::ban_user(&operator_id, &body.user_id, body.ban_duration_minutes, body.reason.as_deref()) {
+                    Ok(until) => (true, until, None),
+                    Err(e) => (false, None, Some(e)),
+                }
+            } else {
+                (false, None, None)
+            };
+            
+            return Ok(HttpResponse::Ok().json(ApiResponse::success(PurgeResult {
+                purged_count: message_ids.len(),
+                message_ids,
+                banned,
+                ban_until,
+                ban_error,
+            })));
         }
     }
     
@@ -1334,14 +1377,15 @@ pub async fn ban_user(
 }
 
 pub async fn get_current_user(req: HttpRequest) -> Result<HttpResponse> {
-    let cookie = req.cookie("session_token");
-    if let Some(cookie) = cookie {
-        let token = cookie.value();
+    if let Some(token) = crate::auth::request_token(&req) {
         let state = APP_STATE.lock().unwrap();
         
-        if let Some(user_id) = state.sessions.get(token) {
+        if let Some(user_id) = state.token_user_id(&token) {
             if let Some(user) = state.users.get(user_id) {
-                return Ok(HttpResponse::Ok().json(ApiResponse::success(user)));
+                return Ok(HttpResponse::Ok().json(ApiResponse::success(CurrentUserResponse {
+                    user,
+                    unread: state.unread_summary(user_id),
+                })));
             }
         }
     }
@@ -1355,35 +1399,14 @@ pub async fn unmute_user(
     req: HttpRequest,
     body: web::Json<UnmuteUserRequest>,
 ) -> Result<HttpResponse> {
-    let cookie = req.cookie("session_token");
-    if let Some(cookie) = cookie {
-        let token = cookie.value();
-        let mut state = APP_STATE.lock().unwrap();
+    if let Some(token) = crate::auth::request_token(&req) {
+        let operator_id = APP_STATE.lock().unwrap().token_user_id(&token).cloned();
         
-        if let Some(operator_id) = state.sessions.get(token) {
-            if let Some(operator) = state.users.get(operator_id) {
-                let can_unmute = match operator.role {
-                    UserRole::Admin => true,
-                    UserRole::DeputyAdmin => {
-                        // 次管理员只能解除禁言普通成员
-                        if let Some(target_user) = state.users.get(&body.user_id) {
-                            target_user.role == UserRole::Member
-                        } else {
-                            false
-                        }
-                    }
-                    UserRole::Member => false,
-                };
-                
-                if can_unmute {
-                    if let Some(user) = state.users.get_mut(&body.user_id) {
-                        user.muted_until = None;
-                        state.save_users();
-                        
-                        return Ok(HttpResponse::Ok().json(ApiResponse::success("已解除禁言")));
-                    }
-                }
-            }
+        if let Some(operator_id) = operator_id {
+            return match crate::chat::unmute_user(&operator_id, &body.user_id, body.reason.as_deref()) {
+                Ok(_) => Ok(HttpResponse::Ok().json(ApiResponse::success("已解除禁言"))),
+                Err(e) => Ok(HttpResponse::Ok().json(ApiResponse::<()>::error(e))),
+            };
         }
     }
     
@@ -1393,12 +1416,10 @@ pub async fn unmute_user(
 }
 
 pub async fn get_mention_checks(req: HttpRequest) -> Result<HttpResponse> {
-    let cookie = req.cookie("session_token");
-    if let Some(cookie) = cookie {
-        let token = cookie.value();
+    if let Some(token) = crate::auth::request_token(&req) {
         let state = APP_STATE.lock().unwrap();
         
-        if let Some(user_id) = state.sessions.get(token) {
+        if let Some(user_id) = state.token_user_id(&token) {
             if let Some(mention_check) = state.mention_checks.get(user_id) {
                 return Ok(HttpResponse::Ok().json(ApiResponse::success(&mention_check.checked_message_ids)));
             } else {
@@ -1412,16 +1433,34 @@ pub async fn get_mention_checks(req: HttpRequest) -> Result<HttpResponse> {
     )))
 }
 
+// 新增：获取未查看的@消息
+pub async fn get_unread_mentions(req: HttpRequest) -> Result<HttpResponse> {
+    if let Some(token) = crate::auth::request_token(&req) {
+        let state = APP_STATE.lock().unwrap();
+        
+        if let Some(user_id) = state.token_user_id(&token) {
+            let message_ids = state.unread_mention_ids(user_id);
+            
+            return Ok(HttpResponse::Ok().json(ApiResponse::success(UnreadMentionsResponse {
+                count: message_ids.len(),
+                message_ids,
+            })));
+        }
+    }
+    
+    Ok(HttpResponse::Unauthorized().json(ApiResponse::<()>::error(
+        "未登录".to_string()
+    )))
+}
+
 pub async fn mark_mentions_checked(
     req: HttpRequest,
     body: web::Json<MarkMentionsCheckedRequest>,
 ) -> Result<HttpResponse> {
-    let cookie = req.cookie("session_token");
-    if let Some(cookie) = cookie {
-        let token = cookie.value();
+    if let Some(token) = crate::auth::request_token(&req) {
         let mut state = APP_STATE.lock().unwrap();
         
-        if let Some(user_id) = state.sessions.get(token) {
+        if let Some(user_id) = state.token_user_id(&token) {
             let user_id = user_id.clone();
             
             if let Some(mention_check) = state.mention_checks.get_mut(&user_id) {
@@ -1439,11 +1478,15 @@ pub async fn mark_mentions_checked(
                     checked_message_ids: body.message_ids.clone(),
                     last_updated: Utc::now(),
                 };
-                state.mention_checks.insert(user_id, mention_check);
+                state.mention_checks.insert(user_id.clone(), mention_check);
             }
             
             state.save_mention_checks();
             
+            let unread = state.unread_summary(&user_id);
+            drop(state);
+            crate::websocket::send_read_marker_updated(&user_id, &unread);
+            
             return Ok(HttpResponse::Ok().json(ApiResponse::success("已标记为已查看")));
         }
     }
@@ -1453,19 +1496,53 @@ pub async fn mark_mentions_checked(
     )))
 }
 
+// 新增：获取在线用户及其状态
+pub async fn get_online_users(req: HttpRequest) -> Result<HttpResponse> {
+    if let Some(token) = crate::auth::request_token(&req) {
+        let logged_in = APP_STATE.lock().unwrap().token_user_id(&token).is_some();
+        
+        if logged_in {
+            return Ok(HttpResponse::Ok().json(ApiResponse::success(crate::presence::online_users())));
+        }
+    }
+    
+    Ok(HttpResponse::Unauthorized().json(ApiResponse::<()>::error(
+        "未登录".to_string()
+    )))
+}
+
+// 新增：推进已读位置，只能向后移动
+pub async fn mark_read(
+    req: HttpRequest,
+    body: web::Json<MarkReadRequest>,
+) -> Result<HttpResponse> {
+    if let Some(token) = crate::auth::request_token(&req) {
+        let user_id = APP_STATE.lock().unwrap().token_user_id(&token).cloned();
+        
+        if let Some(user_id) = user_id {
+            return match crate::chat::mark_read(&user_id, &body.message_id) {
+                Ok(unread) => Ok(HttpResponse::Ok().json(ApiResponse::success(unread))),
+                Err(e) => Ok(HttpResponse::Ok().json(ApiResponse::<()>::error(e))),
+            };
+        }
+    }
+    
+    Ok(HttpResponse::Unauthorized().json(ApiResponse::<()>::error(
+        "未登录".to_string()
+    )))
+}
+
 // 新增：注销账号接口
 pub async fn delete_account(req: HttpRequest) -> Result<HttpResponse> {
-    let cookie = req.cookie("session_token");
-    if let Some(cookie) = cookie {
-        let token = cookie.value();
+    if let Some(token) = crate::auth::request_token(&req) {
         let mut state = APP_STATE.lock().unwrap();
         
-        if let Some(user_id) = state.sessions.get(token) {
+        if let Some(user_id) = state.token_user_id(&token) {
             let user_id = user_id.clone();
             
             if let Some(user) = state.users.get(&user_id) {
                 // 管理员不能注销账号
-                if user.role == UserRole::Admin {
+                if user.role == ROLE_ADMIN {
                     return Ok(HttpResponse::Ok().json(ApiResponse::<()>::error(
                         "管理员账号不能注销".to_string()
                     )));
@@ -1480,9 +1557,13 @@ pub async fn delete_account(req: HttpRequest) -> Result<HttpResponse> {
                         state.sessions.remove(&session.token);
                     }
                 }
+                state.api_tokens.retain(|_, t| t.user_id != user_id);
+                state.bot_commands.retain(|_, c| c.bot_user_id != user_id);
                 
                 state.save_users();
                 state.save_sessions();
+                state.save_api_tokens();
+                state.save_bot_commands();
                 
                 drop(state);
                 
@@ -1511,12 +1592,10 @@ pub async fn update_display_name(
     req: HttpRequest,
     body: web::Json<UpdateDisplayNameRequest>,
 ) -> Result<HttpResponse> {
-    let cookie = req.cookie("session_token");
-    if let Some(cookie) = cookie {
-        let token = cookie.value();
+    if let Some(token) = crate::auth::request_token(&req) {
         let mut state = APP_STATE.lock().unwrap();
         
-        if let Some(user_id) = state.sessions.get(token) {
+        if let Some(user_id) = state.token_user_id(&token) {
             let user_id = user_id.clone();
             
             if let Some(user) = state.users.get_mut(&user_id) {
@@ -1544,15 +1623,13 @@ pub async fn update_user_display_name(
     req: HttpRequest,
     body: web::Json<UpdateUserDisplayNameRequest>,
 ) -> Result<HttpResponse> {
-    let cookie = req.cookie("session_token");
-    if let Some(cookie) = cookie {
-        let token = cookie.value();
+    if let Some(token) = crate::auth::request_token(&req) {
         let mut state = APP_STATE.lock().unwrap();
         
-        if let Some(operator_id) = state.sessions.get(token) {
+        if let Some(operator_id) = state.token_user_id(&token) {
             let operator_id = operator_id.clone();
             
-            if let Some(operator) = state.users.get(&operator_id) {
+            if state.users.contains_key(&operator_id) {
                 // 如果是修改自己的昵称
                 if operator_id == body.user_id {
                     if let Some(user) = state.users.get_mut(&body.user_id) {
@@ -1570,18 +1647,8 @@ pub async fn update_user_display_name(
                     }
                 } else {
                     // 修改其他人的昵称，检查权限
-                    if let Some(target_user) = state.users.get(&body.user_id) {
-                        let can_edit = match operator.role {
-                            UserRole::Admin => {
-                                // 管理员可以修改次管理员和普通成员
-                                target_user.role == UserRole::DeputyAdmin || target_user.role == UserRole::Member
-                            },
-                            UserRole::DeputyAdmin => {
-                                // 次管理员只能修改普通成员
-                                target_user.role == UserRole::Member
-                            },
-                            UserRole::Member => false,
-                        };
+                    if state.users.contains_key(&body.user_id) {
+                        let can_edit = crate::permissions::can_act_on(&state, &operator_id, &body.user_id, Permission::EditNicknames);
                         
                         if can_edit {
                             if let Some(user) = state.users.get_mut(&body.user_id) {
@@ -1608,6 +1675,293 @@ pub async fn update_user_display_name(
         }
     }
     
+    Ok(HttpResponse::Unauthorized().json(ApiResponse::<()>::error(
+        "未登录".to_string()
+    )))
+}
+
+// 新增：上传头像，请求体为图片原始字节
+pub async fn upload_avatar(
+    req: HttpRequest,
+    body: web::Bytes,
+) -> Result<HttpResponse> {
+    if let Some(token) = crate::auth::request_token(&req) {
+        let user_id = APP_STATE.lock().unwrap().token_user_id(&token).cloned();
+        
+        if let Some(user_id) = user_id {
+            if body.is_empty() || body.len() > crate::images::MAX_AVATAR_BYTES {
+                return Ok(HttpResponse::Ok().json(ApiResponse::<()>::error(
+                    "头像大小不能超过2MB".to_string()
+                )));
+            }
+            
+            // 解码和缩放比较耗时，放到线程池中执行
+            let uid = user_id.clone();
+            let result = web::block(move || crate::images::process_avatar(&uid, &body)).await;
+            let avatar_url = match result {
+                Ok(Ok(url)) => url,
+                Ok(Err(e)) => {
+                    eprintln!("处理头像失败: {}", e);
+                    return Ok(HttpResponse::Ok().json(ApiResponse::<()>::error(
+                        "无法识别的图片".to_string()
+                    )));
+                }
+                Err(_) => {
+                    return Ok(HttpResponse::Ok().json(ApiResponse::<()>::error(
+                        "头像处理失败，请稍后再试".to_string()
+                    )));
+                }
+            };
+            
+            let mut state = APP_STATE.lock().unwrap();
+            if let Some(user) = state.users.get_mut(&user_id) {
+                let old_avatar = user.avatar.replace(avatar_url.clone());
+                let new_avatar = user.avatar.clone();
+                state.save_users();
+                drop(state);
+                
+                if let Some(old_avatar) = old_avatar {
+                    crate::images::remove_avatar_file(&user_id, &old_avatar);
+                }
+                
+                // 广播头像变更
+                crate::websocket::broadcast_avatar_changed(&user_id, &new_avatar);
+                
+                return Ok(HttpResponse::Ok().json(ApiResponse::success(avatar_url)));
+            }
+            
+            // 处理期间用户已被删除
+            crate::images::remove_avatar_file(&user_id, &avatar_url);
+        }
+    }
+    
+    Ok(HttpResponse::Unauthorized().json(ApiResponse::<()>::error(
+        "未登录".to_string()
+    )))
+}
+
+// 新增：管理员创建机器人账号，机器人没有密码，只能通过访问令牌使用
+pub async fn create_bot(
+    req: HttpRequest,
+    body: web::Json<CreateBotRequest>,
+) -> Result<HttpResponse> {
+    if let Some(admin_id) = crate::auth::permitted_user_id(&req, Permission::ManageIntegrations) {
+        let username = body.username.trim();
+        if username.is_empty() {
+            return Ok(HttpResponse::Ok().json(ApiResponse::<()>::error(
+                "用户名不能为空".to_string()
+            )));
+        }
+        
+        // 头像使用管理员已上传的图片生成
+        let bot_id = Uuid::new_v4().to_string();
+        let avatar = match &body.avatar_id {
+            Some(avatar_id) => match crate::images::avatar_from_attachment(&admin_id, avatar_id, &bot_id).await {
+                Ok(url) => Some(url),
+                Err(e) => return Ok(HttpResponse::Ok().json(ApiResponse::<()>::error(e))),
+            },
+            None => None,
+        };
+        
+        let mut state = APP_STATE.lock().unwrap();
+        if state.users.values().any(|u| u.username == username) {
+            drop(state);
+            if let Some(avatar) = &avatar {
+                crate::images::remove_avatar_file(&bot_id, avatar);
+            }
+            return Ok(HttpResponse::Ok().json(ApiResponse::<()>::error(
+                "该用户名已被使用".to_string()
+            )));
+        }
+        
+        let bot = User {
+            id: bot_id,
+            username: username.to_string(),
+            email: String::new(),
+            password_hash: String::new(),
+            avatar,
+            display_name: body.display_name.clone(),
+            role: ROLE_MEMBER.to_string(),
+            status: UserStatus::Active,
+            created_at: Utc::now(),
+            last_ips: Vec::new(),
+            muted_until: None,
+            mute_reason: None,
+            is_bot: true,
+            ban: None,
+        };
+        
+        state.users.insert(bot.id.clone(), bot.clone());
+        state.save_users();
+        
+        return Ok(HttpResponse::Ok().json(ApiResponse::success(bot)));
+    }
+    
+    Ok(HttpResponse::Unauthorized().json(ApiResponse::<()>::error(
+        "无权操作".to_string()
+    )))
+}
+
+// 新增：机器人列表
+pub async fn get_bots(req: HttpRequest) -> Result<HttpResponse> {
+    if let Some(token) = crate::auth::request_token(&req) {
+        let state = APP_STATE.lock().unwrap();
+        
+        if let Some(user_id) = state.token_user_id(&token) {
+            if crate::permissions::has_permission(&state, user_id, Permission::ManageIntegrations) {
+                let bots: Vec<&User> = state.users.values()
+                    .filter(|u| u.is_bot)
+                    .collect();
+                
+                return Ok(HttpResponse::Ok().json(ApiResponse::success(bots)));
+            }
+        }
+    }
+    
+    Ok(HttpResponse::Unauthorized().json(ApiResponse::<()>::error(
+        "无权访问".to_string()
+    )))
+}
+
+// 新增：创建个人访问令牌；管理员也可以为机器人创建
+pub async fn create_token(
+    req: HttpRequest,
+    body: web::Json<CreateTokenRequest>,
+) -> Result<HttpResponse> {
+    if let Some(token) = crate::auth::request_token(&req) {
+        let mut state = APP_STATE.lock().unwrap();
+        
+        if let Some(user_id) = state.token_user_id(&token) {
+            let user_id = user_id.clone();
+            let can_manage_bots = crate::permissions::has_permission(&state, &user_id, Permission::ManageIntegrations);
+            
+            let name = body.name.trim();
+            if name.is_empty() || name.chars().count() > 64 {
+                return Ok(HttpResponse::Ok().json(ApiResponse::<()>::error(
+                    "令牌名称不能为空且不超过64个字符".to_string()
+                )));
+            }
+            
+            if body.scopes.is_empty() {
+                return Ok(HttpResponse::Ok().json(ApiResponse::<()>::error(
+                    "请至少选择一项权限".to_string()
+                )));
+            }
+            
+            // 只能为自己创建，拥有 manage_integrations 权限的用户可以为机器人创建
+            let owner_id = body.user_id.clone().unwrap_or_else(|| user_id.clone());
+            let owner = match state.users.get(&owner_id) {
+                Some(owner) if owner_id == user_id || (can_manage_bots && owner.is_bot) => owner,
+                _ => {
+                    return Ok(HttpResponse::Ok().json(ApiResponse::<()>::error(
+                        "只能为自己或机器人账号创建令牌".to_string()
+                    )));
+                }
+            };
+            
+            if body.scopes.contains(&TokenScope::Moderate) && !crate::permissions::can_moderate(&state, &owner.id) {
+                return Ok(HttpResponse::Ok().json(ApiResponse::<()>::error(
+                    "普通成员的令牌不能包含管理权限".to_string()
+                )));
+            }
+            
+            let expires_at = match body.expires_in_days {
+                None => None,
+                Some(days) if (1..=365).contains(&days) => Some(Utc::now() + Duration::days(days)),
+                Some(_) => {
+                    return Ok(HttpResponse::Ok().json(ApiResponse::<()>::error(
+                        "有效期必须在1到365天之间".to_string()
+                    )));
+                }
+            };
+            
+            let plain_token = crate::auth::generate_token();
+            let api_token = ApiToken {
+                id: Uuid::new_v4().to_string(),
+                user_id: owner_id,
+                name: name.to_string(),
+                token_hash: crate::auth::hash_token(&plain_token),
+                scopes: body.scopes.clone(),
+                created_by: user_id,
+                created_at: Utc::now(),
+                expires_at,
+                last_used_at: None,
+                revoked_at: None,
+            };
+            
+            let info = ApiTokenInfo::from(&api_token);
+            state.api_tokens.insert(api_token.id.clone(), api_token);
+            state.save_api_tokens();
+            
+            return Ok(HttpResponse::Ok().json(ApiResponse::success(CreateTokenResponse {
+                token: plain_token,
+                info,
+            })));
+        }
+    }
+    
+    Ok(HttpResponse::Unauthorized().json(ApiResponse::<()>::error(
+        "未登录".to_string()
+    )))
+}
+
+// 新增：令牌列表，拥有 manage_users 权限的用户可以看到所有令牌
+pub async fn get_tokens(req: HttpRequest) -> Result<HttpResponse> {
+    if let Some(token) = crate::auth::request_token(&req) {
+        let state = APP_STATE.lock().unwrap();
+        
+        if let Some(user_id) = state.token_user_id(&token) {
+            if state.users.contains_key(user_id) {
+                let can_manage = crate::permissions::has_permission(&state, user_id, Permission::ManageUsers);
+                let mut tokens: Vec<ApiTokenInfo> = state.api_tokens.values()
+                    .filter(|t| can_manage || &t.user_id == user_id)
+                    .map(ApiTokenInfo::from)
+                    .collect();
+                tokens.sort_by_key(|t| std::cmp::Reverse(t.created_at));
+                
+                return Ok(HttpResponse::Ok().json(ApiResponse::success(tokens)));
+            }
+        }
+    }
+    
+    Ok(HttpResponse::Unauthorized().json(ApiResponse::<()>::error(
+        "未登录".to_string()
+    )))
+}
+
+// 新增：撤销令牌，本人或拥有 manage_users 权限的用户可以操作
+pub async fn revoke_token(
+    req: HttpRequest,
+    body: web::Json<RevokeTokenRequest>,
+) -> Result<HttpResponse> {
+    if let Some(token) = crate::auth::request_token(&req) {
+        let mut state = APP_STATE.lock().unwrap();
+        
+        if let Some(user_id) = state.token_user_id(&token) {
+            let user_id = user_id.clone();
+            let can_manage = crate::permissions::has_permission(&state, &user_id, Permission::ManageUsers);
+            
+            match state.api_tokens.get_mut(&body.token_id) {
+                Some(api_token) if api_token.user_id == user_id || can_manage => {
+                    if api_token.revoked_at.is_some() {
+                        return Ok(HttpResponse::Ok().json(ApiResponse::<()>::error(
+                            "令牌已撤销".to_string()
+                        )));
+                    }
+                    api_token.revoked_at = Some(Utc::now());
+                    state.save_api_tokens();
+                    
+                    return Ok(HttpResponse::Ok().json(ApiResponse::success("令牌已撤销")));
+                }
+                _ => {
+                    return Ok(HttpResponse::Ok().json(ApiResponse::<()>::error(
+                        "令牌不存在".to_string()
+                    )));
+                }
+            }
+        }
+    }
+    
     Ok(HttpResponse::Unauthorized().json(ApiResponse::<()>::error(
         "未登录".to_string()
     )))
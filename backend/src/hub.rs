@@ -0,0 +1,268 @@
+// 事件分发中心：每个连接有一个有界的发送队列，由该连接自己的写任务按顺序取出发送，
+// 广播时只需把事件放入各个队列，不再为每个连接每个事件创建任务
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::sync::{Arc, Mutex};
+use lazy_static::lazy_static;
+use tokio::sync::Notify;
+use uuid::Uuid;
+use crate::event_log::{Audience, Replay};
+use crate::models::SlowConsumerPolicy;
+use crate::protocol::ServerEvent;
+
+// 每个连接最多积压的待发送消息数
+pub const OUTBOUND_QUEUE_CAPACITY: usize = 256;
+
+pub enum Outbound {
+    Text(Arc<str>),
+    Close,
+}
+
+struct OutboxState {
+    queue: VecDeque<Outbound>,
+    closed: bool,
+}
+
+pub struct Outbox {
+    state: Mutex<OutboxState>,
+    notify: Notify,
+}
+
+impl Outbox {
+    fn new() -> Self {
+        Outbox {
+            state: Mutex::new(OutboxState {
+                queue: VecDeque::with_capacity(OUTBOUND_QUEUE_CAPACITY),
+                closed: false,
+            }),
+            notify: Notify::new(),
+        }
+    }
+
+    // 队列已满时按策略处理：丢弃最早的消息，或关闭该连接
+    fn push(&self, item: Outbound, policy: &SlowConsumerPolicy) {
+        let mut state = self.state.lock().unwrap();
+        if state.closed {
+            return;
+        }
+        if state.queue.len() >= OUTBOUND_QUEUE_CAPACITY {
+            match policy {
+                SlowConsumerPolicy::DropOldest => {
+                    state.queue.pop_front();
+                }
+                SlowConsumerPolicy::Disconnect => {
+                    state.queue.clear();
+                    state.closed = true;
+                    drop(state);
+                    self.notify.notify_one();
+                    return;
+                }
+            }
+        }
+        state.queue.push_back(item);
+        drop(state);
+        self.notify.notify_one();
+    }
+
+    // 取出下一条待发送的消息；队列关闭且已取空时返回 None
+    pub async fn next(&self) -> Option<Outbound> {
+        loop {
+            {
+                let mut state = self.state.lock().unwrap();
+                if let Some(item) = state.queue.pop_front() {
+                    return Some(item);
+                }
+                if state.closed {
+                    return None;
+                }
+            }
+            self.notify.notified().await;
+        }
+    }
+
+    // 不等待，队列为空时返回 None
+    pub fn try_next(&self) -> Option<Outbound> {
+        self.state.lock().unwrap().queue.pop_front()
+    }
+
+    pub fn close(&self) {
+        self.state.lock().unwrap().closed = true;
+        self.notify.notify_one();
+    }
+
+    pub fn is_closed(&self) -> bool {
+        self.state.lock().unwrap().closed
+    }
+
+    fn len(&self) -> usize {
+        self.state.lock().unwrap().queue.len()
+    }
+}
+
+#[derive(Clone)]
+pub struct Connection {
+    pub id: String,
+    pub user_id: String,
+    pub outbox: Arc<Outbox>,
+}
+
+// 同一用户可以同时打开多个标签页或设备，每个连接单独编号
+struct Hub {
+    by_id: HashMap<String, Connection>, // connection_id -> connection
+    by_user: HashMap<String, HashSet<String>>, // user_id -> connection_ids
+    policy: SlowConsumerPolicy,
+}
+
+impl Hub {
+    fn of_user<'a>(&'a self, user_id: &str) -> impl Iterator<Item = &'a Connection> + 'a {
+        self.by_user
+            .get(user_id)
+            .into_iter()
+            .flatten()
+            .filter_map(move |id| self.by_id.get(id))
+    }
+}
+
+lazy_static! {
+    static ref HUB: Arc<Mutex<Hub>> = Arc::new(Mutex::new(Hub {
+        by_id: HashMap::new(),
+        by_user: HashMap::new(),
+        policy: SlowConsumerPolicy::default(),
+    }));
+}
+
+pub fn set_policy(policy: SlowConsumerPolicy) {
+    HUB.lock().unwrap().policy = policy;
+}
+
+pub fn register(user_id: &str) -> Connection {
+    let connection = Connection {
+        id: Uuid::new_v4().to_string(),
+        user_id: user_id.to_string(),
+        outbox: Arc::new(Outbox::new()),
+    };
+
+    let mut hub = HUB.lock().unwrap();
+    hub.by_user
+        .entry(user_id.to_string())
+        .or_default()
+        .insert(connection.id.clone());
+    hub.by_id.insert(connection.id.clone(), connection.clone());
+
+    connection
+}
+
+// 移除连接并更新在线状态；重复移除时只处理一次
+pub fn unregister(connection: &Connection) {
+    let removed = {
+        let mut hub = HUB.lock().unwrap();
+        if let Some(connection) = hub.by_id.remove(&connection.id) {
+            connection.outbox.close();
+            if let Some(ids) = hub.by_user.get_mut(&connection.user_id) {
+                ids.remove(&connection.id);
+                if ids.is_empty() {
+                    hub.by_user.remove(&connection.user_id);
+                }
+            }
+            true
+        } else {
+            false
+        }
+    };
+
+    if removed {
+        crate::presence::disconnected(&connection.user_id);
+    }
+}
+
+// 先持有连接表的锁再分配序号，保证各连接收到的顺序与序号一致
+pub fn publish(event: &ServerEvent, audience: Audience) {
+    let hub = HUB.lock().unwrap();
+    let msg: Arc<str> = crate::event_log::record(event, audience.clone()).into();
+
+    match &audience {
+        Audience::All => {
+            for connection in hub.by_id.values() {
+                connection.outbox.push(Outbound::Text(msg.clone()), &hub.policy);
+            }
+        }
+        Audience::User(user_id) => {
+            for connection in hub.of_user(user_id) {
+                connection.outbox.push(Outbound::Text(msg.clone()), &hub.policy);
+            }
+        }
+        Audience::Users(user_ids) => {
+            for connection in user_ids.iter().flat_map(|user_id| hub.of_user(user_id)) {
+                connection.outbox.push(Outbound::Text(msg.clone()), &hub.policy);
+            }
+        }
+    }
+}
+
+// 不分配序号的临时事件，发给除 excluded_user_id 以外的所有连接
+pub fn publish_transient(msg: String, excluded_user_id: &str) {
+    let hub = HUB.lock().unwrap();
+    let msg: Arc<str> = msg.into();
+
+    for connection in hub.by_id.values() {
+        if connection.user_id != excluded_user_id {
+            connection.outbox.push(Outbound::Text(msg.clone()), &hub.policy);
+        }
+    }
+}
+
+// 不分配序号的临时事件，只发给指定用户的所有连接
+pub fn publish_transient_to_user(msg: String, user_id: &str) {
+    let hub = HUB.lock().unwrap();
+    let msg: Arc<str> = msg.into();
+
+    for connection in hub.of_user(user_id) {
+        connection.outbox.push(Outbound::Text(msg.clone()), &hub.policy);
+    }
+}
+
+// 只发给指定连接，用于握手、回复和补发
+pub fn send_to_connection(connection_id: &str, msg: String) {
+    let hub = HUB.lock().unwrap();
+    if let Some(connection) = hub.by_id.get(connection_id) {
+        connection.outbox.push(Outbound::Text(msg.into()), &hub.policy);
+    }
+}
+
+// 在已排队的消息发送完后关闭该用户的所有连接
+pub fn close_user(user_id: &str) {
+    let hub = HUB.lock().unwrap();
+    for connection in hub.of_user(user_id) {
+        connection.outbox.push(Outbound::Close, &hub.policy);
+    }
+}
+
+// 补发断线期间错过的事件；补发与实时推送可能重叠，客户端按 seq 去重
+// 查询日志和放入队列期间一直持有连接表的锁，实时事件只会排在补发的事件之后
+pub fn resume(connection: &Connection, last_seq: u64, epoch: Option<&str>) -> serde_json::Value {
+    let hub = HUB.lock().unwrap();
+    let target = match hub.by_id.get(&connection.id) {
+        Some(target) => target,
+        None => return serde_json::json!({ "replayed": 0, "resync_required": false }),
+    };
+
+    match crate::event_log::replay_since(epoch, last_seq, &connection.user_id) {
+        // 队列放不下全部补发事件时，部分补发会被挤掉，直接要求全量同步
+        Replay::Events(events)
+            if target.outbox.len() + events.len() <= OUTBOUND_QUEUE_CAPACITY =>
+        {
+            let replayed = events.len();
+            for msg in events {
+                target.outbox.push(Outbound::Text(msg.into()), &hub.policy);
+            }
+            serde_json::json!({ "replayed": replayed, "resync_required": false })
+        }
+        _ => {
+            let event = ServerEvent::ResyncRequired {
+                epoch: crate::event_log::epoch().to_string(),
+                last_seq: crate::event_log::last_seq(),
+            };
+            target.outbox.push(Outbound::Text(event.to_text().into()), &hub.policy);
+            serde_json::json!({ "replayed": 0, "resync_required": true })
+        }
+    }
+}
\ No newline at end of file
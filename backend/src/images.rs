@@ -0,0 +1,231 @@
+use image::codecs::jpeg::JpegEncoder;
+use image::imageops::FilterType;
+use image::{DynamicImage, ImageDecoder, ImageFormat, ImageReader, Limits};
+use std::fs::{self, File};
+use std::io::{BufWriter, Cursor};
+use actix_web::web;
+use uuid::Uuid;
+use crate::models::{Attachment, AttachmentStatus, Thumbnail};
+
+// 上传图片存储目录，通过 /uploads 对外提供访问
+pub const UPLOAD_DIR: &str = "data/uploads";
+pub const UPLOAD_URL_PREFIX: &str = "/uploads";
+
+// 单张图片最大 10MB
+pub const MAX_IMAGE_BYTES: usize = 10 * 1024 * 1024;
+// 头像最大 2MB，裁剪为正方形后缩放到固定尺寸
+pub const MAX_AVATAR_BYTES: usize = 2 * 1024 * 1024;
+const AVATAR_SIZE: u32 = 256;
+// 解码时的最大宽高，防止解压炸弹
+const MAX_IMAGE_DIMENSION: u32 = 8192;
+// 缩略图尺寸（最长边）
+const THUMBNAIL_SIZES: [u32; 3] = [128, 320, 800];
+const JPEG_QUALITY: u8 = 85;
+
+pub struct ProcessedImage {
+    pub url: String,
+    pub content_type: String,
+    pub width: u32,
+    pub height: u32,
+    pub thumbnails: Vec<Thumbnail>,
+}
+
+// 根据文件头判断图片格式，只接受常见的几种
+pub fn sniff_format(data: &[u8]) -> Option<ImageFormat> {
+    match image::guess_format(data) {
+        Ok(format @ (ImageFormat::Jpeg | ImageFormat::Png | ImageFormat::Gif | ImageFormat::WebP)) => Some(format),
+        _ => None,
+    }
+}
+
+// 解码图片并按EXIF方向信息旋转，之后重新编码时不再携带任何元数据
+pub fn decode_image(data: &[u8]) -> Result<DynamicImage, String> {
+    let mut limits = Limits::default();
+    limits.max_image_width = Some(MAX_IMAGE_DIMENSION);
+    limits.max_image_height = Some(MAX_IMAGE_DIMENSION);
+
+    let mut reader = ImageReader::new(Cursor::new(data))
+        .with_guessed_format()
+        .map_err(|e| e.to_string())?;
+    reader.limits(limits);
+
+    let mut decoder = reader.into_decoder().map_err(|e| e.to_string())?;
+    let orientation = decoder.orientation().map_err(|e| e.to_string())?;
+    let mut img = DynamicImage::from_decoder(decoder).map_err(|e| e.to_string())?;
+    img.apply_orientation(orientation);
+
+    Ok(img)
+}
+
+// 照片类图片保存为JPEG，其余（可能带透明通道）保存为PNG
+fn output_format(source: ImageFormat) -> ImageFormat {
+    if source == ImageFormat::Jpeg {
+        ImageFormat::Jpeg
+    } else {
+        ImageFormat::Png
+    }
+}
+
+pub fn save_image(img: &DynamicImage, format: ImageFormat, path: &str) -> Result<(), String> {
+    match format {
+        ImageFormat::Jpeg => {
+            let file = File::create(path).map_err(|e| e.to_string())?;
+            let mut encoder = JpegEncoder::new_with_quality(BufWriter::new(file), JPEG_QUALITY);
+            encoder.encode_image(&img.to_rgb8()).map_err(|e| e.to_string())
+        }
+        _ => img.save_with_format(path, format).map_err(|e| e.to_string()),
+    }
+}
+
+// 解码、去除EXIF并生成缩略图，文件名以附件ID为前缀
+pub fn process_upload(attachment_id: &str, data: &[u8]) -> Result<ProcessedImage, String> {
+    let source_format = sniff_format(data).ok_or_else(|| "不支持的图片格式".to_string())?;
+    let img = decode_image(data)?;
+    let format = output_format(source_format);
+    let ext = format.extensions_str()[0];
+
+    fs::create_dir_all(UPLOAD_DIR).map_err(|e| e.to_string())?;
+
+    let file_name = format!("{}.{}", attachment_id, ext);
+    save_image(&img, format, &format!("{}/{}", UPLOAD_DIR, file_name))?;
+
+    let mut thumbnails = Vec::new();
+    for size in THUMBNAIL_SIZES {
+        // 原图已经足够小时不再生成更大的缩略图
+        if img.width().max(img.height()) <= size {
+            break;
+        }
+        let thumb = img.thumbnail(size, size);
+        let thumb_name = format!("{}_{}.{}", attachment_id, size, ext);
+        save_image(&thumb, format, &format!("{}/{}", UPLOAD_DIR, thumb_name))?;
+        thumbnails.push(Thumbnail {
+            size,
+            url: format!("{}/{}", UPLOAD_URL_PREFIX, thumb_name),
+            width: thumb.width(),
+            height: thumb.height(),
+        });
+    }
+
+    Ok(ProcessedImage {
+        url: format!("{}/{}", UPLOAD_URL_PREFIX, file_name),
+        content_type: format.to_mime_type().to_string(),
+        width: img.width(),
+        height: img.height(),
+        thumbnails,
+    })
+}
+
+// 新增：处理头像，居中裁剪为正方形并缩放，返回访问URL
+pub fn process_avatar(user_id: &str, data: &[u8]) -> Result<String, String> {
+    let source_format = sniff_format(data).ok_or_else(|| "不支持的图片格式".to_string())?;
+    let mut img = decode_image(data)?;
+    let format = output_format(source_format);
+
+    let side = img.width().min(img.height());
+    let x = (img.width() - side) / 2;
+    let y = (img.height() - side) / 2;
+    let avatar = img
+        .crop(x, y, side, side)
+        .resize_exact(AVATAR_SIZE, AVATAR_SIZE, FilterType::Lanczos3);
+
+    let dir = format!("{}/avatars", UPLOAD_DIR);
+    fs::create_dir_all(&dir).map_err(|e| e.to_string())?;
+
+    // 每次使用新文件名，避免客户端缓存旧头像
+    let file_name = format!("{}_{}.{}", user_id, Uuid::new_v4().simple(), format.extensions_str()[0]);
+    save_image(&avatar, format, &format!("{}/{}", dir, file_name))?;
+
+    Ok(format!("{}/avatars/{}", UPLOAD_URL_PREFIX, file_name))
+}
+
+// 新增：用已处理完成的图片附件生成 user_id 的头像，附件必须由 uploader_id 上传；返回头像URL
+pub async fn avatar_from_attachment(uploader_id: &str, attachment_id: &str, user_id: &str) -> Result<String, String> {
+    let path = {
+        let state = crate::APP_STATE.lock().unwrap();
+        state.attachments.get(attachment_id)
+            .filter(|a| a.uploader_id == uploader_id && a.status == AttachmentStatus::Ready)
+            .and_then(|a| a.url.as_deref())
+            .and_then(|url| url.strip_prefix(&format!("{}/", UPLOAD_URL_PREFIX)))
+            .map(|file_name| format!("{}/{}", UPLOAD_DIR, file_name))
+    };
+    let path = path.ok_or_else(|| "头像图片不存在或尚未处理完成".to_string())?;
+
+    let user_id = user_id.to_string();
+    let result = web::block(move || {
+        let data = fs::read(&path).map_err(|e| e.to_string())?;
+        process_avatar(&user_id, &data)
+    })
+    .await;
+    match result {
+        Ok(Ok(url)) => Ok(url),
+        Ok(Err(e)) => {
+            eprintln!("处理头像失败: {}", e);
+            Err("无法识别的图片".to_string())
+        }
+        Err(_) => Err("头像处理失败，请稍后再试".to_string()),
+    }
+}
+
+// 删除本地存储的旧头像，只处理属于该用户的文件，外部链接不处理
+pub fn remove_avatar_file(user_id: &str, url: &str) {
+    if let Some(file_name) = url.strip_prefix(&format!("{}/avatars/", UPLOAD_URL_PREFIX)) {
+        if file_name.starts_with(&format!("{}_", user_id))
+            && !file_name.contains('/')
+            && !file_name.contains("..")
+        {
+            fs::remove_file(format!("{}/avatars/{}", UPLOAD_DIR, file_name)).ok();
+        }
+    }
+}
+
+// 删除附件的原图和缩略图
+pub fn remove_attachment_files(attachment: &Attachment) {
+    let urls = attachment.url.iter().chain(attachment.thumbnails.iter().map(|t| &t.url));
+    for url in urls {
+        if let Some(file_name) = url.strip_prefix(&format!("{}/", UPLOAD_URL_PREFIX)) {
+            if !file_name.contains('/') && !file_name.contains("..") {
+                fs::remove_file(format!("{}/{}", UPLOAD_DIR, file_name)).ok();
+            }
+        }
+    }
+}
+
+// 在后台线程处理上传的图片，完成后更新附件状态并通知客户端
+pub fn spawn_processing(attachment_id: String, data: Vec<u8>) {
+    actix_web::rt::spawn(async move {
+        let id = attachment_id.clone();
+        let result = web::block(move || process_upload(&id, &data))
+            .await
+            .map_err(|e| e.to_string())
+            .and_then(|r| r);
+
+        let mut state = crate::APP_STATE.lock().unwrap();
+        let updated = if let Some(attachment) = state.attachments.get_mut(&attachment_id) {
+            match result {
+                Ok(processed) => {
+                    attachment.status = AttachmentStatus::Ready;
+                    attachment.url = Some(processed.url);
+                    attachment.width = Some(processed.width);
+                    attachment.height = Some(processed.height);
+                    attachment.content_type = processed.content_type;
+                    attachment.thumbnails = processed.thumbnails;
+                }
+                Err(e) => {
+                    eprintln!("处理图片失败 {}: {}", attachment_id, e);
+                    attachment.status = AttachmentStatus::Failed;
+                }
+            }
+            Some(attachment.clone())
+        } else {
+            None
+        };
+
+        if let Some(attachment) = updated {
+            state.save_attachments();
+            // 处理完成前已随消息发出时，所有人都需要更新
+            let sent = state.messages.iter().any(|m| m.attachment_ids.contains(&attachment.id));
+            drop(state);
+            crate::websocket::send_attachment_updated(&attachment, sent);
+        }
+    });
+}
\ No newline at end of file
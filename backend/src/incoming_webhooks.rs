@@ -0,0 +1,504 @@
+// 传入 Webhook：构建服务器、监控告警等外部系统通过 POST 向聊天室发送消息
+use std::collections::{HashMap, VecDeque};
+use std::sync::Mutex;
+use actix_web::{web, HttpMessage, HttpRequest, HttpResponse, Result};
+use chrono::{DateTime, Duration, Utc};
+use lazy_static::lazy_static;
+use regex::Regex;
+use serde::Deserialize;
+use uuid::Uuid;
+use crate::APP_STATE;
+use crate::models::*;
+
+pub const MAX_WEBHOOK_PAYLOAD_BYTES: usize = 64 * 1024;
+// 单条消息的最大长度（字符数）
+const MAX_WEBHOOK_MESSAGE_CHARS: usize = 4000;
+const DEFAULT_RATE_LIMIT_PER_MINUTE: u32 = 30;
+const MAX_RATE_LIMIT_PER_MINUTE: u32 = 600;
+
+lazy_static! {
+    // webhook_id -> 最近一分钟内的请求时间
+    static ref RECENT_REQUESTS: Mutex<HashMap<String, VecDeque<DateTime<Utc>>>> =
+        Mutex::new(HashMap::new());
+    static ref SLACK_LINK_REGEX: Regex = Regex::new(r"<([^<>|]+)\|([^<>]+)>").unwrap();
+    static ref SLACK_URL_REGEX: Regex = Regex::new(r"<((?:https?|mailto):[^<>]+)>").unwrap();
+    static ref SLACK_SPECIAL_MENTION_REGEX: Regex = Regex::new(r"<!(channel|here|everyone)>").unwrap();
+}
+
+// 兼容纯文本 {"text": "..."} 以及 Slack 的 attachments / blocks 格式
+#[derive(Deserialize)]
+struct WebhookPayload {
+    text: Option<String>,
+    content: Option<String>,
+    #[serde(default)]
+    attachments: Vec<SlackAttachment>,
+    #[serde(default)]
+    blocks: Vec<SlackBlock>,
+}
+
+#[derive(Deserialize)]
+struct SlackAttachment {
+    fallback: Option<String>,
+    pretext: Option<String>,
+    title: Option<String>,
+    title_link: Option<String>,
+    text: Option<String>,
+    #[serde(default)]
+    fields: Vec<SlackField>,
+}
+
+#[derive(Deserialize)]
+struct SlackField {
+    title: Option<String>,
+    value: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct SlackBlock {
+    #[serde(rename = "type")]
+    kind: String,
+    text: Option<SlackText>,
+    #[serde(default)]
+    fields: Vec<SlackText>,
+    #[serde(default)]
+    elements: Vec<serde_json::Value>,
+}
+
+#[derive(Deserialize)]
+struct SlackText {
+    text: String,
+}
+
+// 将 Slack 的 mrkdwn 链接和转义转换为 Markdown
+fn convert_mrkdwn(text: &str) -> String {
+    let text = SLACK_SPECIAL_MENTION_REGEX.replace_all(text, "@all");
+    let text = SLACK_LINK_REGEX.replace_all(&text, "[$2]($1)");
+    let text = SLACK_URL_REGEX.replace_all(&text, "$1");
+    text.replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&amp;", "&")
+}
+
+fn render_blocks(blocks: &[SlackBlock]) -> Vec<String> {
+    let mut lines = Vec::new();
+    for block in blocks {
+        match block.kind.as_str() {
+            "header" => {
+                if let Some(text) = &block.text {
+                    lines.push(format!("**{}**", text.text));
+                }
+            }
+            "section" => {
+                if let Some(text) = &block.text {
+                    lines.push(text.text.clone());
+                }
+                for field in &block.fields {
+                    lines.push(field.text.clone());
+                }
+            }
+            "context" => {
+                let texts: Vec<&str> = block.elements.iter()
+                    .filter_map(|e| e.get("text").and_then(|t| t.as_str()))
+                    .collect();
+                if !texts.is_empty() {
+                    lines.push(texts.join(" "));
+                }
+            }
+            "divider" => lines.push("---".to_string()),
+            _ => {}
+        }
+    }
+    lines
+}
+
+fn render_attachment(attachment: &SlackAttachment) -> Vec<String> {
+    let mut lines = Vec::new();
+    if let Some(pretext) = &attachment.pretext {
+        lines.push(pretext.clone());
+    }
+    match (&attachment.title, &attachment.title_link) {
+        (Some(title), Some(link)) => lines.push(format!("**[{}]({})**", title, link)),
+        (Some(title), None) => lines.push(format!("**{}**", title)),
+        _ => {}
+    }
+    if let Some(text) = &attachment.text {
+        lines.push(text.clone());
+    }
+    for field in &attachment.fields {
+        match (&field.title, &field.value) {
+            (Some(title), Some(value)) => lines.push(format!("**{}**: {}", title, value)),
+            (None, Some(value)) => lines.push(value.clone()),
+            (Some(title), None) => lines.push(format!("**{}**", title)),
+            (None, None) => {}
+        }
+    }
+    // 没有其他内容时使用 fallback
+    if lines.is_empty() {
+        if let Some(fallback) = &attachment.fallback {
+            lines.push(fallback.clone());
+        }
+    }
+    lines
+}
+
+// 解析请求内容为消息文本：text/plain 直接作为消息，其余按 JSON 解析
+fn parse_payload(req: &HttpRequest, body: &[u8]) -> Result<String, String> {
+    let body = std::str::from_utf8(body).map_err(|_| "请求内容不是有效的 UTF-8".to_string())?;
+
+    if req.content_type() == "text/plain" {
+        return Ok(body.to_string());
+    }
+
+    let payload: WebhookPayload = serde_json::from_str(body)
+        .map_err(|e| format!("无法解析的请求内容：{}", e))?;
+
+    // Slack 中有 blocks 时 text 只作为通知的后备内容
+    let mut lines = if !payload.blocks.is_empty() {
+        render_blocks(&payload.blocks)
+    } else {
+        payload.text.or(payload.content).into_iter().collect()
+    };
+    for attachment in &payload.attachments {
+        lines.extend(render_attachment(attachment));
+    }
+
+    Ok(convert_mrkdwn(&lines.join("\n")))
+}
+
+// 滑动窗口限流，返回本次请求是否允许
+fn allow_request(webhook_id: &str, limit_per_minute: u32) -> bool {
+    let now = Utc::now();
+    let mut recent = RECENT_REQUESTS.lock().unwrap();
+    let requests = recent.entry(webhook_id.to_string()).or_default();
+    while requests.front().map(|t| now.signed_duration_since(*t) >= Duration::minutes(1)).unwrap_or(false) {
+        requests.pop_front();
+    }
+    if requests.len() >= limit_per_minute as usize {
+        return false;
+    }
+    requests.push_back(now);
+    true
+}
+
+fn webhook_url(req: &HttpRequest, webhook_id: &str, secret: &str) -> String {
+    let info = req.connection_info();
+    format!("{}://{}/api/hooks/{}/{}", info.scheme(), info.host(), webhook_id, secret)
+}
+
+fn validate_rate_limit(rate_limit: Option<u32>) -> Result<u32, String> {
+    match rate_limit {
+        None => Ok(DEFAULT_RATE_LIMIT_PER_MINUTE),
+        Some(limit) if (1..=MAX_RATE_LIMIT_PER_MINUTE).contains(&limit) => Ok(limit),
+        Some(_) => Err(format!("频率限制必须在1到{}次/分钟之间", MAX_RATE_LIMIT_PER_MINUTE)),
+    }
+}
+
+// 外部系统调用的地址：POST /api/hooks/{webhook_id}/{secret}
+pub async fn receive_webhook(
+    req: HttpRequest,
+    path: web::Path<(String, String)>,
+    body: web::Bytes,
+) -> Result<HttpResponse> {
+    let (webhook_id, secret) = path.into_inner();
+
+    let webhook = {
+        let state = APP_STATE.lock().unwrap();
+        let secret_hash = crate::auth::hash_token(&secret);
+        state.incoming_webhooks.get(&webhook_id)
+            .filter(|w| w.token_hash == secret_hash)
+            .map(|w| (w.user_id.clone(), w.rate_limit_per_minute))
+    };
+    let (user_id, rate_limit) = match webhook {
+        Some(webhook) => webhook,
+        None => {
+            return Ok(HttpResponse::NotFound().json(ApiResponse::<()>::error(
+                "Webhook 不存在或密钥错误".to_string()
+            )));
+        }
+    };
+
+    if !allow_request(&webhook_id, rate_limit) {
+        return Ok(HttpResponse::TooManyRequests().json(ApiResponse::<()>::error(
+            "请求过于频繁，请稍后再试".to_string()
+        )));
+    }
+
+    let content = match parse_payload(&req, &body) {
+        Ok(content) => content,
+        Err(e) => return Ok(HttpResponse::BadRequest().json(ApiResponse::<()>::error(e))),
+    };
+    if content.trim().is_empty() {
+        return Ok(HttpResponse::BadRequest().json(ApiResponse::<()>::error(
+            "消息内容不能为空".to_string()
+        )));
+    }
+    if content.chars().count() > MAX_WEBHOOK_MESSAGE_CHARS {
+        return Ok(HttpResponse::BadRequest().json(ApiResponse::<()>::error(
+            format!("消息内容不能超过{}个字符", MAX_WEBHOOK_MESSAGE_CHARS)
+        )));
+    }
+
+    // 与普通消息走同一条路径：禁言检查、@解析、保存并广播
+    match crate::chat::send_message(&user_id, &content, &[]) {
+        Ok(message) => {
+            let mut state = APP_STATE.lock().unwrap();
+            if let Some(webhook) = state.incoming_webhooks.get_mut(&webhook_id) {
+                webhook.last_used_at = Some(Utc::now());
+            }
+            state.save_incoming_webhooks();
+
+            Ok(HttpResponse::Ok().json(ApiResponse::success(serde_json::json!({
+                "message_id": message.id
+            }))))
+        }
+        Err(e) => Ok(HttpResponse::BadRequest().json(ApiResponse::<()>::error(e))),
+    }
+}
+
+pub async fn create_webhook(
+    req: HttpRequest,
+    body: web::Json<CreateWebhookRequest>,
+) -> Result<HttpResponse> {
+    if let Some(admin_id) = crate::auth::permitted_user_id(&req, Permission::ManageIntegrations) {
+        let name = body.name.trim();
+        if name.is_empty() || name.chars().count() > 64 {
+            return Ok(HttpResponse::Ok().json(ApiResponse::<()>::error(
+                "名称不能为空且不超过64个字符".to_string()
+            )));
+        }
+        let rate_limit_per_minute = match validate_rate_limit(body.rate_limit_per_minute) {
+            Ok(limit) => limit,
+            Err(e) => return Ok(HttpResponse::Ok().json(ApiResponse::<()>::error(e))),
+        };
+
+        let webhook_id = Uuid::new_v4().to_string();
+        let secret = crate::auth::generate_token();
+        let bot_id = Uuid::new_v4().to_string();
+
+        // 头像使用管理员已上传的图片生成，归属于机器人账号
+        let avatar = match &body.avatar_id {
+            Some(avatar_id) => match crate::images::avatar_from_attachment(&admin_id, avatar_id, &bot_id).await {
+                Ok(url) => Some(url),
+                Err(e) => return Ok(HttpResponse::Ok().json(ApiResponse::<()>::error(e))),
+            },
+            None => None,
+        };
+
+        // Webhook 发送的消息归属于一个专用的机器人账号
+        let bot = User {
+            id: bot_id,
+            username: format!("webhook_{}", &webhook_id[..8]),
+            email: String::new(),
+            password_hash: String::new(),
+            avatar: avatar.clone(),
+            display_name: Some(name.to_string()),
+            role: ROLE_MEMBER.to_string(),
+            status: UserStatus::Active,
+            created_at: Utc::now(),
+            last_ips: Vec::new(),
+            muted_until: None,
+            mute_reason: None,
+            is_bot: true,
+            ban: None,
+        };
+
+        let webhook = IncomingWebhook {
+            id: webhook_id.clone(),
+            name: name.to_string(),
+            avatar,
+            user_id: bot.id.clone(),
+            token_hash: crate::auth::hash_token(&secret),
+            rate_limit_per_minute,
+            created_by: admin_id,
+            created_at: Utc::now(),
+            last_used_at: None,
+        };
+        let info = IncomingWebhookInfo::from(&webhook);
+
+        let mut state = APP_STATE.lock().unwrap();
+        state.users.insert(bot.id.clone(), bot);
+        state.incoming_webhooks.insert(webhook_id.clone(), webhook);
+        state.save_users();
+        state.save_incoming_webhooks();
+        drop(state);
+
+        return Ok(HttpResponse::Ok().json(ApiResponse::success(WebhookUrlResponse {
+            url: webhook_url(&req, &webhook_id, &secret),
+            info,
+        })));
+    }
+
+    Ok(HttpResponse::Unauthorized().json(ApiResponse::<()>::error(
+        "无权操作".to_string()
+    )))
+}
+
+pub async fn get_webhooks(req: HttpRequest) -> Result<HttpResponse> {
+    if crate::auth::permitted_user_id(&req, Permission::ManageIntegrations).is_some() {
+        let state = APP_STATE.lock().unwrap();
+        let mut webhooks: Vec<IncomingWebhookInfo> = state.incoming_webhooks.values()
+            .map(IncomingWebhookInfo::from)
+            .collect();
+        webhooks.sort_by_key(|w| w.created_at);
+
+        return Ok(HttpResponse::Ok().json(ApiResponse::success(webhooks)));
+    }
+
+    Ok(HttpResponse::Unauthorized().json(ApiResponse::<()>::error(
+        "无权访问".to_string()
+    )))
+}
+
+pub async fn update_webhook(
+    req: HttpRequest,
+    body: web::Json<UpdateWebhookRequest>,
+) -> Result<HttpResponse> {
+    if let Some(admin_id) = crate::auth::permitted_user_id(&req, Permission::ManageIntegrations) {
+        if let Some(name) = &body.name {
+            if name.trim().is_empty() || name.trim().chars().count() > 64 {
+                return Ok(HttpResponse::Ok().json(ApiResponse::<()>::error(
+                    "名称不能为空且不超过64个字符".to_string()
+                )));
+            }
+        }
+        if body.rate_limit_per_minute.is_some() {
+            if let Err(e) = validate_rate_limit(body.rate_limit_per_minute) {
+                return Ok(HttpResponse::Ok().json(ApiResponse::<()>::error(e)));
+            }
+        }
+
+        let bot_id = APP_STATE.lock().unwrap().incoming_webhooks.get(&body.webhook_id).map(|w| w.user_id.clone());
+        let bot_id = match bot_id {
+            Some(bot_id) => bot_id,
+            None => {
+                return Ok(HttpResponse::Ok().json(ApiResponse::<()>::error(
+                    "Webhook 不存在".to_string()
+                )));
+            }
+        };
+        let new_avatar = match &body.avatar_id {
+            Some(avatar_id) => match crate::images::avatar_from_attachment(&admin_id, avatar_id, &bot_id).await {
+                Ok(url) => Some(url),
+                Err(e) => return Ok(HttpResponse::Ok().json(ApiResponse::<()>::error(e))),
+            },
+            None => None,
+        };
+
+        let mut state = APP_STATE.lock().unwrap();
+        let webhook = match state.incoming_webhooks.get_mut(&body.webhook_id) {
+            Some(webhook) => webhook,
+            None => {
+                drop(state);
+                if let Some(avatar) = &new_avatar {
+                    crate::images::remove_avatar_file(&bot_id, avatar);
+                }
+                return Ok(HttpResponse::Ok().json(ApiResponse::<()>::error(
+                    "Webhook 不存在".to_string()
+                )));
+            }
+        };
+
+        if let Some(name) = &body.name {
+            webhook.name = name.trim().to_string();
+        }
+        if new_avatar.is_some() {
+            webhook.avatar = new_avatar.clone();
+        }
+        if let Some(limit) = body.rate_limit_per_minute {
+            webhook.rate_limit_per_minute = limit;
+        }
+        let info = IncomingWebhookInfo::from(&*webhook);
+
+        // 同步到对应的机器人账号
+        let mut name_change = None;
+        let mut old_avatar = None;
+        if let Some(bot) = state.users.get_mut(&info.user_id) {
+            let new_name = Some(info.name.clone());
+            if bot.display_name != new_name {
+                name_change = Some(bot.display_name.clone());
+                bot.display_name = new_name;
+            }
+            if new_avatar.is_some() {
+                old_avatar = std::mem::replace(&mut bot.avatar, new_avatar.clone());
+            }
+        }
+        state.save_users();
+        state.save_incoming_webhooks();
+        drop(state);
+
+        if let Some(old_name) = name_change {
+            crate::websocket::broadcast_display_name_changed(&info.user_id, &old_name, &Some(info.name.clone()));
+        }
+        if new_avatar.is_some() {
+            if let Some(old_avatar) = old_avatar {
+                crate::images::remove_avatar_file(&info.user_id, &old_avatar);
+            }
+            crate::websocket::broadcast_avatar_changed(&info.user_id, &new_avatar);
+        }
+
+        return Ok(HttpResponse::Ok().json(ApiResponse::success(info)));
+    }
+
+    Ok(HttpResponse::Unauthorized().json(ApiResponse::<()>::error(
+        "无权操作".to_string()
+    )))
+}
+
+// 重新生成密钥，旧地址立即失效
+pub async fn reset_webhook_secret(
+    req: HttpRequest,
+    body: web::Json<WebhookIdRequest>,
+) -> Result<HttpResponse> {
+    if crate::auth::permitted_user_id(&req, Permission::ManageIntegrations).is_some() {
+        let secret = crate::auth::generate_token();
+
+        let mut state = APP_STATE.lock().unwrap();
+        let info = match state.incoming_webhooks.get_mut(&body.webhook_id) {
+            Some(webhook) => {
+                webhook.token_hash = crate::auth::hash_token(&secret);
+                IncomingWebhookInfo::from(&*webhook)
+            }
+            None => {
+                return Ok(HttpResponse::Ok().json(ApiResponse::<()>::error(
+                    "Webhook 不存在".to_string()
+                )));
+            }
+        };
+        state.save_incoming_webhooks();
+        drop(state);
+
+        return Ok(HttpResponse::Ok().json(ApiResponse::success(WebhookUrlResponse {
+            url: webhook_url(&req, &info.id, &secret),
+            info,
+        })));
+    }
+
+    Ok(HttpResponse::Unauthorized().json(ApiResponse::<()>::error(
+        "无权操作".to_string()
+    )))
+}
+
+// 删除 Webhook；机器人账号保留，以便历史消息仍能显示发送者
+pub async fn delete_webhook(
+    req: HttpRequest,
+    body: web::Json<WebhookIdRequest>,
+) -> Result<HttpResponse> {
+    if crate::auth::permitted_user_id(&req, Permission::ManageIntegrations).is_some() {
+        let mut state = APP_STATE.lock().unwrap();
+        if state.incoming_webhooks.remove(&body.webhook_id).is_none() {
+            return Ok(HttpResponse::Ok().json(ApiResponse::<()>::error(
+                "Webhook 不存在".to_string()
+            )));
+        }
+        state.save_incoming_webhooks();
+        drop(state);
+
+        RECENT_REQUESTS.lock().unwrap().remove(&body.webhook_id);
+
+        return Ok(HttpResponse::Ok().json(ApiResponse::success("Webhook 已删除")));
+    }
+
+    Ok(HttpResponse::Unauthorized().json(ApiResponse::<()>::error(
+        "无权操作".to_string()
+    )))
+}
\ No newline at end of file
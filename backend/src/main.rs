@@ -8,6 +8,24 @@ use lazy_static::lazy_static;
 mod models;
 mod handlers;
 mod websocket;
+mod images;
+mod mentions;
+mod presence;
+mod chat;
+mod event_log;
+mod hub;
+mod protocol;
+mod realtime;
+mod auth;
+mod incoming_webhooks;
+mod outgoing_webhooks;
+mod commands;
+mod audit;
+mod blacklist;
+mod moderation;
+mod antispam;
+mod reports;
+mod permissions;
 
 use models::*;
 use handlers::*;
@@ -23,9 +41,17 @@ async fn main() -> std::io::Result<()> {
     
     // 确保数据目录存在
     fs::create_dir_all("data").unwrap_or_default();
+    fs::create_dir_all(images::UPLOAD_DIR).unwrap_or_default();
     
     // 加载现有数据
     APP_STATE.lock().unwrap().load_data();
+    hub::set_policy(APP_STATE.lock().unwrap().settings.slow_consumer_policy.clone());
+    
+    // 定期将长时间无操作的用户标记为离开
+    actix_web::rt::spawn(presence::run_idle_checker());
+    actix_web::rt::spawn(outgoing_webhooks::run_delivery_worker());
+    actix_web::rt::spawn(chat::run_expiry_worker());
+    actix_web::rt::spawn(realtime::run_poll_reaper());
 
     HttpServer::new(|| {
         let cors = Cors::default()
@@ -39,6 +65,8 @@ async fn main() -> std::io::Result<()> {
             .wrap(middleware::Logger::default())
             .service(
                 web::scope("/api")
+                    // 校验 Authorization: Bearer 访问令牌及其权限范围
+                    .wrap(middleware::from_fn(auth::token_guard))
                     .route("/public-settings", web::get().to(get_public_settings))
                     .route("/register", web::post().to(register))
                     .route("/login", web::post().to(login))
@@ -55,21 +83,89 @@ async fn main() -> std::io::Result<()> {
                     .route("/update-settings", web::post().to(update_settings))
                     .route("/messages", web::get().to(get_messages))
                     .route("/send-message", web::post().to(send_message))
+                    .service(
+                        web::resource("/upload-image")
+                            .app_data(web::PayloadConfig::new(images::MAX_IMAGE_BYTES))
+                            .route(web::post().to(upload_image))
+                    )
                     .route("/recall-message", web::post().to(recall_message))
+                    .route("/react-message", web::post().to(react_message))
                     .route("/set-deputy-admin", web::post().to(set_deputy_admin))
                     .route("/mute-user", web::post().to(mute_user))
                     .route("/unmute-user", web::post().to(unmute_user))
                     .route("/ban-user", web::post().to(ban_user))
+                    .route("/unban-user", web::post().to(unban_user))
+                    .route("/purge-user-messages", web::post().to(purge_user_messages))
+                    .route("/set-user-role", web::post().to(set_user_role))
+                    .route("/roles", web::get().to(permissions::get_roles))
+                    .route("/create-role", web::post().to(permissions::create_role))
+                    .route("/update-role", web::post().to(permissions::update_role))
+                    .route("/delete-role", web::post().to(permissions::delete_role))
                     .route("/current-user", web::get().to(get_current_user))
                     .route("/mention-checks", web::get().to(get_mention_checks))
+                    .route("/unread-mentions", web::get().to(get_unread_mentions))
                     .route("/mark-mentions-checked", web::post().to(mark_mentions_checked))
+                    .route("/mark-read", web::post().to(mark_read))
+                    .route("/online-users", web::get().to(get_online_users))
                     .route("/delete-account", web::post().to(delete_account))
                     .route("/update-display-name", web::post().to(update_display_name))
                     .route("/update-user-display-name", web::post().to(update_user_display_name))
+                    .service(
+                        web::resource("/upload-avatar")
+                            .app_data(web::PayloadConfig::new(images::MAX_AVATAR_BYTES))
+                            .route(web::post().to(upload_avatar))
+                    )
+                    .route("/bots", web::get().to(get_bots))
+                    .route("/create-bot", web::post().to(create_bot))
+                    .route("/tokens", web::get().to(get_tokens))
+                    .route("/create-token", web::post().to(create_token))
+                    .route("/revoke-token", web::post().to(revoke_token))
+                    .route("/webhooks", web::get().to(incoming_webhooks::get_webhooks))
+                    .route("/create-webhook", web::post().to(incoming_webhooks::create_webhook))
+                    .route("/update-webhook", web::post().to(incoming_webhooks::update_webhook))
+                    .route("/reset-webhook-secret", web::post().to(incoming_webhooks::reset_webhook_secret))
+                    .route("/delete-webhook", web::post().to(incoming_webhooks::delete_webhook))
+                    .route("/outgoing-webhooks", web::get().to(outgoing_webhooks::get_outgoing_webhooks))
+                    .route("/create-outgoing-webhook", web::post().to(outgoing_webhooks::create_outgoing_webhook))
+                    .route("/update-outgoing-webhook", web::post().to(outgoing_webhooks::update_outgoing_webhook))
+                    .route("/reset-outgoing-webhook-secret", web::post().to(outgoing_webhooks::reset_outgoing_webhook_secret))
+                    .route("/delete-outgoing-webhook", web::post().to(outgoing_webhooks::delete_outgoing_webhook))
+                    .route("/webhook-deliveries", web::get().to(outgoing_webhooks::get_webhook_deliveries))
+                    .route("/redeliver-webhook", web::post().to(outgoing_webhooks::redeliver_webhook))
+                    .service(
+                        web::resource("/hooks/{webhook_id}/{secret}")
+                            .app_data(web::PayloadConfig::new(incoming_webhooks::MAX_WEBHOOK_PAYLOAD_BYTES))
+                            .route(web::post().to(incoming_webhooks::receive_webhook))
+                    )
+                    .route("/commands", web::get().to(commands::get_commands))
+                    .route("/register-command", web::post().to(commands::register_command))
+                    .route("/unregister-command", web::post().to(commands::unregister_command))
+                    .route("/command-reply", web::post().to(commands::reply_to_command))
+                    .route("/blacklist", web::get().to(blacklist::get_blacklist))
+                    .route("/add-ip-blacklist", web::post().to(blacklist::add_ip_blacklist))
+                    .route("/remove-ip-blacklist", web::post().to(blacklist::remove_ip_blacklist))
+                    .route("/add-email-blacklist", web::post().to(blacklist::add_email_blacklist))
+                    .route("/remove-email-blacklist", web::post().to(blacklist::remove_email_blacklist))
+                    .route("/update-allowed-email-domains", web::post().to(blacklist::update_allowed_email_domains))
+                    .route("/moderation-config", web::get().to(moderation::get_moderation_config))
+                    .route("/add-filter-rule", web::post().to(moderation::add_filter_rule))
+                    .route("/delete-filter-rule", web::post().to(moderation::delete_filter_rule))
+                    .route("/update-link-policy", web::post().to(moderation::update_link_policy))
+                    .route("/moderation-flags", web::get().to(moderation::get_moderation_flags))
+                    .route("/review-moderation-flag", web::post().to(moderation::review_moderation_flag))
+                    .route("/report-message", web::post().to(reports::report_message))
+                    .route("/moderation-queue", web::get().to(reports::get_moderation_queue))
+                    .route("/resolve-report", web::post().to(reports::resolve_report))
+                    .route("/audit-log", web::get().to(audit::get_audit_log))
+                    .route("/export-audit-log", web::get().to(audit::export_audit_log))
                     .route("/ws", web::get().to(websocket_handler))
+                    .route("/ws-schema", web::get().to(get_ws_schema))
+                    .route("/events", web::get().to(realtime::event_stream))
+                    .route("/poll-events", web::get().to(realtime::poll_events))
             )
             // 静态文件服务
             .service(Files::new("/assets", "../frontend/dist/assets"))
+            .service(Files::new(images::UPLOAD_URL_PREFIX, images::UPLOAD_DIR))
             // 所有其他路由返回 index.html（支持前端路由）
             .default_service(web::get().to(serve_index))
     })
@@ -86,20 +182,34 @@ async fn serve_index() -> Result<HttpResponse> {
         .body(html))
 }
 
-async fn websocket_handler(req: HttpRequest, stream: web::Payload) -> Result<HttpResponse, Error> {
-    let cookie = req.cookie("session_token");
-    if let Some(cookie) = cookie {
-        let token = cookie.value();
+async fn websocket_handler(
+    req: HttpRequest,
+    stream: web::Payload,
+    query: web::Query<WsConnectQuery>,
+) -> Result<HttpResponse, Error> {
+    if let Some(token) = auth::request_token(&req) {
         let state = APP_STATE.lock().unwrap();
-        if let Some(user_id) = state.sessions.get(token) {
+        if let Some(user_id) = state.token_user_id(&token) {
             let user_id = user_id.clone();
             drop(state);
+            let scopes = auth::request_scopes(&req);
+            
+            // 协商协议版本
+            let protocol_version = match protocol::negotiate(query.protocol.as_deref()) {
+                Some(version) => version,
+                None => {
+                    return Ok(HttpResponse::BadRequest().json(ApiResponse::<()>::error(format!(
+                        "不支持的协议版本，服务端支持：{:?}",
+                        protocol::supported_versions()
+                    ))));
+                }
+            };
             
             let (response, session, msg_stream) = actix_ws::handle(&req, stream)?;
             
             // 启动websocket处理
             actix_web::rt::spawn(async move {
-                handle_websocket(user_id, session, msg_stream).await;
+                handle_websocket(user_id, protocol_version, scopes, session, msg_stream).await;
             });
             
             return Ok(response);
@@ -0,0 +1,179 @@
+use std::collections::HashMap;
+use crate::models::{User, UserStatus};
+
+// 判断@名称之后是否为分隔位置（与前端不带 u 标志的 (?!\w) 保持一致，只把 ASCII 字母数字和下划线视为单词字符）
+fn is_boundary(rest: &str) -> bool {
+    rest.chars()
+        .next()
+        .map(|c| !(c.is_ascii_alphanumeric() || c == '_'))
+        .unwrap_or(true)
+}
+
+// 解析消息中的@提及，返回被提及的用户ID（不包含发送者本人）
+// 支持三种写法：@[user_id:昵称]、@用户名/@群昵称、@all（需要 mention_all 权限）
+pub fn parse_mentions(content: &str, author: &User, can_mention_all: bool, users: &HashMap<String, User>) -> Vec<String> {
+    let mut mentioned: Vec<String> = Vec::new();
+    let mut push = |id: &str| {
+        if id != author.id && !mentioned.iter().any(|m| m == id) {
+            mentioned.push(id.to_string());
+        }
+    };
+
+    // 按名称长度从长到短匹配，避免“张三”抢先匹配到“张三丰”
+    let mut names: Vec<(&str, &str)> = Vec::new();
+    for user in users.values().filter(|u| u.status == UserStatus::Active) {
+        names.push((user.username.as_str(), user.id.as_str()));
+        if let Some(display_name) = &user.display_name {
+            if !display_name.is_empty() {
+                names.push((display_name.as_str(), user.id.as_str()));
+            }
+        }
+    }
+    names.sort_by_key(|(name, _)| std::cmp::Reverse(name.len()));
+
+    for (index, _) in content.match_indices('@') {
+        let rest = &content[index + 1..];
+
+        // 特殊格式 @[user_id:display_name]
+        if let Some(inner) = rest.strip_prefix('[') {
+            if let Some(end) = inner.find(']') {
+                if let Some((id, _)) = inner[..end].split_once(':') {
+                    if users.get(id).map(|u| u.status == UserStatus::Active).unwrap_or(false) {
+                        push(id);
+                        continue;
+                    }
+                }
+            }
+        }
+
+        if let Some(after) = rest.strip_prefix("all") {
+            if is_boundary(after) && can_mention_all {
+                for user in users.values().filter(|u| u.status == UserStatus::Active) {
+                    push(&user.id);
+                }
+                continue;
+            }
+        }
+
+        if let Some((_, id)) = names.iter().find(|(name, _)| {
+            rest.strip_prefix(name).map(is_boundary).unwrap_or(false)
+        }) {
+            push(id);
+        }
+    }
+
+    mentioned
+}
+
+// 解析命令参数开头的用户，返回用户ID和剩余参数
+// 支持 @[user_id:昵称]、@用户名、@群昵称，@ 可省略；名称中不能有空格
+// 被封禁的用户也能匹配，以便解除封禁；审核中的用户不匹配
+pub fn resolve_user<'a>(args: &'a str, users: &HashMap<String, User>) -> Option<(String, &'a str)> {
+    let args = args.trim_start();
+    let target = args.strip_prefix('@').unwrap_or(args);
+
+    if let Some(inner) = target.strip_prefix('[') {
+        let end = inner.find(']')?;
+        let (id, _) = inner[..end].split_once(':')?;
+        return users.get(id)
+            .filter(|u| u.status != UserStatus::Pending)
+            .map(|u| (u.id.clone(), &inner[end + 1..]));
+    }
+
+    let end = target.find(char::is_whitespace).unwrap_or(target.len());
+    let name = &target[..end];
+    if name.is_empty() {
+        return None;
+    }
+    // 用户名优先于群昵称
+    let candidates = || users.values().filter(|u| u.status != UserStatus::Pending);
+    candidates().find(|u| u.username == name)
+        .or_else(|| candidates().find(|u| u.display_name.as_deref() == Some(name)))
+        .map(|u| (u.id.clone(), &target[end..]))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Utc;
+
+    fn user(id: &str, username: &str, display_name: Option<&str>) -> User {
+        User {
+            id: id.to_string(),
+            username: username.to_string(),
+            email: String::new(),
+            password_hash: String::new(),
+            avatar: None,
+            display_name: display_name.map(str::to_string),
+            role: crate::models::ROLE_MEMBER.to_string(),
+            status: UserStatus::Active,
+            created_at: Utc::now(),
+            last_ips: Vec::new(),
+            muted_until: None,
+            mute_reason: None,
+            is_bot: false,
+            ban: None,
+        }
+    }
+
+    fn users(list: Vec<User>) -> HashMap<String, User> {
+        list.into_iter().map(|u| (u.id.clone(), u)).collect()
+    }
+
+    #[test]
+    fn cjk_name_followed_by_cjk_text() {
+        let author = user("a", "author", None);
+        let users = users(vec![author.clone(), user("z", "zhangsan", Some("张三"))]);
+
+        assert_eq!(parse_mentions("@张三你好", &author, false, &users), vec!["z"]);
+        assert_eq!(parse_mentions("@张三，在吗", &author, false, &users), vec!["z"]);
+    }
+
+    #[test]
+    fn ascii_name_requires_boundary() {
+        let author = user("a", "author", None);
+        let users = users(vec![author.clone(), user("b", "bob", None)]);
+
+        assert_eq!(parse_mentions("@bob hi", &author, false, &users), vec!["b"]);
+        assert_eq!(parse_mentions("@bob你好", &author, false, &users), vec!["b"]);
+        assert!(parse_mentions("@bobby hi", &author, false, &users).is_empty());
+        assert!(parse_mentions("@bob_x hi", &author, false, &users).is_empty());
+    }
+
+    #[test]
+    fn explicit_id_format() {
+        let author = user("a", "author", None);
+        let mut pending = user("p", "pending", None);
+        pending.status = UserStatus::Pending;
+        let users = users(vec![author.clone(), user("b", "bob", Some("鲍勃")), pending]);
+
+        assert_eq!(parse_mentions("@[b:随便写的名字] 看这里", &author, false, &users), vec!["b"]);
+        assert!(parse_mentions("@[p:pending]", &author, false, &users).is_empty());
+        assert!(parse_mentions("@[missing:bob]", &author, false, &users).is_empty());
+    }
+
+    #[test]
+    fn longest_name_wins() {
+        let author = user("a", "author", None);
+        let users = users(vec![
+            author.clone(),
+            user("s", "zs", Some("张三")),
+            user("f", "zsf", Some("张三丰")),
+        ]);
+
+        assert_eq!(parse_mentions("@张三丰 好久不见", &author, false, &users), vec!["f"]);
+        assert_eq!(parse_mentions("@张三 好久不见", &author, false, &users), vec!["s"]);
+    }
+
+    #[test]
+    fn mention_all_and_self() {
+        let author = user("a", "author", None);
+        let users = users(vec![author.clone(), user("b", "bob", None), user("c", "carol", None)]);
+
+        assert!(parse_mentions("@all 开会", &author, false, &users).is_empty());
+        let mut all = parse_mentions("@all 开会", &author, true, &users);
+        all.sort();
+        assert_eq!(all, vec!["b", "c"]);
+        assert!(parse_mentions("@author", &author, false, &users).is_empty());
+    }
+}
\ No newline at end of file
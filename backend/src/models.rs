@@ -1,9 +1,13 @@
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use schemars::JsonSchema;
+use std::collections::{BTreeMap, HashMap};
 use chrono::{DateTime, Utc};
 use std::fs;
+use std::io::Write;
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+const AUDIT_LOG_FILE: &str = "data/audit_log.jsonl";
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 pub struct User {
     pub id: String,
     pub username: String,
@@ -11,26 +15,72 @@ pub struct User {
     pub password_hash: String,
     pub avatar: Option<String>,
     pub display_name: Option<String>, // 新增：群聊昵称
-    pub role: UserRole,
+    pub role: String, // 角色ID，内置角色见 ROLE_ADMIN 等
     pub status: UserStatus,
     pub created_at: DateTime<Utc>,
     pub last_ips: Vec<String>,
     pub muted_until: Option<DateTime<Utc>>,
+    #[serde(default)]
+    pub mute_reason: Option<String>, // 新增：禁言原因
+    #[serde(default)]
+    pub is_bot: bool, // 新增：机器人账号，只能通过访问令牌使用
+    #[serde(default)]
+    pub ban: Option<BanInfo>, // 新增：封禁信息，status 为 Banned 时有效
+}
+
+// 新增：封禁信息
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct BanInfo {
+    pub reason: Option<String>,
+    pub banned_by: String,
+    pub banned_at: DateTime<Utc>,
+    pub until: Option<DateTime<Utc>>, // None 表示永久封禁
 }
 
 #[derive(Debug, Deserialize)]
 pub struct UnmuteUserRequest {
     pub user_id: String,
+    #[serde(default)]
+    pub reason: Option<String>, // 新增：记入审计日志
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
-pub enum UserRole {
-    Admin,
-    DeputyAdmin,
-    Member,
+// 内置角色的ID，与旧版 UserRole 枚举的序列化结果相同，已有的用户数据无需迁移
+pub const ROLE_ADMIN: &str = "Admin";
+pub const ROLE_DEPUTY_ADMIN: &str = "DeputyAdmin";
+pub const ROLE_MEMBER: &str = "Member";
+
+// 新增：细分的操作权限，角色由一组权限组成
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum Permission {
+    RecallAny,          // 撤回他人的消息、批量清理消息
+    Mute,               // 禁言、解除禁言
+    Ban,                // 封禁、解除封禁
+    Approve,            // 审核注册申请
+    ManageUsers,        // 查看用户列表、添加和删除用户、管理他人的访问令牌
+    EditNicknames,      // 修改他人的昵称
+    ManageSettings,     // 修改聊天室设置
+    PinMessages,        // 置顶消息、设置话题
+    MentionAll,         // 使用 @all
+    ReviewReports,      // 处理举报和过滤命中记录
+    ManageModeration,   // 管理过滤规则、链接策略和黑名单
+    ViewAuditLog,       // 查看和导出审计日志
+    ManageRoles,        // 管理角色、修改用户的角色
+    ManageIntegrations, // 管理机器人、Webhook 和机器人命令
+    BypassSlowMode,     // 不受慢速模式和自动禁言限制
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+// 新增：角色；rank 越大等级越高，只能处置等级低于自己的用户（管理员除外）
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct Role {
+    pub id: String,
+    pub name: String,
+    pub rank: u32,
+    pub permissions: Vec<Permission>, // 管理员角色始终拥有全部权限，此项不生效
+    pub built_in: bool, // 内置角色不能删除
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, JsonSchema)]
 pub enum UserStatus {
     Pending,
     Active,
@@ -41,12 +91,31 @@ pub enum UserStatus {
 pub struct Settings {
     pub registration_open: bool,
     pub require_approval: bool, // 新增：注册是否需要审核
+    #[serde(default)]
+    pub slow_consumer_policy: SlowConsumerPolicy, // 新增：连接发送队列积压时的处理方式
+    #[serde(default)]
+    pub topic: Option<String>, // 新增：聊天室话题
+    #[serde(default)]
+    pub pinned_message_ids: Vec<String>, // 新增：置顶消息
+    #[serde(default)]
+    pub slow_mode_seconds: u32, // 新增：慢速模式，普通成员每 N 秒只能发一条消息，0 为关闭
+}
+
+// 新增：慢消费者策略
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+pub enum SlowConsumerPolicy {
+    #[default]
+    DropOldest,
+    Disconnect,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct PublicSettings {
     pub registration_open: bool,
     pub require_approval: bool, // 新增：公开设置中也包含此字段
+    pub topic: Option<String>,
+    pub pinned_message_ids: Vec<String>,
+    pub slow_mode_seconds: u32,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -65,9 +134,20 @@ pub struct VerificationCode {
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct IpBlacklist {
-    pub ip: String,
+    pub ip: String, // 单个 IP 或 CIDR 网段
     pub reason: String,
-    pub until: DateTime<Utc>,
+    pub until: Option<DateTime<Utc>>, // None 表示永久
+    #[serde(default)]
+    pub added_by: Option<String>, // 新增：None 表示系统自动添加
+}
+
+// 新增：邮箱黑名单，pattern 为完整邮箱或 *@域名、*@*.域名
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EmailBlacklist {
+    pub pattern: String,
+    pub reason: String,
+    pub until: Option<DateTime<Utc>>,
+    pub added_by: Option<String>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -76,6 +156,36 @@ pub struct VerificationCodeAttempt {
     pub timestamp: DateTime<Utc>,
 }
 
+// 新增：图片附件
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, JsonSchema)]
+pub enum AttachmentStatus {
+    Processing,
+    Ready,
+    Failed,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct Thumbnail {
+    pub size: u32,
+    pub url: String,
+    pub width: u32,
+    pub height: u32,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct Attachment {
+    pub id: String,
+    pub uploader_id: String,
+    pub content_type: String,
+    pub status: AttachmentStatus,
+    pub url: Option<String>,
+    pub width: Option<u32>,
+    pub height: Option<u32>,
+    pub size_bytes: u64,
+    pub thumbnails: Vec<Thumbnail>,
+    pub created_at: DateTime<Utc>,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct MentionCheck {
     pub user_id: String,
@@ -83,6 +193,264 @@ pub struct MentionCheck {
     pub last_updated: DateTime<Utc>,
 }
 
+// 新增：访问令牌的权限范围
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub enum TokenScope {
+    ReadMessages,
+    SendMessages,
+    Moderate,
+}
+
+// 新增：个人访问令牌，只保存令牌的哈希值
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ApiToken {
+    pub id: String,
+    pub user_id: String,
+    pub name: String,
+    pub token_hash: String,
+    pub scopes: Vec<TokenScope>,
+    pub created_by: String,
+    pub created_at: DateTime<Utc>,
+    pub expires_at: Option<DateTime<Utc>>,
+    pub last_used_at: Option<DateTime<Utc>>,
+    pub revoked_at: Option<DateTime<Utc>>,
+}
+
+impl ApiToken {
+    pub fn is_active(&self) -> bool {
+        self.revoked_at.is_none()
+            && self.expires_at.map(|t| t > Utc::now()).unwrap_or(true)
+    }
+}
+
+// 新增：传入 Webhook，每个 Webhook 对应一个机器人账号作为消息的发送者
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct IncomingWebhook {
+    pub id: String,
+    pub name: String,
+    pub avatar: Option<String>,
+    pub user_id: String,
+    pub token_hash: String,
+    pub rate_limit_per_minute: u32,
+    pub created_by: String,
+    pub created_at: DateTime<Utc>,
+    pub last_used_at: Option<DateTime<Utc>>,
+}
+
+// 新增：传出 Webhook 可订阅的事件
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum WebhookEvent {
+    NewMessage,
+    MessageRecalled,
+    UserApproved,
+    UserBanned,
+    UserUnbanned,
+    MessagesPurged,
+}
+
+// 新增：传出 Webhook，事件发生时向 url 发送带签名的 POST 请求
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OutgoingWebhook {
+    pub id: String,
+    pub name: String,
+    pub url: String,
+    pub secret: String, // 用于 HMAC 签名，需要保存明文
+    pub events: Vec<WebhookEvent>,
+    pub enabled: bool,
+    pub created_by: String,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum DeliveryStatus {
+    Pending,
+    Succeeded,
+    Failed, // 重试次数用尽
+}
+
+// 新增：一次投递，未完成的投递即为待发送队列，完成后保留作为投递日志
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WebhookDelivery {
+    pub id: String,
+    pub webhook_id: String,
+    pub event: WebhookEvent,
+    pub payload: String,
+    pub status: DeliveryStatus,
+    pub attempts: u32,
+    pub next_attempt_at: DateTime<Utc>,
+    pub created_at: DateTime<Utc>,
+    pub last_attempt_at: Option<DateTime<Utc>>,
+    pub last_status_code: Option<u16>,
+    pub last_error: Option<String>,
+}
+
+// 新增：机器人注册的斜杠命令，用户调用时以事件的形式发给机器人
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BotCommand {
+    pub name: String,
+    pub description: String,
+    pub usage: Option<String>,
+    pub bot_user_id: String,
+    pub created_at: DateTime<Utc>,
+}
+
+// 新增：内容过滤命中后的处理方式，按严重程度排序
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FilterAction {
+    Flag,   // 照常发送，记录下来供管理员复查
+    Mask,   // 命中的内容替换为 *
+    #[default]
+    Reject, // 拒绝发送
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FilterKind {
+    Word,  // 不区分大小写的关键词
+    Regex, // 正则表达式
+}
+
+// 新增：管理员配置的过滤规则
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FilterRule {
+    pub id: String,
+    pub kind: FilterKind,
+    pub pattern: String,
+    pub action: FilterAction,
+    pub created_by: String,
+    pub created_at: DateTime<Utc>,
+}
+
+// 新增：链接白名单；allowed_domains 为空时不限制链接
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct LinkPolicy {
+    pub allowed_domains: Vec<String>,
+    pub action: FilterAction,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ModerationConfig {
+    pub rules: Vec<FilterRule>,
+    pub link_policy: LinkPolicy,
+}
+
+// 新增：一次过滤命中
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FilterMatch {
+    pub filter: String,          // 过滤器名称
+    pub rule_id: Option<String>, // 命中的规则，链接等内置检查为 None
+    pub matched: String,
+    pub action: FilterAction,
+}
+
+// 新增：被过滤器命中的消息，供拥有 review_reports 权限的用户复查
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ModerationFlag {
+    pub id: String,
+    pub message_id: Option<String>, // 被拒绝的消息没有保存，为 None
+    pub user_id: String,
+    pub content: String, // 过滤前的原始内容
+    pub matches: Vec<FilterMatch>,
+    pub action: FilterAction, // 实际采取的处理方式
+    pub created_at: DateTime<Utc>,
+    pub reviewed_by: Option<String>,
+    pub reviewed_at: Option<DateTime<Utc>>,
+}
+
+// 新增：举报的处理方式
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ReportResolution {
+    Dismiss,
+    Recall,
+    MuteAuthor,
+    BanAuthor,
+}
+
+// 新增：成员对消息的举报
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MessageReport {
+    pub id: String,
+    pub message_id: String,
+    pub author_id: String,
+    pub content: String, // 举报时的消息内容，消息被删除后仍可查看
+    pub reporter_id: String,
+    pub reason: String,
+    pub created_at: DateTime<Utc>,
+    pub resolution: Option<ReportResolution>, // None 表示待处理
+    pub resolved_by: Option<String>,
+    pub resolved_at: Option<DateTime<Utc>>,
+}
+
+// 新增：批量清理消息的方式，delete 为彻底删除
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum PurgeMode {
+    Recall,
+    Delete,
+}
+
+// 新增：审计日志记录的操作
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AuditAction {
+    BanUser,
+    UnbanUser,
+    AddIpBlacklist,
+    RemoveIpBlacklist,
+    AddEmailBlacklist,
+    RemoveEmailBlacklist,
+    UpdateAllowedEmailDomains,
+    MuteUser,
+    UnmuteUser,
+    RecallMessage,
+    SetDeputyAdmin,
+    DeleteUser,
+    ApproveUser,
+    RejectUser,
+    UpdateSettings,
+    AddFilterRule,
+    DeleteFilterRule,
+    UpdateLinkPolicy,
+    ResolveReport,
+    PurgeMessages,
+    CreateRole,
+    UpdateRole,
+    DeleteRole,
+    SetUserRole,
+}
+
+// 新增：审计日志条目，只追加不修改；用户名在记录时保存，账号删除后仍可辨认
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AuditEntry {
+    pub id: String,
+    pub timestamp: DateTime<Utc>,
+    pub actor_id: String,
+    pub actor_name: String,
+    pub action: AuditAction,
+    pub target_id: Option<String>,
+    pub target_name: Option<String>,
+    pub params: serde_json::Value,
+    pub reason: Option<String>,
+}
+
+// 新增：用户已读位置
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ReadMarker {
+    pub user_id: String,
+    pub last_read_message_id: String,
+    pub last_read_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Serialize, JsonSchema)]
+pub struct UnreadSummary {
+    pub unread_count: usize,
+    pub unread_mention_count: usize,
+    pub last_read_message_id: Option<String>,
+}
+
 pub struct AppState {
     pub users: HashMap<String, User>,
     pub messages: Vec<Message>,
@@ -91,10 +459,23 @@ pub struct AppState {
     pub user_sessions: HashMap<String, Vec<Session>>, // user_id -> sessions
     pub verification_codes: HashMap<String, VerificationCode>,
     pub ip_blacklist: Vec<IpBlacklist>,
-    pub email_blacklist: Vec<String>,
+    pub email_blacklist: Vec<EmailBlacklist>,
+    pub allowed_email_domains: Vec<String>, // 新增：不为空时只允许这些域名的邮箱注册
     pub registration_attempts: HashMap<String, Vec<DateTime<Utc>>>, // ip -> attempts
     pub verification_attempts: HashMap<String, Vec<VerificationCodeAttempt>>, // ip -> attempts
     pub mention_checks: HashMap<String, MentionCheck>, // user_id -> mention check data
+    pub attachments: HashMap<String, Attachment>, // attachment_id -> attachment
+    pub read_markers: HashMap<String, ReadMarker>, // user_id -> read marker
+    pub api_tokens: HashMap<String, ApiToken>, // token_id -> token
+    pub incoming_webhooks: HashMap<String, IncomingWebhook>, // webhook_id -> webhook
+    pub outgoing_webhooks: HashMap<String, OutgoingWebhook>, // webhook_id -> webhook
+    pub webhook_deliveries: Vec<WebhookDelivery>,
+    pub bot_commands: HashMap<String, BotCommand>, // name -> command
+    pub audit_log: Vec<AuditEntry>, // 按时间顺序
+    pub moderation: ModerationConfig,
+    pub moderation_flags: Vec<ModerationFlag>, // 按时间顺序
+    pub reports: Vec<MessageReport>, // 按时间顺序
+    pub roles: Vec<Role>,
 }
 
 impl AppState {
@@ -105,15 +486,32 @@ impl AppState {
             settings: Settings {
                 registration_open: true,
                 require_approval: false, // 默认关闭审核
+                slow_consumer_policy: SlowConsumerPolicy::default(),
+                topic: None,
+                pinned_message_ids: Vec::new(),
+                slow_mode_seconds: 0,
             },
             sessions: HashMap::new(),
             user_sessions: HashMap::new(),
             verification_codes: HashMap::new(),
             ip_blacklist: Vec::new(),
             email_blacklist: Vec::new(),
+            allowed_email_domains: Vec::new(),
             registration_attempts: HashMap::new(),
             verification_attempts: HashMap::new(),
             mention_checks: HashMap::new(),
+            attachments: HashMap::new(),
+            read_markers: HashMap::new(),
+            api_tokens: HashMap::new(),
+            incoming_webhooks: HashMap::new(),
+            outgoing_webhooks: HashMap::new(),
+            bot_commands: HashMap::new(),
+            audit_log: Vec::new(),
+            webhook_deliveries: Vec::new(),
+            moderation: ModerationConfig::default(),
+            moderation_flags: Vec::new(),
+            reports: Vec::new(),
+            roles: crate::permissions::builtin_roles(),
         }
     }
 
@@ -164,7 +562,7 @@ impl AppState {
                     self.sessions.insert(session.token.clone(), session.user_id.clone());
                     self.user_sessions
                         .entry(session.user_id.clone())
-                        .or_insert_with(Vec::new)
+                        .or_default()
                         .push(session);
                 }
             }
@@ -172,9 +570,10 @@ impl AppState {
 
         // 加载黑名单
         if let Ok(data) = fs::read_to_string("data/blacklist.json") {
-            if let Ok(blacklist) = serde_json::from_str::<(Vec<IpBlacklist>, Vec<String>)>(&data) {
-                self.ip_blacklist = blacklist.0;
-                self.email_blacklist = blacklist.1;
+            if let Some((ip, email, allowed_domains)) = crate::blacklist::parse_saved(&data) {
+                self.ip_blacklist = ip;
+                self.email_blacklist = email;
+                self.allowed_email_domains = allowed_domains;
             }
         }
 
@@ -193,6 +592,110 @@ impl AppState {
                 }
             }
         }
+
+        // 加载图片附件
+        if let Ok(data) = fs::read_to_string("data/attachments.json") {
+            if let Ok(attachments) = serde_json::from_str::<Vec<Attachment>>(&data) {
+                for mut attachment in attachments {
+                    // 上次运行时未处理完的图片已无法恢复
+                    if attachment.status == AttachmentStatus::Processing {
+                        attachment.status = AttachmentStatus::Failed;
+                    }
+                    self.attachments.insert(attachment.id.clone(), attachment);
+                }
+            }
+        }
+
+        // 加载已读位置
+        if let Ok(data) = fs::read_to_string("data/read_markers.json") {
+            if let Ok(markers) = serde_json::from_str::<Vec<ReadMarker>>(&data) {
+                for marker in markers {
+                    self.read_markers.insert(marker.user_id.clone(), marker);
+                }
+            }
+        }
+
+        // 加载访问令牌
+        if let Ok(data) = fs::read_to_string("data/api_tokens.json") {
+            if let Ok(tokens) = serde_json::from_str::<Vec<ApiToken>>(&data) {
+                for token in tokens {
+                    self.api_tokens.insert(token.id.clone(), token);
+                }
+            }
+        }
+
+        // 加载传入 Webhook
+        if let Ok(data) = fs::read_to_string("data/incoming_webhooks.json") {
+            if let Ok(webhooks) = serde_json::from_str::<Vec<IncomingWebhook>>(&data) {
+                for webhook in webhooks {
+                    self.incoming_webhooks.insert(webhook.id.clone(), webhook);
+                }
+            }
+        }
+
+        // 加载传出 Webhook 及其投递记录
+        if let Ok(data) = fs::read_to_string("data/outgoing_webhooks.json") {
+            if let Ok(webhooks) = serde_json::from_str::<Vec<OutgoingWebhook>>(&data) {
+                for webhook in webhooks {
+                    self.outgoing_webhooks.insert(webhook.id.clone(), webhook);
+                }
+            }
+        }
+
+        if let Ok(data) = fs::read_to_string("data/webhook_deliveries.json") {
+            if let Ok(deliveries) = serde_json::from_str(&data) {
+                self.webhook_deliveries = deliveries;
+            }
+        }
+
+        // 加载审计日志，每行一条记录
+        if let Ok(data) = fs::read_to_string(AUDIT_LOG_FILE) {
+            self.audit_log = data.lines()
+                .filter_map(|line| serde_json::from_str(line).ok())
+                .collect();
+        }
+
+        // 加载内容过滤配置
+        if let Ok(data) = fs::read_to_string("data/moderation.json") {
+            if let Ok(moderation) = serde_json::from_str::<ModerationConfig>(&data) {
+                self.moderation = moderation;
+            }
+        }
+
+        // 加载过滤命中记录
+        if let Ok(data) = fs::read_to_string("data/moderation_flags.json") {
+            if let Ok(flags) = serde_json::from_str::<Vec<ModerationFlag>>(&data) {
+                self.moderation_flags = flags;
+            }
+        }
+
+        // 加载角色，缺少的内置角色使用默认设置
+        if let Ok(data) = fs::read_to_string("data/roles.json") {
+            if let Ok(roles) = serde_json::from_str::<Vec<Role>>(&data) {
+                self.roles = roles;
+            }
+        }
+        for role in crate::permissions::builtin_roles() {
+            if !self.roles.iter().any(|r| r.id == role.id) {
+                self.roles.push(role);
+            }
+        }
+
+        // 加载举报
+        if let Ok(data) = fs::read_to_string("data/reports.json") {
+            if let Ok(reports) = serde_json::from_str::<Vec<MessageReport>>(&data) {
+                self.reports = reports;
+            }
+        }
+
+        // 加载机器人命令
+        if let Ok(data) = fs::read_to_string("data/bot_commands.json") {
+            if let Ok(commands) = serde_json::from_str::<Vec<BotCommand>>(&data) {
+                for command in commands {
+                    self.bot_commands.insert(command.name.clone(), command);
+                }
+            }
+        }
     }
 
     pub fn save_users(&self) {
@@ -225,7 +728,7 @@ impl AppState {
     }
 
     pub fn save_blacklist(&self) {
-        let blacklist = (&self.ip_blacklist, &self.email_blacklist);
+        let blacklist = (&self.ip_blacklist, &self.email_blacklist, &self.allowed_email_domains);
         if let Ok(data) = serde_json::to_string_pretty(&blacklist) {
             fs::write("data/blacklist.json", data).ok();
         }
@@ -244,11 +747,166 @@ impl AppState {
         }
     }
 
+    pub fn save_attachments(&self) {
+        let attachments: Vec<&Attachment> = self.attachments.values().collect();
+        if let Ok(data) = serde_json::to_string_pretty(&attachments) {
+            fs::write("data/attachments.json", data).ok();
+        }
+    }
+
+    pub fn save_read_markers(&self) {
+        let markers: Vec<&ReadMarker> = self.read_markers.values().collect();
+        if let Ok(data) = serde_json::to_string_pretty(&markers) {
+            fs::write("data/read_markers.json", data).ok();
+        }
+    }
+
+    pub fn save_api_tokens(&self) {
+        let tokens: Vec<&ApiToken> = self.api_tokens.values().collect();
+        if let Ok(data) = serde_json::to_string_pretty(&tokens) {
+            fs::write("data/api_tokens.json", data).ok();
+        }
+    }
+
+    pub fn save_incoming_webhooks(&self) {
+        let webhooks: Vec<&IncomingWebhook> = self.incoming_webhooks.values().collect();
+        if let Ok(data) = serde_json::to_string_pretty(&webhooks) {
+            fs::write("data/incoming_webhooks.json", data).ok();
+        }
+    }
+
+    pub fn save_outgoing_webhooks(&self) {
+        let webhooks: Vec<&OutgoingWebhook> = self.outgoing_webhooks.values().collect();
+        if let Ok(data) = serde_json::to_string_pretty(&webhooks) {
+            fs::write("data/outgoing_webhooks.json", data).ok();
+        }
+    }
+
+    pub fn save_webhook_deliveries(&self) {
+        if let Ok(data) = serde_json::to_string_pretty(&self.webhook_deliveries) {
+            fs::write("data/webhook_deliveries.json", data).ok();
+        }
+    }
+
+    // 审计日志只追加写入，不重写已有内容
+    pub fn append_audit_entry(&mut self, entry: AuditEntry) {
+        if let Ok(line) = serde_json::to_string(&entry) {
+            if let Ok(mut file) = fs::OpenOptions::new().create(true).append(true).open(AUDIT_LOG_FILE) {
+                writeln!(file, "{}", line).ok();
+            }
+        }
+        self.audit_log.push(entry);
+    }
+
+    pub fn save_moderation(&self) {
+        if let Ok(data) = serde_json::to_string_pretty(&self.moderation) {
+            fs::write("data/moderation.json", data).ok();
+        }
+    }
+
+    pub fn save_moderation_flags(&self) {
+        if let Ok(data) = serde_json::to_string_pretty(&self.moderation_flags) {
+            fs::write("data/moderation_flags.json", data).ok();
+        }
+    }
+
+    pub fn save_roles(&self) {
+        if let Ok(data) = serde_json::to_string_pretty(&self.roles) {
+            fs::write("data/roles.json", data).ok();
+        }
+    }
+
+    pub fn save_reports(&self) {
+        if let Ok(data) = serde_json::to_string_pretty(&self.reports) {
+            fs::write("data/reports.json", data).ok();
+        }
+    }
+
+    pub fn save_bot_commands(&self) {
+        let commands: Vec<&BotCommand> = self.bot_commands.values().collect();
+        if let Ok(data) = serde_json::to_string_pretty(&commands) {
+            fs::write("data/bot_commands.json", data).ok();
+        }
+    }
+
+    // 新增：根据会话 cookie 或访问令牌查找用户；会话 cookie 只在会话中查找
+    pub fn token_user_id(&self, credential: &crate::auth::Credential) -> Option<&String> {
+        match credential {
+            crate::auth::Credential::Session(token) => self.sessions.get(token),
+            crate::auth::Credential::ApiToken(token) => {
+                let token_hash = crate::auth::hash_token(token);
+                self.api_tokens.values()
+                    .find(|t| t.token_hash == token_hash && t.is_active())
+                    .map(|t| &t.user_id)
+            }
+        }
+    }
+
+    // 将消息与发送者信息、附件信息组合
+    pub fn message_with_user(&self, message: &Message) -> MessageWithUser {
+        MessageWithUser {
+            id: message.id.clone(),
+            user_id: message.user_id.clone(),
+            content: message.content.clone(),
+            timestamp: message.timestamp,
+            recalled: message.recalled,
+            user: self.users.get(&message.user_id).cloned(),
+            original_content: message.original_content.clone(),
+            attachments: message.attachment_ids.iter()
+                .filter_map(|id| self.attachments.get(id).cloned())
+                .collect(),
+            mentions: message.mentions.clone(),
+            reactions: message.reactions.clone(),
+        }
+    }
+
+    // 新增：用户尚未查看的@消息ID（已撤回的不计入）
+    pub fn unread_mention_ids(&self, user_id: &str) -> Vec<String> {
+        let checked = self.mention_checks.get(user_id)
+            .map(|c| &c.checked_message_ids);
+        
+        self.messages.iter()
+            .filter(|msg| !msg.recalled && msg.mentions.iter().any(|id| id == user_id))
+            .filter(|msg| !checked.map(|ids| ids.contains(&msg.id)).unwrap_or(false))
+            .map(|msg| msg.id.clone())
+            .collect()
+    }
+
+    pub fn message_index(&self, message_id: &str) -> Option<usize> {
+        self.messages.iter().position(|msg| msg.id == message_id)
+    }
+
+    // 新增：统计未读消息（自己发送的和已撤回的不计入）
+    pub fn unread_summary(&self, user_id: &str) -> UnreadSummary {
+        let marker = self.read_markers.get(user_id);
+        let start = match marker.and_then(|m| self.message_index(&m.last_read_message_id)) {
+            Some(index) => index + 1,
+            // 从未标记过已读时，只统计注册之后的消息
+            None => {
+                let created_at = self.users.get(user_id).map(|u| u.created_at);
+                self.messages.iter()
+                    .position(|msg| created_at.map(|t| msg.timestamp > t).unwrap_or(true))
+                    .unwrap_or(self.messages.len())
+            }
+        };
+        
+        let unread_count = self.messages[start..].iter()
+            .filter(|msg| !msg.recalled && msg.user_id != user_id)
+            .count();
+        
+        UnreadSummary {
+            unread_count,
+            unread_mention_count: self.unread_mention_ids(user_id).len(),
+            last_read_message_id: marker.map(|m| m.last_read_message_id.clone()),
+        }
+    }
+
     pub fn clean_expired_data(&mut self) {
         let now = Utc::now();
         
         // 清理过期的IP黑名单
-        self.ip_blacklist.retain(|item| item.until > now);
+        self.ip_blacklist.retain(|item| item.until.map(|until| until > now).unwrap_or(true));
+        self.email_blacklist.retain(|item| item.until.map(|until| until > now).unwrap_or(true));
         
         // 清理过期的验证码
         self.verification_codes.retain(|_, code| {
@@ -295,6 +953,8 @@ pub struct SendVerificationCodeRequest {
 #[derive(Debug, Deserialize)]
 pub struct ApproveRejectRequest {
     pub user_id: String,
+    #[serde(default)]
+    pub reason: Option<String>, // 新增：记入审计日志
 }
 
 #[derive(Debug, Deserialize)]
@@ -305,42 +965,510 @@ pub struct AddUserRequest {
     pub avatar: Option<String>,
 }
 
+// 新增：黑名单管理
+#[derive(Debug, Serialize)]
+pub struct BlacklistResponse<'a> {
+    pub ip: &'a [IpBlacklist],
+    pub email: &'a [EmailBlacklist],
+    pub allowed_email_domains: &'a [String],
+}
+
+#[derive(Debug, Deserialize)]
+pub struct AddIpBlacklistRequest {
+    pub ip: String,
+    pub reason: String,
+    pub duration_minutes: Option<i64>, // 不填为永久
+}
+
+#[derive(Debug, Deserialize)]
+pub struct RemoveIpBlacklistRequest {
+    pub ip: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct AddEmailBlacklistRequest {
+    pub pattern: String,
+    pub reason: String,
+    pub duration_minutes: Option<i64>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct RemoveEmailBlacklistRequest {
+    pub pattern: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct UpdateAllowedEmailDomainsRequest {
+    pub domains: Vec<String>,
+}
+
 #[derive(Debug, Deserialize)]
 pub struct DeleteUserRequest {
     pub user_id: String,
+    #[serde(default)]
+    pub reason: Option<String>, // 新增：记入审计日志
+}
+
+// 新增：创建机器人账号
+#[derive(Debug, Deserialize)]
+pub struct CreateBotRequest {
+    pub username: String,
+    pub display_name: Option<String>,
+    pub avatar_id: Option<String>, // 通过 /upload-image 上传的图片ID
+}
+
+// 新增：创建访问令牌，user_id 为空时为自己创建，管理员可为机器人创建
+#[derive(Debug, Deserialize)]
+pub struct CreateTokenRequest {
+    pub name: String,
+    pub scopes: Vec<TokenScope>,
+    pub user_id: Option<String>,
+    pub expires_in_days: Option<i64>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct RevokeTokenRequest {
+    pub token_id: String,
+}
+
+// 新增：令牌列表中的信息，不包含令牌本身
+#[derive(Debug, Serialize)]
+pub struct ApiTokenInfo {
+    pub id: String,
+    pub user_id: String,
+    pub name: String,
+    pub scopes: Vec<TokenScope>,
+    pub created_by: String,
+    pub created_at: DateTime<Utc>,
+    pub expires_at: Option<DateTime<Utc>>,
+    pub last_used_at: Option<DateTime<Utc>>,
+    pub revoked_at: Option<DateTime<Utc>>,
+}
+
+impl From<&ApiToken> for ApiTokenInfo {
+    fn from(token: &ApiToken) -> Self {
+        ApiTokenInfo {
+            id: token.id.clone(),
+            user_id: token.user_id.clone(),
+            name: token.name.clone(),
+            scopes: token.scopes.clone(),
+            created_by: token.created_by.clone(),
+            created_at: token.created_at,
+            expires_at: token.expires_at,
+            last_used_at: token.last_used_at,
+            revoked_at: token.revoked_at,
+        }
+    }
+}
+
+// 新增：令牌明文只在创建时返回一次
+#[derive(Debug, Serialize)]
+pub struct CreateTokenResponse {
+    pub token: String,
+    pub info: ApiTokenInfo,
+}
+
+// 新增：创建传入 Webhook
+#[derive(Debug, Deserialize)]
+pub struct CreateWebhookRequest {
+    pub name: String,
+    pub avatar_id: Option<String>, // 通过 /upload-image 上传的图片ID
+    pub rate_limit_per_minute: Option<u32>,
+}
+
+// 新增：修改传入 Webhook，未提供的字段保持不变
+#[derive(Debug, Deserialize)]
+pub struct UpdateWebhookRequest {
+    pub webhook_id: String,
+    pub name: Option<String>,
+    pub avatar_id: Option<String>, // 通过 /upload-image 上传的图片ID
+    pub rate_limit_per_minute: Option<u32>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct WebhookIdRequest {
+    pub webhook_id: String,
+}
+
+// 新增：Webhook 列表中的信息，不包含密钥
+#[derive(Debug, Serialize)]
+pub struct IncomingWebhookInfo {
+    pub id: String,
+    pub name: String,
+    pub avatar: Option<String>,
+    pub user_id: String,
+    pub rate_limit_per_minute: u32,
+    pub created_by: String,
+    pub created_at: DateTime<Utc>,
+    pub last_used_at: Option<DateTime<Utc>>,
+}
+
+impl From<&IncomingWebhook> for IncomingWebhookInfo {
+    fn from(webhook: &IncomingWebhook) -> Self {
+        IncomingWebhookInfo {
+            id: webhook.id.clone(),
+            name: webhook.name.clone(),
+            avatar: webhook.avatar.clone(),
+            user_id: webhook.user_id.clone(),
+            rate_limit_per_minute: webhook.rate_limit_per_minute,
+            created_by: webhook.created_by.clone(),
+            created_at: webhook.created_at,
+            last_used_at: webhook.last_used_at,
+        }
+    }
+}
+
+// 新增：Webhook 地址包含密钥，只在创建或重置密钥时返回
+#[derive(Debug, Serialize)]
+pub struct WebhookUrlResponse {
+    pub url: String,
+    pub info: IncomingWebhookInfo,
+}
+
+// 新增：创建传出 Webhook
+#[derive(Debug, Deserialize)]
+pub struct CreateOutgoingWebhookRequest {
+    pub name: String,
+    pub url: String,
+    pub events: Vec<WebhookEvent>,
+}
+
+// 新增：修改传出 Webhook，未提供的字段保持不变
+#[derive(Debug, Deserialize)]
+pub struct UpdateOutgoingWebhookRequest {
+    pub webhook_id: String,
+    pub name: Option<String>,
+    pub url: Option<String>,
+    pub events: Option<Vec<WebhookEvent>>,
+    pub enabled: Option<bool>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct OutgoingWebhookInfo {
+    pub id: String,
+    pub name: String,
+    pub url: String,
+    pub events: Vec<WebhookEvent>,
+    pub enabled: bool,
+    pub created_by: String,
+    pub created_at: DateTime<Utc>,
+}
+
+impl From<&OutgoingWebhook> for OutgoingWebhookInfo {
+    fn from(webhook: &OutgoingWebhook) -> Self {
+        OutgoingWebhookInfo {
+            id: webhook.id.clone(),
+            name: webhook.name.clone(),
+            url: webhook.url.clone(),
+            events: webhook.events.clone(),
+            enabled: webhook.enabled,
+            created_by: webhook.created_by.clone(),
+            created_at: webhook.created_at,
+        }
+    }
+}
+
+// 新增：签名密钥只在创建或重置时返回
+#[derive(Debug, Serialize)]
+pub struct OutgoingWebhookSecretResponse {
+    pub secret: String,
+    pub info: OutgoingWebhookInfo,
+}
+
+// 新增：投递日志查询参数
+#[derive(Debug, Deserialize)]
+pub struct DeliveryLogQuery {
+    pub webhook_id: String,
+    pub limit: Option<usize>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct RedeliverRequest {
+    pub delivery_id: String,
+}
+
+// 新增：机器人注册斜杠命令，name 不含斜杠
+#[derive(Debug, Deserialize)]
+pub struct RegisterCommandRequest {
+    pub name: String,
+    pub description: String,
+    pub usage: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct UnregisterCommandRequest {
+    pub name: String,
+}
+
+// 新增：机器人回复命令调用，回复只发给调用者
+#[derive(Debug, Deserialize)]
+pub struct CommandReplyRequest {
+    pub invocation_id: String,
+    pub text: String,
+}
+
+// 新增：命令列表中的一项，bot_user_id 为空表示内置命令
+#[derive(Debug, Serialize)]
+pub struct CommandInfo {
+    pub name: String,
+    pub usage: String,
+    pub description: String,
+    pub bot_user_id: Option<String>,
+}
+
+// 新增：内容过滤管理
+#[derive(Debug, Deserialize)]
+pub struct AddFilterRuleRequest {
+    pub kind: FilterKind,
+    pub pattern: String,
+    pub action: FilterAction,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct DeleteFilterRuleRequest {
+    pub rule_id: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct UpdateLinkPolicyRequest {
+    pub allowed_domains: Vec<String>,
+    pub action: FilterAction,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ModerationFlagQuery {
+    #[serde(default)]
+    pub include_reviewed: bool,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ReviewModerationFlagRequest {
+    pub flag_id: String,
+}
+
+// 新增：角色管理
+#[derive(Debug, Deserialize)]
+pub struct CreateRoleRequest {
+    pub name: String,
+    pub rank: u32,
+    #[serde(default)]
+    pub permissions: Vec<Permission>,
+    #[serde(default)]
+    pub reason: Option<String>,
+}
+
+// 只修改提供的字段
+#[derive(Debug, Deserialize)]
+pub struct UpdateRoleRequest {
+    pub role_id: String,
+    #[serde(default)]
+    pub name: Option<String>,
+    #[serde(default)]
+    pub rank: Option<u32>,
+    #[serde(default)]
+    pub permissions: Option<Vec<Permission>>,
+    #[serde(default)]
+    pub reason: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct DeleteRoleRequest {
+    pub role_id: String,
+    #[serde(default)]
+    pub reason: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SetUserRoleRequest {
+    pub user_id: String,
+    pub role_id: String,
+    #[serde(default)]
+    pub reason: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct RoleList {
+    pub roles: Vec<Role>, // 等级从高到低
+    pub permissions: &'static [Permission], // 可以授予的全部权限
+}
+
+// 新增：举报与处理
+#[derive(Debug, Deserialize)]
+pub struct ReportMessageRequest {
+    pub message_id: String,
+    pub reason: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ModerationQueueQuery {
+    #[serde(default)]
+    pub include_resolved: bool,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ResolveReportRequest {
+    pub message_id: String,
+    pub action: ReportResolution,
+    pub duration_minutes: Option<i64>, // 禁言必填，封禁不填为永久
+    #[serde(default)]
+    pub reason: Option<String>,
+}
+
+// 新增：批量清理某用户的消息，since/until 不填则不限
+#[derive(Debug, Deserialize)]
+pub struct PurgeUserMessagesRequest {
+    pub user_id: String,
+    #[serde(default)]
+    pub since: Option<DateTime<Utc>>,
+    #[serde(default)]
+    pub until: Option<DateTime<Utc>>,
+    pub mode: PurgeMode,
+    #[serde(default)]
+    pub ban: bool, // 同时封禁该用户
+    #[serde(default)]
+    pub ban_duration_minutes: Option<i64>, // 不填为永久封禁
+    #[serde(default)]
+    pub reason: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct PurgeResult {
+    pub purged_count: usize,
+    pub message_ids: Vec<String>,
+    pub banned: bool,
+    pub ban_until: Option<DateTime<Utc>>,
+    pub ban_error: Option<String>, // 清理成功但封禁失败时的原因
+}
+
+// 同一条消息的举报合并为一项
+#[derive(Debug, Serialize)]
+pub struct ModerationQueueItem<'a> {
+    pub message_id: &'a str,
+    pub author_id: &'a str,
+    pub author_name: String, // 作者账号已删除时为空
+    pub content: &'a str, // 消息已撤回时为撤回前的内容
+    pub recalled: bool,
+    pub deleted: bool, // 消息已被删除，content 为举报时保存的内容
+    pub timestamp: Option<DateTime<Utc>>,
+    pub report_count: usize,
+    pub last_reported_at: DateTime<Utc>,
+    pub reports: Vec<&'a MessageReport>,
+}
+
+// 新增：审计日志查询，条件均可选；导出时 format 为 csv 或 json
+#[derive(Debug, Deserialize)]
+pub struct AuditLogQuery {
+    pub action: Option<AuditAction>,
+    pub actor_id: Option<String>,
+    pub target_id: Option<String>,
+    pub from: Option<DateTime<Utc>>,
+    pub to: Option<DateTime<Utc>>,
+    pub page: Option<usize>,
+    pub page_size: Option<usize>,
+    pub format: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct AuditLogPage<'a> {
+    pub total: usize,
+    pub page: usize,
+    pub page_size: usize,
+    pub entries: Vec<&'a AuditEntry>,
+}
+
+// 新增：WebSocket 连接参数，protocol 为客户端支持的协议版本列表，如 "1,2"
+#[derive(Debug, Deserialize)]
+pub struct WsConnectQuery {
+    pub protocol: Option<String>,
+}
+
+// 新增：SSE 连接参数，重连时带上已收到的 last_seq 和 epoch 以补发错过的事件
+#[derive(Debug, Deserialize)]
+pub struct EventStreamQuery {
+    pub protocol: Option<String>,
+    pub last_seq: Option<u64>,
+    pub epoch: Option<String>,
+}
+
+// 新增：长轮询参数，timeout 为最长等待秒数；connection_id 为上一次轮询返回的连接编号
+#[derive(Debug, Deserialize)]
+pub struct PollQuery {
+    pub protocol: Option<String>,
+    pub connection_id: Option<String>,
+    pub last_seq: Option<u64>,
+    pub epoch: Option<String>,
+    pub timeout: Option<u64>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct PollResponse {
+    pub connection_id: String,
+    pub epoch: String,
+    pub last_seq: u64,
+    pub events: Vec<serde_json::Value>,
+    pub resync_required: bool,
 }
 
 #[derive(Debug, Deserialize)]
 pub struct UpdateSettingsRequest {
     pub registration_open: bool,
     pub require_approval: bool, // 新增
+    pub slow_consumer_policy: Option<SlowConsumerPolicy>,
+    pub slow_mode_seconds: Option<u32>, // 新增：不填则不修改
+    #[serde(default)]
+    pub reason: Option<String>, // 新增：记入审计日志
 }
 
 #[derive(Debug, Deserialize)]
 pub struct SendMessageRequest {
     pub content: String,
+    #[serde(default)]
+    pub attachment_ids: Vec<String>,
 }
 
 #[derive(Debug, Deserialize)]
 pub struct RecallMessageRequest {
     pub message_id: String,
+    #[serde(default)]
+    pub reason: Option<String>, // 新增：记入审计日志
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ReactMessageRequest {
+    pub message_id: String,
+    pub emoji: String,
 }
 
 #[derive(Debug, Deserialize)]
 pub struct SetDeputyAdminRequest {
     pub user_id: String,
     pub is_deputy: bool,
+    #[serde(default)]
+    pub reason: Option<String>, // 新增：记入审计日志
 }
 
 #[derive(Debug, Deserialize)]
 pub struct MuteUserRequest {
     pub user_id: String,
     pub duration_minutes: i64,
+    #[serde(default)]
+    pub reason: Option<String>, // 新增：记入审计日志，并告知被禁言的用户
 }
 
 #[derive(Debug, Deserialize)]
 pub struct BanUserRequest {
     pub user_id: String,
+    #[serde(default)]
+    pub duration_minutes: Option<i64>, // 新增：不填为永久封禁
+    #[serde(default)]
+    pub reason: Option<String>, // 新增：记入审计日志
+}
+
+#[derive(Debug, Deserialize)]
+pub struct UnbanUserRequest {
+    pub user_id: String,
+    #[serde(default)]
+    pub reason: Option<String>,
 }
 
 #[derive(Debug, Deserialize)]
@@ -353,6 +1481,30 @@ pub struct UpdateDisplayNameRequest {
     pub display_name: Option<String>,
 }
 
+#[derive(Debug, Deserialize)]
+pub struct MarkReadRequest {
+    pub message_id: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct LoginResponse {
+    pub message: String,
+    pub unread: UnreadSummary,
+}
+
+#[derive(Debug, Serialize)]
+pub struct CurrentUserResponse<'a> {
+    #[serde(flatten)]
+    pub user: &'a User,
+    pub unread: UnreadSummary,
+}
+
+#[derive(Debug, Serialize)]
+pub struct UnreadMentionsResponse {
+    pub count: usize,
+    pub message_ids: Vec<String>,
+}
+
 #[derive(Debug, Serialize)]
 pub struct ApiResponse<T> {
     pub success: bool,
@@ -387,9 +1539,18 @@ pub struct Message {
     pub recalled: bool,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub original_content: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub attachment_ids: Vec<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub mentions: Vec<String>, // 被@的用户ID，由服务端解析
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub reactions: Reactions,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+// 表情 -> 回应的用户ID
+pub type Reactions = BTreeMap<String, Vec<String>>;
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 pub struct MessageWithUser {
     pub id: String,
     pub user_id: String,
@@ -399,6 +1560,12 @@ pub struct MessageWithUser {
     pub user: Option<User>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub original_content: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub attachments: Vec<Attachment>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub mentions: Vec<String>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub reactions: Reactions,
 }
 
 #[derive(Debug, Deserialize)]
@@ -0,0 +1,546 @@
+// 内容过滤：消息保存前依次经过各个过滤器，按命中结果拒绝、打码或标记待复查
+use std::collections::HashMap;
+use std::ops::Range;
+use std::sync::Mutex;
+use actix_web::{web, HttpRequest, HttpResponse, Result};
+use chrono::Utc;
+use lazy_static::lazy_static;
+use regex::{Regex, RegexBuilder};
+use uuid::Uuid;
+use crate::APP_STATE;
+use crate::models::*;
+
+const MAX_RULES: usize = 500;
+const MAX_PATTERN_CHARS: usize = 200;
+const MAX_LINK_DOMAINS: usize = 200;
+// 正则编译后的大小上限，防止规则过于复杂
+const REGEX_SIZE_LIMIT: usize = 1 << 20;
+// 最多保留的命中记录数量
+const MAX_FLAGS: usize = 2000;
+const MAX_FLAGS_PER_PAGE: usize = 200;
+
+lazy_static! {
+    // rule_id -> 编译好的正则，规则删除时移除
+    static ref RULE_REGEXES: Mutex<HashMap<String, Regex>> = Mutex::new(HashMap::new());
+    // 没有协议头时 www. 也算作域名的一部分，和白名单中的 www.example.com 一致
+    static ref LINK_REGEX: Regex = Regex::new(r"(?i)\b(?:https?://|(www\.))([a-z0-9-]+(?:\.[a-z0-9-]+)+)").unwrap();
+}
+
+// 一次命中：range 为消息内容中的字节范围
+pub struct Hit {
+    pub rule_id: Option<String>,
+    pub range: Range<usize>,
+    pub action: FilterAction,
+}
+
+// 过滤器：检查消息内容，返回所有命中
+pub trait ContentFilter: Sync {
+    fn name(&self) -> &'static str;
+    fn check(&self, config: &ModerationConfig, user: &User, content: &str) -> Vec<Hit>;
+}
+
+// 管理员配置的关键词和正则规则
+struct RuleFilter;
+
+impl ContentFilter for RuleFilter {
+    fn name(&self) -> &'static str {
+        "rules"
+    }
+
+    fn check(&self, config: &ModerationConfig, _user: &User, content: &str) -> Vec<Hit> {
+        let mut regexes = RULE_REGEXES.lock().unwrap();
+        let mut hits = Vec::new();
+        for rule in &config.rules {
+            let regex = match regexes.get(&rule.id) {
+                Some(regex) => regex,
+                None => match compile(rule.kind, &rule.pattern) {
+                    Ok(regex) => regexes.entry(rule.id.clone()).or_insert(regex),
+                    Err(_) => continue,
+                },
+            };
+            hits.extend(regex.find_iter(content).filter(|m| !m.is_empty()).map(|m| Hit {
+                rule_id: Some(rule.id.clone()),
+                range: m.range(),
+                action: rule.action,
+            }));
+        }
+        hits
+    }
+}
+
+// 不在白名单中的链接
+struct LinkFilter;
+
+impl ContentFilter for LinkFilter {
+    fn name(&self) -> &'static str {
+        "links"
+    }
+
+    fn check(&self, config: &ModerationConfig, _user: &User, content: &str) -> Vec<Hit> {
+        let policy = &config.link_policy;
+        if policy.allowed_domains.is_empty() {
+            return Vec::new();
+        }
+        LINK_REGEX.captures_iter(content)
+            .filter(|caps| {
+                let www = caps.get(1).map(|m| m.as_str()).unwrap_or("");
+                let domain = format!("{}{}", www, &caps[2]).to_lowercase();
+                !policy.allowed_domains.iter().any(|allowed| {
+                    domain == *allowed || domain.ends_with(&format!(".{}", allowed))
+                })
+            })
+            .map(|caps| Hit {
+                rule_id: None,
+                range: caps.get(0).unwrap().range(),
+                action: policy.action,
+            })
+            .collect()
+    }
+}
+
+// 依次执行的过滤器；自定义过滤器实现 ContentFilter 后加入此列表即可
+const FILTERS: &[&dyn ContentFilter] = &[&RuleFilter, &LinkFilter];
+
+// 过滤结果：content 为打码后的内容，action 为命中中最严重的处理方式
+pub struct Screened {
+    pub content: String,
+    pub matches: Vec<FilterMatch>,
+    pub action: Option<FilterAction>,
+}
+
+impl Screened {
+    pub fn rejection_message(&self) -> String {
+        let by_links = self.matches.iter()
+            .filter(|m| m.action == FilterAction::Reject)
+            .all(|m| m.filter == LinkFilter.name());
+        if by_links {
+            "消息包含不在白名单中的链接".to_string()
+        } else {
+            "消息包含不允许发送的内容".to_string()
+        }
+    }
+}
+
+fn compile(kind: FilterKind, pattern: &str) -> Result<Regex, regex::Error> {
+    let pattern = match kind {
+        FilterKind::Word => regex::escape(pattern),
+        FilterKind::Regex => pattern.to_string(),
+    };
+    RegexBuilder::new(&pattern)
+        .case_insensitive(true)
+        .size_limit(REGEX_SIZE_LIMIT)
+        .build()
+}
+
+// 把 ranges 覆盖到的字符替换为 *
+fn mask(content: &str, ranges: &[Range<usize>]) -> String {
+    content.char_indices()
+        .map(|(index, c)| if ranges.iter().any(|r| r.contains(&index)) { '*' } else { c })
+        .collect()
+}
+
+pub fn screen(config: &ModerationConfig, user: &User, content: &str) -> Screened {
+    let mut matches = Vec::new();
+    let mut mask_ranges = Vec::new();
+    for filter in FILTERS {
+        for hit in filter.check(config, user, content) {
+            if hit.action == FilterAction::Mask {
+                mask_ranges.push(hit.range.clone());
+            }
+            matches.push(FilterMatch {
+                filter: filter.name().to_string(),
+                rule_id: hit.rule_id,
+                matched: content[hit.range].to_string(),
+                action: hit.action,
+            });
+        }
+    }
+
+    Screened {
+        content: if mask_ranges.is_empty() { content.to_string() } else { mask(content, &mask_ranges) },
+        action: matches.iter().map(|m| m.action).max(),
+        matches,
+    }
+}
+
+// 调用方需持有 APP_STATE 的锁
+pub fn record_flag(state: &mut AppState, user_id: &str, message_id: Option<&str>, original: &str, screened: &Screened) {
+    let action = match screened.action {
+        Some(action) => action,
+        None => return,
+    };
+    state.moderation_flags.push(ModerationFlag {
+        id: Uuid::new_v4().to_string(),
+        message_id: message_id.map(str::to_string),
+        user_id: user_id.to_string(),
+        content: original.to_string(),
+        matches: screened.matches.clone(),
+        action,
+        created_at: Utc::now(),
+        reviewed_by: None,
+        reviewed_at: None,
+    });
+    if state.moderation_flags.len() > MAX_FLAGS {
+        let excess = state.moderation_flags.len() - MAX_FLAGS;
+        state.moderation_flags.drain(..excess);
+    }
+    state.save_moderation_flags();
+}
+
+fn normalize_domain(domain: &str) -> Option<String> {
+    let domain = domain.trim().trim_start_matches("*.").to_lowercase();
+    let valid = domain.contains('.')
+        && domain.split('.').all(|label| {
+            !label.is_empty() && label.chars().all(|c| c.is_ascii_alphanumeric() || c == '-')
+        });
+    valid.then_some(domain)
+}
+
+pub async fn get_moderation_config(req: HttpRequest) -> Result<HttpResponse> {
+    if crate::auth::permitted_user_id(&req, Permission::ManageModeration).is_some() {
+        let state = APP_STATE.lock().unwrap();
+        return Ok(HttpResponse::Ok().json(ApiResponse::success(&state.moderation)));
+    }
+
+    Ok(HttpResponse::Unauthorized().json(ApiResponse::<()>::error(
+        "无权访问".to_string()
+    )))
+}
+
+pub async fn add_filter_rule(
+    req: HttpRequest,
+    body: web::Json<AddFilterRuleRequest>,
+) -> Result<HttpResponse> {
+    if let Some(admin_id) = crate::auth::permitted_user_id(&req, Permission::ManageModeration) {
+        let pattern = body.pattern.trim();
+        if pattern.is_empty() || pattern.chars().count() > MAX_PATTERN_CHARS {
+            return Ok(HttpResponse::Ok().json(ApiResponse::<()>::error(
+                format!("规则不能为空且不超过{}个字符", MAX_PATTERN_CHARS)
+            )));
+        }
+        let regex = match compile(body.kind, pattern) {
+            Ok(regex) => regex,
+            Err(e) => {
+                return Ok(HttpResponse::Ok().json(ApiResponse::<()>::error(
+                    format!("正则表达式无效：{}", e)
+                )));
+            }
+        };
+        if regex.is_match("") {
+            return Ok(HttpResponse::Ok().json(ApiResponse::<()>::error(
+                "规则不能匹配空内容".to_string()
+            )));
+        }
+
+        let mut state = APP_STATE.lock().unwrap();
+        if state.moderation.rules.len() >= MAX_RULES {
+            return Ok(HttpResponse::Ok().json(ApiResponse::<()>::error(
+                format!("最多只能添加{}条规则", MAX_RULES)
+            )));
+        }
+        if state.moderation.rules.iter().any(|r| r.kind == body.kind && r.pattern == pattern) {
+            return Ok(HttpResponse::Ok().json(ApiResponse::<()>::error(
+                "该规则已存在".to_string()
+            )));
+        }
+
+        let rule = FilterRule {
+            id: Uuid::new_v4().to_string(),
+            kind: body.kind,
+            pattern: pattern.to_string(),
+            action: body.action,
+            created_by: admin_id.clone(),
+            created_at: Utc::now(),
+        };
+        RULE_REGEXES.lock().unwrap().insert(rule.id.clone(), regex);
+        state.moderation.rules.push(rule.clone());
+        state.save_moderation();
+        crate::audit::record(&mut state, &admin_id, AuditAction::AddFilterRule, None, serde_json::json!({
+            "rule_id": rule.id,
+            "kind": rule.kind,
+            "pattern": rule.pattern,
+            "action": rule.action,
+        }), None);
+
+        return Ok(HttpResponse::Ok().json(ApiResponse::success(rule)));
+    }
+
+    Ok(HttpResponse::Unauthorized().json(ApiResponse::<()>::error(
+        "无权操作".to_string()
+    )))
+}
+
+pub async fn delete_filter_rule(
+    req: HttpRequest,
+    body: web::Json<DeleteFilterRuleRequest>,
+) -> Result<HttpResponse> {
+    if let Some(admin_id) = crate::auth::permitted_user_id(&req, Permission::ManageModeration) {
+        let mut state = APP_STATE.lock().unwrap();
+        let rule = match state.moderation.rules.iter().position(|r| r.id == body.rule_id) {
+            Some(index) => state.moderation.rules.remove(index),
+            None => {
+                return Ok(HttpResponse::Ok().json(ApiResponse::<()>::error(
+                    "规则不存在".to_string()
+                )));
+            }
+        };
+        RULE_REGEXES.lock().unwrap().remove(&rule.id);
+        state.save_moderation();
+        crate::audit::record(&mut state, &admin_id, AuditAction::DeleteFilterRule, None, serde_json::json!({
+            "rule_id": rule.id,
+            "kind": rule.kind,
+            "pattern": rule.pattern,
+            "action": rule.action,
+        }), None);
+
+        return Ok(HttpResponse::Ok().json(ApiResponse::success("规则已删除")));
+    }
+
+    Ok(HttpResponse::Unauthorized().json(ApiResponse::<()>::error(
+        "无权操作".to_string()
+    )))
+}
+
+// 整体替换链接白名单，传空列表表示不限制链接
+pub async fn update_link_policy(
+    req: HttpRequest,
+    body: web::Json<UpdateLinkPolicyRequest>,
+) -> Result<HttpResponse> {
+    if let Some(admin_id) = crate::auth::permitted_user_id(&req, Permission::ManageModeration) {
+        if body.allowed_domains.len() > MAX_LINK_DOMAINS {
+            return Ok(HttpResponse::Ok().json(ApiResponse::<()>::error(
+                format!("最多只能设置{}个域名", MAX_LINK_DOMAINS)
+            )));
+        }
+        let mut allowed_domains: Vec<String> = Vec::new();
+        for domain in &body.allowed_domains {
+            match normalize_domain(domain) {
+                Some(domain) if !allowed_domains.contains(&domain) => allowed_domains.push(domain),
+                Some(_) => {}
+                None => {
+                    return Ok(HttpResponse::Ok().json(ApiResponse::<()>::error(
+                        format!("域名格式不正确：{}", domain)
+                    )));
+                }
+            }
+        }
+
+        let mut state = APP_STATE.lock().unwrap();
+        let old_policy = std::mem::replace(&mut state.moderation.link_policy, LinkPolicy {
+            allowed_domains,
+            action: body.action,
+        });
+        state.save_moderation();
+        let params = serde_json::json!({
+            "old": old_policy,
+            "new": state.moderation.link_policy,
+        });
+        crate::audit::record(&mut state, &admin_id, AuditAction::UpdateLinkPolicy, None, params, None);
+
+        return Ok(HttpResponse::Ok().json(ApiResponse::success("链接白名单已更新")));
+    }
+
+    Ok(HttpResponse::Unauthorized().json(ApiResponse::<()>::error(
+        "无权操作".to_string()
+    )))
+}
+
+// 最近的命中记录，最新的在前；默认只返回未复查的
+pub async fn get_moderation_flags(
+    req: HttpRequest,
+    query: web::Query<ModerationFlagQuery>,
+) -> Result<HttpResponse> {
+    if let Some(token) = crate::auth::request_token(&req) {
+        let state = APP_STATE.lock().unwrap();
+
+        if let Some(user_id) = state.token_user_id(&token) {
+            if crate::permissions::has_permission(&state, user_id, Permission::ReviewReports) {
+                let flags: Vec<&ModerationFlag> = state.moderation_flags.iter()
+                    .rev()
+                    .filter(|f| query.include_reviewed || f.reviewed_by.is_none())
+                    .take(MAX_FLAGS_PER_PAGE)
+                    .collect();
+                return Ok(HttpResponse::Ok().json(ApiResponse::success(flags)));
+            }
+        }
+    }
+
+    Ok(HttpResponse::Unauthorized().json(ApiResponse::<()>::error(
+        "无权访问".to_string()
+    )))
+}
+
+pub async fn review_moderation_flag(
+    req: HttpRequest,
+    body: web::Json<ReviewModerationFlagRequest>,
+) -> Result<HttpResponse> {
+    if let Some(token) = crate::auth::request_token(&req) {
+        let mut state = APP_STATE.lock().unwrap();
+
+        if let Some(user_id) = state.token_user_id(&token).cloned() {
+            if crate::permissions::has_permission(&state, &user_id, Permission::ReviewReports) {
+                return match state.moderation_flags.iter_mut().find(|f| f.id == body.flag_id) {
+                    Some(flag) if flag.reviewed_by.is_none() => {
+                        flag.reviewed_by = Some(user_id);
+                        flag.reviewed_at = Some(Utc::now());
+                        state.save_moderation_flags();
+                        Ok(HttpResponse::Ok().json(ApiResponse::success("已标记为已复查")))
+                    }
+                    Some(_) => Ok(HttpResponse::Ok().json(ApiResponse::<()>::error(
+                        "该记录已复查".to_string()
+                    ))),
+                    None => Ok(HttpResponse::Ok().json(ApiResponse::<()>::error(
+                        "记录不存在".to_string()
+                    ))),
+                };
+            }
+        }
+    }
+
+    Ok(HttpResponse::Unauthorized().json(ApiResponse::<()>::error(
+        "无权操作".to_string()
+    )))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn user() -> User {
+        User {
+            id: "u1".to_string(),
+            username: "alice".to_string(),
+            email: String::new(),
+            password_hash: String::new(),
+            avatar: None,
+            display_name: None,
+            role: crate::models::ROLE_MEMBER.to_string(),
+            status: UserStatus::Active,
+            created_at: Utc::now(),
+            last_ips: Vec::new(),
+            muted_until: None,
+            mute_reason: None,
+            is_bot: false,
+            ban: None,
+        }
+    }
+
+    // 编译好的正则按规则ID缓存，各测试使用不同的ID
+    fn rule(id: &str, pattern: &str, action: FilterAction) -> FilterRule {
+        FilterRule {
+            id: id.to_string(),
+            kind: FilterKind::Word,
+            pattern: pattern.to_string(),
+            action,
+            created_by: "a1".to_string(),
+            created_at: Utc::now(),
+        }
+    }
+
+    fn links(allowed: &[&str]) -> ModerationConfig {
+        ModerationConfig {
+            rules: Vec::new(),
+            link_policy: LinkPolicy {
+                allowed_domains: allowed.iter().map(|d| d.to_string()).collect(),
+                action: FilterAction::Reject,
+            },
+        }
+    }
+
+    #[test]
+    fn mask_replaces_multibyte_characters() {
+        assert_eq!(mask("这是坏词啊", &[6..9, 9..12]), "这是**啊");
+        assert_eq!(mask("a😀b😀", &[1..5, 6..10]), "a*b*");
+
+        let config = ModerationConfig {
+            rules: vec![rule("test-mask-cjk", "坏词", FilterAction::Mask)],
+            ..Default::default()
+        };
+        let screened = screen(&config, &user(), "坏词，还是坏词😀");
+        assert_eq!(screened.content, "**，还是**😀");
+        assert_eq!(screened.action, Some(FilterAction::Mask));
+        assert_eq!(screened.matches.len(), 2);
+        assert_eq!(screened.matches[0].matched, "坏词");
+    }
+
+    #[test]
+    fn allowlisted_domains_and_subdomains_pass() {
+        let config = links(&["example.com"]);
+        for content in [
+            "看 https://example.com/page",
+            "看 http://docs.example.com",
+            "看 https://WWW.Example.com/a",
+            "看 www.example.com",
+            "没有链接 example.org",
+        ] {
+            assert_eq!(screen(&config, &user(), content).action, None, "{}", content);
+        }
+    }
+
+    #[test]
+    fn other_domains_are_rejected() {
+        let config = links(&["example.com"]);
+        for content in [
+            "https://notexample.com",
+            "https://example.com.evil.org",
+            "www.evil.org",
+        ] {
+            let screened = screen(&config, &user(), content);
+            assert_eq!(screened.action, Some(FilterAction::Reject), "{}", content);
+            assert_eq!(screened.rejection_message(), "消息包含不在白名单中的链接");
+        }
+    }
+
+    #[test]
+    fn www_allowlist_entry_matches_link_without_scheme() {
+        let config = links(&["www.example.com"]);
+        assert_eq!(screen(&config, &user(), "www.example.com/a").action, None);
+        assert_eq!(screen(&config, &user(), "https://www.example.com").action, None);
+        // 白名单只有 www 子域名时，主域名不在其中
+        assert_eq!(screen(&config, &user(), "https://example.com").action, Some(FilterAction::Reject));
+    }
+
+    #[test]
+    fn empty_allowlist_allows_all_links() {
+        assert_eq!(screen(&links(&[]), &user(), "https://anything.org").action, None);
+    }
+
+    #[test]
+    fn most_severe_action_wins() {
+        let config = ModerationConfig {
+            rules: vec![
+                rule("test-order-flag", "apple", FilterAction::Flag),
+                rule("test-order-mask", "banana", FilterAction::Mask),
+                rule("test-order-reject", "cherry", FilterAction::Reject),
+            ],
+            ..Default::default()
+        };
+
+        let screened = screen(&config, &user(), "apple banana cherry");
+        assert_eq!(screened.action, Some(FilterAction::Reject));
+        assert_eq!(screened.matches.len(), 3);
+        assert_eq!(screened.rejection_message(), "消息包含不允许发送的内容");
+
+        // 只打码命中的部分，标记的内容保持原样
+        let screened = screen(&config, &user(), "apple banana");
+        assert_eq!(screened.action, Some(FilterAction::Mask));
+        assert_eq!(screened.content, "apple ******");
+
+        let screened = screen(&config, &user(), "apple");
+        assert_eq!(screened.action, Some(FilterAction::Flag));
+        assert_eq!(screened.content, "apple");
+
+        let screened = screen(&config, &user(), "grape");
+        assert_eq!(screened.action, None);
+        assert!(screened.matches.is_empty());
+    }
+
+    #[test]
+    fn link_reject_takes_precedence_over_rule_mask() {
+        let mut config = links(&["example.com"]);
+        config.rules.push(rule("test-link-mask", "banana", FilterAction::Mask));
+        let screened = screen(&config, &user(), "banana https://evil.org");
+        assert_eq!(screened.action, Some(FilterAction::Reject));
+        assert_eq!(screened.rejection_message(), "消息包含不在白名单中的链接");
+    }
+}
\ No newline at end of file
@@ -0,0 +1,617 @@
+// 传出 Webhook：聊天室中发生订阅的事件时，向外部服务发送带 HMAC 签名的 POST 请求。
+// 投递记录持久化保存，失败后按指数退避重试，完成的记录保留作为投递日志
+use std::time::Duration as StdDuration;
+use actix_web::{web, HttpRequest, HttpResponse, Result};
+use chrono::{Duration, Utc};
+use futures_util::future::join_all;
+use hmac::{Hmac, Mac};
+use rand::{thread_rng, Rng};
+use sha2::Sha256;
+use uuid::Uuid;
+use crate::APP_STATE;
+use crate::models::*;
+
+// 检查到期投递的间隔（秒）
+const DELIVERY_CHECK_INTERVAL_SECONDS: u64 = 1;
+// 每轮最多并发发送的投递数
+const MAX_DELIVERIES_PER_ROUND: usize = 32;
+const REQUEST_TIMEOUT: StdDuration = StdDuration::from_secs(10);
+// 最多尝试次数；第 n 次失败后等待 10 秒 * 2^(n-1)，最长 1 小时
+const MAX_DELIVERY_ATTEMPTS: u32 = 8;
+const RETRY_BASE_SECONDS: i64 = 10;
+const MAX_RETRY_DELAY_SECONDS: i64 = 3600;
+// 每个 Webhook 保留的已完成投递记录数
+const MAX_DELIVERY_LOG_ENTRIES: usize = 100;
+const DEFAULT_DELIVERY_LOG_LIMIT: usize = 50;
+
+const SECRET_PREFIX: &str = "whsec_";
+
+fn generate_secret() -> String {
+    let bytes: [u8; 24] = thread_rng().gen();
+    format!("{}{}", SECRET_PREFIX, hex::encode(bytes))
+}
+
+// 签名内容为 "{timestamp}.{body}"，接收方应校验时间戳以防重放
+fn sign(secret: &str, timestamp: i64, body: &str) -> String {
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes()).unwrap();
+    mac.update(format!("{}.{}", timestamp, body).as_bytes());
+    format!("sha256={}", hex::encode(mac.finalize().into_bytes()))
+}
+
+fn retry_delay(attempts: u32) -> Duration {
+    let seconds = RETRY_BASE_SECONDS
+        .saturating_mul(1i64 << attempts.saturating_sub(1).min(20))
+        .min(MAX_RETRY_DELAY_SECONDS);
+    Duration::seconds(seconds)
+}
+
+// 事件中的用户信息，不包含邮箱、密码等字段
+pub fn user_data(user: &User) -> serde_json::Value {
+    serde_json::json!({
+        "id": user.id,
+        "username": user.username,
+        "display_name": user.display_name,
+        "avatar": user.avatar,
+        "role": user.role,
+        "is_bot": user.is_bot,
+    })
+}
+
+// 为订阅了该事件的每个 Webhook 创建一条待发送的投递；调用方需持有 APP_STATE 的锁
+pub fn enqueue(state: &mut AppState, event: WebhookEvent, data: serde_json::Value) {
+    let webhook_ids: Vec<String> = state.outgoing_webhooks.values()
+        .filter(|w| w.enabled && w.events.contains(&event))
+        .map(|w| w.id.clone())
+        .collect();
+    if webhook_ids.is_empty() {
+        return;
+    }
+
+    let now = Utc::now();
+    let payload = serde_json::json!({
+        "event_id": Uuid::new_v4().to_string(),
+        "event": event,
+        "timestamp": now,
+        "data": data,
+    })
+    .to_string();
+
+    for webhook_id in webhook_ids {
+        state.webhook_deliveries.push(WebhookDelivery {
+            id: Uuid::new_v4().to_string(),
+            webhook_id,
+            event,
+            payload: payload.clone(),
+            status: DeliveryStatus::Pending,
+            attempts: 0,
+            next_attempt_at: now,
+            created_at: now,
+            last_attempt_at: None,
+            last_status_code: None,
+            last_error: None,
+        });
+    }
+    state.save_webhook_deliveries();
+}
+
+struct DueDelivery {
+    delivery: WebhookDelivery,
+    url: String,
+    secret: String,
+}
+
+// 已到重试时间、且所属 Webhook 仍启用的投递；Webhook 停用期间投递保留在队列中
+fn due_deliveries() -> Vec<DueDelivery> {
+    let now = Utc::now();
+    let state = APP_STATE.lock().unwrap();
+    state.webhook_deliveries.iter()
+        .filter(|d| d.status == DeliveryStatus::Pending && d.next_attempt_at <= now)
+        .filter_map(|d| {
+            let webhook = state.outgoing_webhooks.get(&d.webhook_id).filter(|w| w.enabled)?;
+            Some(DueDelivery {
+                delivery: d.clone(),
+                url: webhook.url.clone(),
+                secret: webhook.secret.clone(),
+            })
+        })
+        .take(MAX_DELIVERIES_PER_ROUND)
+        .collect()
+}
+
+// 发送一次，返回响应状态码或错误信息
+async fn attempt(client: &reqwest::Client, due: &DueDelivery) -> Result<u16, String> {
+    let timestamp = Utc::now().timestamp();
+    let payload = &due.delivery.payload;
+    let event = serde_json::to_value(due.delivery.event).unwrap();
+
+    let response = client.post(&due.url)
+        .header("Content-Type", "application/json")
+        .header("User-Agent", "Cloud-PE-Webhook/1.0")
+        .header("X-Webhook-Event", event.as_str().unwrap_or_default())
+        .header("X-Webhook-Delivery", &due.delivery.id)
+        .header("X-Webhook-Timestamp", timestamp.to_string())
+        .header("X-Webhook-Signature", sign(&due.secret, timestamp, payload))
+        .body(payload.clone())
+        .send()
+        .await
+        .map_err(|e| e.to_string())?;
+
+    Ok(response.status().as_u16())
+}
+
+fn record_attempt(delivery_id: &str, result: Result<u16, String>) {
+    let now = Utc::now();
+    let mut state = APP_STATE.lock().unwrap();
+    let delivery = match state.webhook_deliveries.iter_mut().find(|d| d.id == delivery_id) {
+        Some(delivery) => delivery,
+        None => return, // Webhook 已被删除
+    };
+
+    delivery.attempts += 1;
+    delivery.last_attempt_at = Some(now);
+    let succeeded = match result {
+        Ok(code) => {
+            delivery.last_status_code = Some(code);
+            delivery.last_error = if (200..300).contains(&code) {
+                None
+            } else {
+                Some(format!("HTTP {}", code))
+            };
+            (200..300).contains(&code)
+        }
+        Err(e) => {
+            delivery.last_status_code = None;
+            delivery.last_error = Some(e);
+            false
+        }
+    };
+
+    if succeeded {
+        delivery.status = DeliveryStatus::Succeeded;
+    } else if delivery.attempts >= MAX_DELIVERY_ATTEMPTS {
+        delivery.status = DeliveryStatus::Failed;
+    } else {
+        delivery.next_attempt_at = now + retry_delay(delivery.attempts);
+    }
+
+    let webhook_id = delivery.webhook_id.clone();
+    if delivery.status != DeliveryStatus::Pending {
+        prune_log(&mut state, &webhook_id);
+    }
+}
+
+// 只保留每个 Webhook 最近的已完成记录，待发送的投递不受影响
+fn prune_log(state: &mut AppState, webhook_id: &str) {
+    let finished = state.webhook_deliveries.iter()
+        .filter(|d| d.webhook_id == webhook_id && d.status != DeliveryStatus::Pending)
+        .count();
+    let mut excess = finished.saturating_sub(MAX_DELIVERY_LOG_ENTRIES);
+    // 记录按创建时间追加，最早的在前
+    state.webhook_deliveries.retain(|d| {
+        if excess > 0 && d.webhook_id == webhook_id && d.status != DeliveryStatus::Pending {
+            excess -= 1;
+            return false;
+        }
+        true
+    });
+}
+
+// 后台投递任务，服务启动时运行；重启后从持久化的队列继续
+pub async fn run_delivery_worker() {
+    let client = reqwest::Client::builder()
+        .timeout(REQUEST_TIMEOUT)
+        .build()
+        .unwrap();
+    let mut interval = tokio::time::interval(StdDuration::from_secs(DELIVERY_CHECK_INTERVAL_SECONDS));
+
+    loop {
+        interval.tick().await;
+
+        let due = due_deliveries();
+        if due.is_empty() {
+            continue;
+        }
+
+        let results = join_all(due.iter().map(|d| attempt(&client, d))).await;
+        for (due, result) in due.iter().zip(results) {
+            record_attempt(&due.delivery.id, result);
+        }
+        APP_STATE.lock().unwrap().save_webhook_deliveries();
+    }
+}
+
+fn validate_url(url: &str) -> Result<String, String> {
+    let url = url.trim();
+    match reqwest::Url::parse(url) {
+        Ok(parsed) if parsed.scheme() == "http" || parsed.scheme() == "https" => Ok(url.to_string()),
+        _ => Err("Webhook 地址必须是有效的 http 或 https 地址".to_string()),
+    }
+}
+
+fn validate_events(events: &[WebhookEvent]) -> Result<Vec<WebhookEvent>, String> {
+    let mut unique = Vec::new();
+    for event in events {
+        if !unique.contains(event) {
+            unique.push(*event);
+        }
+    }
+    if unique.is_empty() {
+        return Err("请至少选择一个事件".to_string());
+    }
+    Ok(unique)
+}
+
+fn validate_name(name: &str) -> Result<String, String> {
+    let name = name.trim();
+    if name.is_empty() || name.chars().count() > 64 {
+        return Err("名称不能为空且不超过64个字符".to_string());
+    }
+    Ok(name.to_string())
+}
+
+pub async fn get_outgoing_webhooks(req: HttpRequest) -> Result<HttpResponse> {
+    if crate::auth::permitted_user_id(&req, Permission::ManageIntegrations).is_some() {
+        let state = APP_STATE.lock().unwrap();
+        let mut webhooks: Vec<OutgoingWebhookInfo> = state.outgoing_webhooks.values()
+            .map(OutgoingWebhookInfo::from)
+            .collect();
+        webhooks.sort_by_key(|w| w.created_at);
+
+        return Ok(HttpResponse::Ok().json(ApiResponse::success(webhooks)));
+    }
+
+    Ok(HttpResponse::Unauthorized().json(ApiResponse::<()>::error(
+        "无权访问".to_string()
+    )))
+}
+
+pub async fn create_outgoing_webhook(
+    req: HttpRequest,
+    body: web::Json<CreateOutgoingWebhookRequest>,
+) -> Result<HttpResponse> {
+    if let Some(admin_id) = crate::auth::permitted_user_id(&req, Permission::ManageIntegrations) {
+        let validated = validate_name(&body.name).and_then(|name| {
+            Ok((name, validate_url(&body.url)?, validate_events(&body.events)?))
+        });
+        let (name, url, events) = match validated {
+            Ok(validated) => validated,
+            Err(e) => return Ok(HttpResponse::Ok().json(ApiResponse::<()>::error(e))),
+        };
+
+        let webhook = OutgoingWebhook {
+            id: Uuid::new_v4().to_string(),
+            name,
+            url,
+            secret: generate_secret(),
+            events,
+            enabled: true,
+            created_by: admin_id,
+            created_at: Utc::now(),
+        };
+        let response = OutgoingWebhookSecretResponse {
+            secret: webhook.secret.clone(),
+            info: OutgoingWebhookInfo::from(&webhook),
+        };
+
+        let mut state = APP_STATE.lock().unwrap();
+        state.outgoing_webhooks.insert(webhook.id.clone(), webhook);
+        state.save_outgoing_webhooks();
+
+        return Ok(HttpResponse::Ok().json(ApiResponse::success(response)));
+    }
+
+    Ok(HttpResponse::Unauthorized().json(ApiResponse::<()>::error(
+        "无权操作".to_string()
+    )))
+}
+
+pub async fn update_outgoing_webhook(
+    req: HttpRequest,
+    body: web::Json<UpdateOutgoingWebhookRequest>,
+) -> Result<HttpResponse> {
+    if crate::auth::permitted_user_id(&req, Permission::ManageIntegrations).is_some() {
+        let name = match body.name.as_deref().map(validate_name).transpose() {
+            Ok(name) => name,
+            Err(e) => return Ok(HttpResponse::Ok().json(ApiResponse::<()>::error(e))),
+        };
+        let url = match body.url.as_deref().map(validate_url).transpose() {
+            Ok(url) => url,
+            Err(e) => return Ok(HttpResponse::Ok().json(ApiResponse::<()>::error(e))),
+        };
+        let events = match body.events.as_deref().map(validate_events).transpose() {
+            Ok(events) => events,
+            Err(e) => return Ok(HttpResponse::Ok().json(ApiResponse::<()>::error(e))),
+        };
+
+        let mut state = APP_STATE.lock().unwrap();
+        if let Some(webhook) = state.outgoing_webhooks.get_mut(&body.webhook_id) {
+            if let Some(name) = name {
+                webhook.name = name;
+            }
+            if let Some(url) = url {
+                webhook.url = url;
+            }
+            if let Some(events) = events {
+                webhook.events = events;
+            }
+            if let Some(enabled) = body.enabled {
+                webhook.enabled = enabled;
+            }
+            let info = OutgoingWebhookInfo::from(&*webhook);
+            state.save_outgoing_webhooks();
+
+            return Ok(HttpResponse::Ok().json(ApiResponse::success(info)));
+        }
+
+        return Ok(HttpResponse::Ok().json(ApiResponse::<()>::error(
+            "Webhook 不存在".to_string()
+        )));
+    }
+
+    Ok(HttpResponse::Unauthorized().json(ApiResponse::<()>::error(
+        "无权操作".to_string()
+    )))
+}
+
+// 重新生成签名密钥，之后的投递（包括重试）都使用新密钥签名
+pub async fn reset_outgoing_webhook_secret(
+    req: HttpRequest,
+    body: web::Json<WebhookIdRequest>,
+) -> Result<HttpResponse> {
+    if crate::auth::permitted_user_id(&req, Permission::ManageIntegrations).is_some() {
+        let mut state = APP_STATE.lock().unwrap();
+        if let Some(webhook) = state.outgoing_webhooks.get_mut(&body.webhook_id) {
+            webhook.secret = generate_secret();
+            let response = OutgoingWebhookSecretResponse {
+                secret: webhook.secret.clone(),
+                info: OutgoingWebhookInfo::from(&*webhook),
+            };
+            state.save_outgoing_webhooks();
+
+            return Ok(HttpResponse::Ok().json(ApiResponse::success(response)));
+        }
+
+        return Ok(HttpResponse::Ok().json(ApiResponse::<()>::error(
+            "Webhook 不存在".to_string()
+        )));
+    }
+
+    Ok(HttpResponse::Unauthorized().json(ApiResponse::<()>::error(
+        "无权操作".to_string()
+    )))
+}
+
+// 删除 Webhook 及其投递记录，未发送的投递一并取消
+pub async fn delete_outgoing_webhook(
+    req: HttpRequest,
+    body: web::Json<WebhookIdRequest>,
+) -> Result<HttpResponse> {
+    if crate::auth::permitted_user_id(&req, Permission::ManageIntegrations).is_some() {
+        let mut state = APP_STATE.lock().unwrap();
+        if state.outgoing_webhooks.remove(&body.webhook_id).is_none() {
+            return Ok(HttpResponse::Ok().json(ApiResponse::<()>::error(
+                "Webhook 不存在".to_string()
+            )));
+        }
+        state.webhook_deliveries.retain(|d| d.webhook_id != body.webhook_id);
+        state.save_outgoing_webhooks();
+        state.save_webhook_deliveries();
+
+        return Ok(HttpResponse::Ok().json(ApiResponse::success("Webhook 已删除")));
+    }
+
+    Ok(HttpResponse::Unauthorized().json(ApiResponse::<()>::error(
+        "无权操作".to_string()
+    )))
+}
+
+// 投递日志，最新的在前
+pub async fn get_webhook_deliveries(
+    req: HttpRequest,
+    query: web::Query<DeliveryLogQuery>,
+) -> Result<HttpResponse> {
+    if crate::auth::permitted_user_id(&req, Permission::ManageIntegrations).is_some() {
+        let limit = query.limit.unwrap_or(DEFAULT_DELIVERY_LOG_LIMIT).min(MAX_DELIVERY_LOG_ENTRIES);
+        let state = APP_STATE.lock().unwrap();
+        let deliveries: Vec<&WebhookDelivery> = state.webhook_deliveries.iter()
+            .rev()
+            .filter(|d| d.webhook_id == query.webhook_id)
+            .take(limit)
+            .collect();
+
+        return Ok(HttpResponse::Ok().json(ApiResponse::success(deliveries)));
+    }
+
+    Ok(HttpResponse::Unauthorized().json(ApiResponse::<()>::error(
+        "无权访问".to_string()
+    )))
+}
+
+// 重新发送一次历史投递，作为新的投递记录加入队列
+pub async fn redeliver_webhook(
+    req: HttpRequest,
+    body: web::Json<RedeliverRequest>,
+) -> Result<HttpResponse> {
+    if crate::auth::permitted_user_id(&req, Permission::ManageIntegrations).is_some() {
+        let mut state = APP_STATE.lock().unwrap();
+        if let Some(original) = state.webhook_deliveries.iter().find(|d| d.id == body.delivery_id) {
+            let now = Utc::now();
+            let delivery = WebhookDelivery {
+                id: Uuid::new_v4().to_string(),
+                status: DeliveryStatus::Pending,
+                attempts: 0,
+                next_attempt_at: now,
+                created_at: now,
+                last_attempt_at: None,
+                last_status_code: None,
+                last_error: None,
+                ..original.clone()
+            };
+            state.webhook_deliveries.push(delivery.clone());
+            state.save_webhook_deliveries();
+
+            return Ok(HttpResponse::Ok().json(ApiResponse::success(delivery)));
+        }
+
+        return Ok(HttpResponse::Ok().json(ApiResponse::<()>::error(
+            "投递记录不存在".to_string()
+        )));
+    }
+
+    Ok(HttpResponse::Unauthorized().json(ApiResponse::<()>::error(
+        "无权操作".to_string()
+    )))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::{Arc, Mutex};
+    use actix_web::{App, HttpServer};
+
+    fn delivery(webhook_id: &str, status: DeliveryStatus, created_at: chrono::DateTime<Utc>) -> WebhookDelivery {
+        WebhookDelivery {
+            id: Uuid::new_v4().to_string(),
+            webhook_id: webhook_id.to_string(),
+            event: WebhookEvent::NewMessage,
+            payload: "{}".to_string(),
+            status,
+            attempts: 0,
+            next_attempt_at: created_at,
+            created_at,
+            last_attempt_at: None,
+            last_status_code: None,
+            last_error: None,
+        }
+    }
+
+    #[test]
+    fn sign_matches_known_vector() {
+        assert_eq!(
+            sign("whsec_test", 1700000000, r#"{"event":"message_created"}"#),
+            "sha256=7dd5fa15bbe79046162327abe492507c2163fa0388be9e8ae0a15524b48bf01f"
+        );
+    }
+
+    #[test]
+    fn retry_delay_backs_off_and_caps() {
+        assert_eq!(retry_delay(1), Duration::seconds(10));
+        assert_eq!(retry_delay(2), Duration::seconds(20));
+        assert_eq!(retry_delay(3), Duration::seconds(40));
+        assert_eq!(retry_delay(9), Duration::seconds(2560));
+        assert_eq!(retry_delay(10), Duration::seconds(MAX_RETRY_DELAY_SECONDS));
+        assert_eq!(retry_delay(u32::MAX), Duration::seconds(MAX_RETRY_DELAY_SECONDS));
+    }
+
+    #[test]
+    fn prune_log_keeps_recent_finished_and_all_pending() {
+        let mut state = AppState::new();
+        let start = Utc::now();
+        for i in 0..MAX_DELIVERY_LOG_ENTRIES + 5 {
+            let status = if i % 2 == 0 { DeliveryStatus::Succeeded } else { DeliveryStatus::Failed };
+            state.webhook_deliveries.push(delivery("w1", status, start + Duration::seconds(i as i64)));
+        }
+        state.webhook_deliveries.push(delivery("w1", DeliveryStatus::Pending, start));
+        state.webhook_deliveries.push(delivery("w2", DeliveryStatus::Succeeded, start));
+
+        prune_log(&mut state, "w1");
+
+        let finished: Vec<&WebhookDelivery> = state.webhook_deliveries.iter()
+            .filter(|d| d.webhook_id == "w1" && d.status != DeliveryStatus::Pending)
+            .collect();
+        assert_eq!(finished.len(), MAX_DELIVERY_LOG_ENTRIES);
+        // 最早的 5 条被移除
+        assert_eq!(finished[0].created_at, start + Duration::seconds(5));
+        assert!(state.webhook_deliveries.iter().any(|d| d.webhook_id == "w1" && d.status == DeliveryStatus::Pending));
+        assert!(state.webhook_deliveries.iter().any(|d| d.webhook_id == "w2"));
+    }
+
+    // 本地的接收端：第一次返回 500，之后返回 200，并记下收到的请求头和请求体
+    type Received = Arc<Mutex<Vec<(actix_web::http::header::HeaderMap, String)>>>;
+
+    async fn receiver(req: HttpRequest, body: String, received: web::Data<Received>) -> HttpResponse {
+        let mut received = received.lock().unwrap();
+        received.push((req.headers().clone(), body));
+        if received.len() == 1 {
+            HttpResponse::InternalServerError().finish()
+        } else {
+            HttpResponse::Ok().finish()
+        }
+    }
+
+    #[actix_web::test]
+    async fn delivery_is_signed_and_retried_after_server_error() {
+        let received: Received = Arc::new(Mutex::new(Vec::new()));
+        let data = web::Data::new(received.clone());
+        let server = HttpServer::new(move || {
+            App::new().app_data(data.clone()).default_service(web::to(receiver))
+        })
+        .workers(1)
+        .bind(("127.0.0.1", 0))
+        .unwrap();
+        let addr = server.addrs()[0];
+        let server = server.run();
+        let handle = server.handle();
+        actix_web::rt::spawn(server);
+
+        let webhook = OutgoingWebhook {
+            id: Uuid::new_v4().to_string(),
+            name: "test".to_string(),
+            url: format!("http://{}/hook", addr),
+            secret: generate_secret(),
+            events: vec![WebhookEvent::NewMessage],
+            enabled: true,
+            created_by: "admin".to_string(),
+            created_at: Utc::now(),
+        };
+        let mut pending = delivery(&webhook.id, DeliveryStatus::Pending, Utc::now());
+        pending.payload = r#"{"event":"new_message"}"#.to_string();
+        let delivery_id = pending.id.clone();
+        {
+            let mut state = APP_STATE.lock().unwrap();
+            state.outgoing_webhooks.insert(webhook.id.clone(), webhook.clone());
+            state.webhook_deliveries.push(pending);
+        }
+        let due_now = |delivery_id: &str| due_deliveries().into_iter().find(|d| d.delivery.id == delivery_id);
+        let client = reqwest::Client::new();
+
+        // 第一次投递失败，按退避时间等待重试
+        let due = due_now(&delivery_id).expect("delivery should be due");
+        record_attempt(&delivery_id, attempt(&client, &due).await);
+        {
+            let mut state = APP_STATE.lock().unwrap();
+            let entry = state.webhook_deliveries.iter_mut().find(|d| d.id == delivery_id).unwrap();
+            assert_eq!(entry.status, DeliveryStatus::Pending);
+            assert_eq!(entry.attempts, 1);
+            assert_eq!(entry.last_status_code, Some(500));
+            assert_eq!(entry.last_error.as_deref(), Some("HTTP 500"));
+            assert!(entry.next_attempt_at > Utc::now() + Duration::seconds(5));
+            // 跳过等待时间
+            entry.next_attempt_at = Utc::now();
+        }
+
+        let due = due_now(&delivery_id).expect("delivery should be due again");
+        record_attempt(&delivery_id, attempt(&client, &due).await);
+        {
+            let state = APP_STATE.lock().unwrap();
+            let entry = state.webhook_deliveries.iter().find(|d| d.id == delivery_id).unwrap();
+            assert_eq!(entry.status, DeliveryStatus::Succeeded);
+            assert_eq!(entry.attempts, 2);
+            assert_eq!(entry.last_status_code, Some(200));
+            assert_eq!(entry.last_error, None);
+        }
+
+        let received = received.lock().unwrap().clone();
+        assert_eq!(received.len(), 2);
+        for (headers, body) in &received {
+            let header = |name: &str| headers.get(name).unwrap().to_str().unwrap().to_string();
+            let timestamp: i64 = header("X-Webhook-Timestamp").parse().unwrap();
+            assert_eq!(body, r#"{"event":"new_message"}"#);
+            assert_eq!(header("X-Webhook-Signature"), sign(&webhook.secret, timestamp, body));
+            assert_eq!(header("X-Webhook-Delivery"), delivery_id);
+            assert_eq!(header("X-Webhook-Event"), "new_message");
+        }
+
+        handle.stop(true).await;
+    }
+}
\ No newline at end of file
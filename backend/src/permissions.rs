@@ -0,0 +1,516 @@
+// 权限与角色：所有接口通过 has_permission / can_act_on 检查操作权限，
+// 不再各自判断角色。管理员角色始终拥有全部权限，可以处置任何人
+use actix_web::{web, HttpRequest, HttpResponse, Result};
+use uuid::Uuid;
+use crate::APP_STATE;
+use crate::models::*;
+
+const MAX_ROLE_NAME_CHARS: usize = 32;
+// 管理员角色的等级，其他角色必须低于它
+const ADMIN_RANK: u32 = 100;
+const DEPUTY_ADMIN_RANK: u32 = 50;
+
+pub const ALL_PERMISSIONS: &[Permission] = &[
+    Permission::RecallAny,
+    Permission::Mute,
+    Permission::Ban,
+    Permission::Approve,
+    Permission::ManageUsers,
+    Permission::EditNicknames,
+    Permission::ManageSettings,
+    Permission::PinMessages,
+    Permission::MentionAll,
+    Permission::ReviewReports,
+    Permission::ManageModeration,
+    Permission::ViewAuditLog,
+    Permission::ManageRoles,
+    Permission::ManageIntegrations,
+    Permission::BypassSlowMode,
+];
+
+// 访问令牌的 Moderate 范围可以调用的管理接口所需的权限
+const MODERATION_PERMISSIONS: &[Permission] = &[
+    Permission::RecallAny,
+    Permission::Mute,
+    Permission::Ban,
+    Permission::Approve,
+    Permission::ManageUsers,
+    Permission::EditNicknames,
+    Permission::ManageSettings,
+    Permission::ReviewReports,
+    Permission::ManageModeration,
+    Permission::ViewAuditLog,
+    Permission::ManageRoles,
+];
+
+// 原来的三种角色作为内置角色，次管理员的默认权限与原先各接口的判断一致
+pub fn builtin_roles() -> Vec<Role> {
+    vec![
+        Role {
+            id: ROLE_ADMIN.to_string(),
+            name: "管理员".to_string(),
+            rank: ADMIN_RANK,
+            permissions: ALL_PERMISSIONS.to_vec(),
+            built_in: true,
+        },
+        Role {
+            id: ROLE_DEPUTY_ADMIN.to_string(),
+            name: "次管理员".to_string(),
+            rank: DEPUTY_ADMIN_RANK,
+            permissions: vec![
+                Permission::RecallAny,
+                Permission::Mute,
+                Permission::Ban,
+                Permission::EditNicknames,
+                Permission::PinMessages,
+                Permission::MentionAll,
+                Permission::ReviewReports,
+                Permission::BypassSlowMode,
+            ],
+            built_in: true,
+        },
+        Role {
+            id: ROLE_MEMBER.to_string(),
+            name: "成员".to_string(),
+            rank: 0,
+            permissions: Vec::new(),
+            built_in: true,
+        },
+    ]
+}
+
+// 用户的角色；角色不存在时按普通成员处理
+fn role_of<'a>(state: &'a AppState, user_id: &str) -> Option<&'a Role> {
+    let user = state.users.get(user_id)?;
+    state.roles.iter().find(|r| r.id == user.role)
+        .or_else(|| state.roles.iter().find(|r| r.id == ROLE_MEMBER))
+}
+
+fn rank_of(state: &AppState, user_id: &str) -> u32 {
+    role_of(state, user_id).map(|r| r.rank).unwrap_or(0)
+}
+
+pub fn is_admin(state: &AppState, user_id: &str) -> bool {
+    state.users.get(user_id).map(|u| u.role == ROLE_ADMIN).unwrap_or(false)
+}
+
+pub fn has_permission(state: &AppState, user_id: &str, permission: Permission) -> bool {
+    match role_of(state, user_id) {
+        Some(role) => role.id == ROLE_ADMIN || role.permissions.contains(&permission),
+        None => false,
+    }
+}
+
+// 拥有任一管理权限，可以创建带 Moderate 范围的访问令牌；免慢速模式、@全体成员等不算
+pub fn can_moderate(state: &AppState, user_id: &str) -> bool {
+    MODERATION_PERMISSIONS.iter().any(|p| has_permission(state, user_id, *p))
+}
+
+// 对他人执行操作：需要该权限，且对方等级低于自己；对方账号不存在时等级按 0 计算
+pub fn can_act_on(state: &AppState, operator_id: &str, target_id: &str, permission: Permission) -> bool {
+    has_permission(state, operator_id, permission)
+        && (is_admin(state, operator_id) || rank_of(state, operator_id) > rank_of(state, target_id))
+}
+
+// 拥有指定权限的用户，用于发送只给管理人员的通知
+pub fn users_with_permission(state: &AppState, permission: Permission) -> Vec<String> {
+    state.users.values()
+        .filter(|u| has_permission(state, &u.id, permission))
+        .map(|u| u.id.clone())
+        .collect()
+}
+
+// 防止越权：非管理员只能管理等级低于自己、权限不超过自己的角色
+fn check_role_grant(state: &AppState, operator_id: &str, rank: u32, permissions: &[Permission]) -> Result<(), String> {
+    if rank >= ADMIN_RANK {
+        return Err(format!("角色等级需小于{}", ADMIN_RANK));
+    }
+    if is_admin(state, operator_id) {
+        return Ok(());
+    }
+    if rank >= rank_of(state, operator_id) {
+        return Err("只能管理等级低于自己的角色".to_string());
+    }
+    if permissions.iter().any(|p| !has_permission(state, operator_id, *p)) {
+        return Err("不能授予自己没有的权限".to_string());
+    }
+    Ok(())
+}
+
+fn normalize_permissions(permissions: &[Permission]) -> Vec<Permission> {
+    ALL_PERMISSIONS.iter().filter(|p| permissions.contains(p)).copied().collect()
+}
+
+fn validate_role_name(state: &AppState, name: &str, role_id: Option<&str>) -> Result<String, String> {
+    let name = name.trim();
+    if name.is_empty() || name.chars().count() > MAX_ROLE_NAME_CHARS {
+        return Err(format!("角色名称不能为空且不超过{}个字符", MAX_ROLE_NAME_CHARS));
+    }
+    if state.roles.iter().any(|r| r.name == name && Some(r.id.as_str()) != role_id) {
+        return Err("角色名称已存在".to_string());
+    }
+    Ok(name.to_string())
+}
+
+// 修改用户的角色；管理员角色不能通过这里授予或撤销
+pub fn set_user_role(operator_id: &str, target_id: &str, role_id: &str, reason: Option<&str>) -> Result<(), String> {
+    let reason = crate::audit::normalize_reason(reason)?;
+    let mut state = APP_STATE.lock().unwrap();
+    if operator_id == target_id {
+        return Err("不能修改自己的角色".to_string());
+    }
+    if !can_act_on(&state, operator_id, target_id, Permission::ManageRoles) {
+        return Err("无权操作".to_string());
+    }
+
+    let role = state.roles.iter().find(|r| r.id == role_id)
+        .ok_or_else(|| "角色不存在".to_string())?;
+    if role.id == ROLE_ADMIN {
+        return Err("不能授予管理员角色".to_string());
+    }
+    if !is_admin(&state, operator_id) && role.rank >= rank_of(&state, operator_id) {
+        return Err("只能授予等级低于自己的角色".to_string());
+    }
+
+    let user = state.users.get_mut(target_id).ok_or_else(|| "用户不存在".to_string())?;
+    if user.role == ROLE_ADMIN {
+        return Err("不能修改管理员的角色".to_string());
+    }
+    if user.role == role_id {
+        return Err("用户已是该角色".to_string());
+    }
+    let old_role = std::mem::replace(&mut user.role, role_id.to_string());
+    let target = user.clone();
+    state.save_users();
+
+    crate::audit::record(&mut state, operator_id, AuditAction::SetUserRole, Some(&target), serde_json::json!({
+        "old_role": old_role,
+        "new_role": role_id,
+    }), reason);
+    drop(state);
+
+    // 广播权限变更
+    crate::websocket::broadcast_role_changed(target_id, &old_role, role_id);
+
+    Ok(())
+}
+
+// 移除角色，拥有该角色的用户改为普通成员，返回这些用户的ID
+fn remove_role(state: &mut AppState, role_id: &str) -> Vec<String> {
+    state.roles.retain(|r| r.id != role_id);
+
+    let mut affected_user_ids = Vec::new();
+    for user in state.users.values_mut().filter(|u| u.role == role_id) {
+        user.role = ROLE_MEMBER.to_string();
+        affected_user_ids.push(user.id.clone());
+    }
+    affected_user_ids
+}
+
+// 所有角色和可用的权限，登录用户都可以查看，用于显示角色名称
+pub async fn get_roles(req: HttpRequest) -> Result<HttpResponse> {
+    if let Some(token) = crate::auth::request_token(&req) {
+        let state = APP_STATE.lock().unwrap();
+
+        if state.token_user_id(&token).is_some() {
+            let mut roles = state.roles.clone();
+            roles.sort_by(|a, b| b.rank.cmp(&a.rank).then_with(|| a.name.cmp(&b.name)));
+            return Ok(HttpResponse::Ok().json(ApiResponse::success(RoleList {
+                roles,
+                permissions: ALL_PERMISSIONS,
+            })));
+        }
+    }
+
+    Ok(HttpResponse::Unauthorized().json(ApiResponse::<()>::error(
+        "未登录".to_string()
+    )))
+}
+
+pub async fn create_role(
+    req: HttpRequest,
+    body: web::Json<CreateRoleRequest>,
+) -> Result<HttpResponse> {
+    if let Some(operator_id) = crate::auth::permitted_user_id(&req, Permission::ManageRoles) {
+        let reason = match crate::audit::normalize_reason(body.reason.as_deref()) {
+            Ok(reason) => reason,
+            Err(e) => return Ok(HttpResponse::Ok().json(ApiResponse::<()>::error(e))),
+        };
+        let mut state = APP_STATE.lock().unwrap();
+
+        let name = match validate_role_name(&state, &body.name, None) {
+            Ok(name) => name,
+            Err(e) => return Ok(HttpResponse::Ok().json(ApiResponse::<()>::error(e))),
+        };
+        let permissions = normalize_permissions(&body.permissions);
+        if let Err(e) = check_role_grant(&state, &operator_id, body.rank, &permissions) {
+            return Ok(HttpResponse::Ok().json(ApiResponse::<()>::error(e)));
+        }
+
+        let role = Role {
+            id: Uuid::new_v4().to_string(),
+            name,
+            rank: body.rank,
+            permissions,
+            built_in: false,
+        };
+        state.roles.push(role.clone());
+        state.save_roles();
+
+        crate::audit::record(&mut state, &operator_id, AuditAction::CreateRole, None, serde_json::json!({
+            "role": role,
+        }), reason);
+
+        return Ok(HttpResponse::Ok().json(ApiResponse::success(role)));
+    }
+
+    Ok(HttpResponse::Unauthorized().json(ApiResponse::<()>::error(
+        "无权操作".to_string()
+    )))
+}
+
+// 管理员角色不能修改；内置角色可以修改名称和权限，不能修改等级
+pub async fn update_role(
+    req: HttpRequest,
+    body: web::Json<UpdateRoleRequest>,
+) -> Result<HttpResponse> {
+    if let Some(operator_id) = crate::auth::permitted_user_id(&req, Permission::ManageRoles) {
+        let reason = match crate::audit::normalize_reason(body.reason.as_deref()) {
+            Ok(reason) => reason,
+            Err(e) => return Ok(HttpResponse::Ok().json(ApiResponse::<()>::error(e))),
+        };
+        let mut state = APP_STATE.lock().unwrap();
+
+        let old_role = match state.roles.iter().find(|r| r.id == body.role_id) {
+            Some(role) if role.id == ROLE_ADMIN => {
+                return Ok(HttpResponse::Ok().json(ApiResponse::<()>::error(
+                    "管理员角色不能修改".to_string()
+                )));
+            }
+            Some(role) => role.clone(),
+            None => {
+                return Ok(HttpResponse::Ok().json(ApiResponse::<()>::error(
+                    "角色不存在".to_string()
+                )));
+            }
+        };
+        if old_role.built_in && body.rank.map(|rank| rank != old_role.rank).unwrap_or(false) {
+            return Ok(HttpResponse::Ok().json(ApiResponse::<()>::error(
+                "内置角色不能修改等级".to_string()
+            )));
+        }
+
+        let name = match &body.name {
+            Some(name) => match validate_role_name(&state, name, Some(&old_role.id)) {
+                Ok(name) => name,
+                Err(e) => return Ok(HttpResponse::Ok().json(ApiResponse::<()>::error(e))),
+            },
+            None => old_role.name.clone(),
+        };
+        let rank = body.rank.unwrap_or(old_role.rank);
+        let permissions = body.permissions.as_deref()
+            .map(normalize_permissions)
+            .unwrap_or_else(|| old_role.permissions.clone());
+        // 修改前后都必须在自己的管理范围内
+        let check = check_role_grant(&state, &operator_id, old_role.rank, &old_role.permissions)
+            .and_then(|_| check_role_grant(&state, &operator_id, rank, &permissions));
+        if let Err(e) = check {
+            return Ok(HttpResponse::Ok().json(ApiResponse::<()>::error(e)));
+        }
+
+        let role = Role {
+            name,
+            rank,
+            permissions,
+            ..old_role.clone()
+        };
+        if let Some(existing) = state.roles.iter_mut().find(|r| r.id == role.id) {
+            *existing = role.clone();
+        }
+        state.save_roles();
+
+        crate::audit::record(&mut state, &operator_id, AuditAction::UpdateRole, None, serde_json::json!({
+            "old_role": old_role,
+            "new_role": role,
+        }), reason);
+
+        return Ok(HttpResponse::Ok().json(ApiResponse::success(role)));
+    }
+
+    Ok(HttpResponse::Unauthorized().json(ApiResponse::<()>::error(
+        "无权操作".to_string()
+    )))
+}
+
+// 删除自定义角色，拥有该角色的用户改为普通成员
+pub async fn delete_role(
+    req: HttpRequest,
+    body: web::Json<DeleteRoleRequest>,
+) -> Result<HttpResponse> {
+    if let Some(operator_id) = crate::auth::permitted_user_id(&req, Permission::ManageRoles) {
+        let reason = match crate::audit::normalize_reason(body.reason.as_deref()) {
+            Ok(reason) => reason,
+            Err(e) => return Ok(HttpResponse::Ok().json(ApiResponse::<()>::error(e))),
+        };
+        let mut state = APP_STATE.lock().unwrap();
+
+        let role = match state.roles.iter().find(|r| r.id == body.role_id) {
+            Some(role) if role.built_in => {
+                return Ok(HttpResponse::Ok().json(ApiResponse::<()>::error(
+                    "内置角色不能删除".to_string()
+                )));
+            }
+            Some(role) => role.clone(),
+            None => {
+                return Ok(HttpResponse::Ok().json(ApiResponse::<()>::error(
+                    "角色不存在".to_string()
+                )));
+            }
+        };
+        if let Err(e) = check_role_grant(&state, &operator_id, role.rank, &role.permissions) {
+            return Ok(HttpResponse::Ok().json(ApiResponse::<()>::error(e)));
+        }
+
+        let affected_user_ids = remove_role(&mut state, &role.id);
+        state.save_roles();
+        if !affected_user_ids.is_empty() {
+            state.save_users();
+        }
+
+        crate::audit::record(&mut state, &operator_id, AuditAction::DeleteRole, None, serde_json::json!({
+            "role": role,
+            "affected_user_ids": affected_user_ids,
+        }), reason);
+        drop(state);
+
+        for user_id in &affected_user_ids {
+            crate::websocket::broadcast_role_changed(user_id, &role.id, ROLE_MEMBER);
+        }
+
+        return Ok(HttpResponse::Ok().json(ApiResponse::success("角色已删除")));
+    }
+
+    Ok(HttpResponse::Unauthorized().json(ApiResponse::<()>::error(
+        "无权操作".to_string()
+    )))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 按旧版本 users.json 的格式构造用户，role 为原来的角色名
+    fn user(id: &str, role: &str) -> User {
+        serde_json::from_value(serde_json::json!({
+            "id": id,
+            "username": id,
+            "email": format!("{}@example.com", id),
+            "password_hash": "",
+            "avatar": null,
+            "display_name": null,
+            "role": role,
+            "status": "Active",
+            "created_at": "2024-01-01T00:00:00Z",
+            "last_ips": [],
+            "muted_until": null,
+        }))
+        .unwrap()
+    }
+
+    fn role(id: &str, rank: u32, permissions: &[Permission]) -> Role {
+        Role {
+            id: id.to_string(),
+            name: id.to_string(),
+            rank,
+            permissions: permissions.to_vec(),
+            built_in: false,
+        }
+    }
+
+    fn state(users: Vec<User>, roles: Vec<Role>) -> AppState {
+        let mut state = AppState::new();
+        state.users = users.into_iter().map(|u| (u.id.clone(), u)).collect();
+        state.roles.extend(roles);
+        state
+    }
+
+    #[test]
+    fn legacy_roles_map_to_builtin_roles() {
+        let state = state(vec![user("admin", "Admin"), user("deputy", "DeputyAdmin"), user("member", "Member")], Vec::new());
+
+        assert!(is_admin(&state, "admin"));
+        assert!(ALL_PERMISSIONS.iter().all(|p| has_permission(&state, "admin", *p)));
+        assert!(!is_admin(&state, "deputy"));
+        assert!(has_permission(&state, "deputy", Permission::Ban));
+        assert!(!has_permission(&state, "deputy", Permission::ManageRoles));
+        assert!(ALL_PERMISSIONS.iter().all(|p| !has_permission(&state, "member", *p)));
+        assert_eq!(rank_of(&state, "deputy"), DEPUTY_ADMIN_RANK);
+        assert_eq!(rank_of(&state, "member"), 0);
+    }
+
+    #[test]
+    fn unknown_role_falls_back_to_member() {
+        let state = state(vec![user("ghost", "deleted-role")], Vec::new());
+
+        assert_eq!(rank_of(&state, "ghost"), 0);
+        assert!(!has_permission(&state, "ghost", Permission::RecallAny));
+    }
+
+    #[test]
+    fn non_admin_cannot_act_on_equal_or_higher_rank() {
+        let state = state(
+            vec![user("admin", "Admin"), user("d1", "DeputyAdmin"), user("d2", "DeputyAdmin"), user("m", "Member")],
+            Vec::new(),
+        );
+
+        assert!(can_act_on(&state, "d1", "m", Permission::Ban));
+        assert!(!can_act_on(&state, "d1", "d2", Permission::Ban));
+        assert!(!can_act_on(&state, "d1", "admin", Permission::Ban));
+        // 没有该权限时对低等级用户也不能操作
+        assert!(!can_act_on(&state, "d1", "m", Permission::ManageUsers));
+        assert!(can_act_on(&state, "admin", "d1", Permission::Ban));
+    }
+
+    #[test]
+    fn non_admin_cannot_grant_missing_permissions_or_higher_rank() {
+        let manager = role("manager", 60, &[Permission::ManageRoles, Permission::Mute]);
+        let state = state(vec![user("admin", "Admin"), user("mgr", "manager")], vec![manager]);
+
+        assert!(check_role_grant(&state, "mgr", 10, &[Permission::Mute]).is_ok());
+        assert!(check_role_grant(&state, "mgr", 10, &[Permission::Ban]).is_err());
+        assert!(check_role_grant(&state, "mgr", 60, &[]).is_err());
+        assert!(check_role_grant(&state, "mgr", 61, &[]).is_err());
+        assert!(check_role_grant(&state, "admin", 99, ALL_PERMISSIONS).is_ok());
+        assert!(check_role_grant(&state, "admin", ADMIN_RANK, &[]).is_err());
+    }
+
+    #[test]
+    fn deleting_role_demotes_members() {
+        let helper = role("helper", 20, &[Permission::Mute]);
+        let mut state = state(vec![user("h1", "helper"), user("h2", "helper"), user("m", "Member")], vec![helper]);
+
+        let mut affected = remove_role(&mut state, "helper");
+        affected.sort();
+
+        assert_eq!(affected, vec!["h1", "h2"]);
+        assert!(state.roles.iter().all(|r| r.id != "helper"));
+        assert_eq!(state.users["h1"].role, ROLE_MEMBER);
+        assert!(!has_permission(&state, "h1", Permission::Mute));
+    }
+
+    #[test]
+    fn moderate_scope_requires_a_moderation_permission() {
+        let slow = role("slow", 10, &[Permission::BypassSlowMode, Permission::MentionAll]);
+        let helper = role("helper", 20, &[Permission::Mute]);
+        let state = state(
+            vec![user("admin", "Admin"), user("d", "DeputyAdmin"), user("s", "slow"), user("h", "helper"), user("m", "Member")],
+            vec![slow, helper],
+        );
+
+        assert!(can_moderate(&state, "admin"));
+        assert!(can_moderate(&state, "d"));
+        assert!(can_moderate(&state, "h"));
+        assert!(!can_moderate(&state, "s"));
+        assert!(!can_moderate(&state, "m"));
+    }
+}
\ No newline at end of file
@@ -0,0 +1,146 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::Duration as StdDuration;
+use chrono::{DateTime, Duration, Utc};
+use lazy_static::lazy_static;
+use schemars::JsonSchema;
+use serde::Serialize;
+
+// 超过该时间没有任何操作视为离开
+const IDLE_AFTER_MINUTES: i64 = 5;
+// 同一用户两次“正在输入”广播的最小间隔
+const TYPING_INTERVAL_SECONDS: i64 = 3;
+const IDLE_CHECK_INTERVAL_SECONDS: u64 = 30;
+
+#[derive(Debug, Clone, Serialize, PartialEq, JsonSchema)]
+pub enum PresenceStatus {
+    Online,
+    Idle,
+    Offline,
+}
+
+#[derive(Debug, Clone, Serialize, JsonSchema)]
+pub struct PresenceInfo {
+    pub user_id: String,
+    pub status: PresenceStatus,
+    pub last_active: DateTime<Utc>,
+}
+
+struct PresenceEntry {
+    connections: usize,
+    status: PresenceStatus,
+    last_active: DateTime<Utc>,
+    last_typing: Option<DateTime<Utc>>,
+}
+
+lazy_static! {
+    static ref PRESENCE: Arc<Mutex<HashMap<String, PresenceEntry>>> =
+        Arc::new(Mutex::new(HashMap::new()));
+}
+
+fn info(user_id: &str, entry: &PresenceEntry) -> PresenceInfo {
+    PresenceInfo {
+        user_id: user_id.to_string(),
+        status: entry.status.clone(),
+        last_active: entry.last_active,
+    }
+}
+
+// 建立连接时调用，首次上线或从离开状态恢复时广播
+pub fn connected(user_id: &str) {
+    let mut presence = PRESENCE.lock().unwrap();
+    let entry = presence.entry(user_id.to_string()).or_insert_with(|| PresenceEntry {
+        connections: 0,
+        status: PresenceStatus::Offline,
+        last_active: Utc::now(),
+        last_typing: None,
+    });
+    entry.connections += 1;
+    entry.last_active = Utc::now();
+
+    if entry.status != PresenceStatus::Online {
+        entry.status = PresenceStatus::Online;
+        let info = info(user_id, entry);
+        drop(presence);
+        crate::websocket::broadcast_presence_changed(&info);
+    }
+}
+
+// 连接断开时调用，最后一个连接关闭后变为离线并移除记录
+pub fn disconnected(user_id: &str) {
+    let mut presence = PRESENCE.lock().unwrap();
+    if let Some(entry) = presence.get_mut(user_id) {
+        entry.connections = entry.connections.saturating_sub(1);
+        if entry.connections == 0 {
+            entry.status = PresenceStatus::Offline;
+            let info = info(user_id, entry);
+            presence.remove(user_id);
+            drop(presence);
+            crate::websocket::broadcast_presence_changed(&info);
+        }
+    }
+}
+
+// 记录用户活动，离开状态的用户恢复为在线
+pub fn touch(user_id: &str) {
+    let mut presence = PRESENCE.lock().unwrap();
+    if let Some(entry) = presence.get_mut(user_id) {
+        entry.last_active = Utc::now();
+        if entry.status == PresenceStatus::Idle {
+            entry.status = PresenceStatus::Online;
+            let info = info(user_id, entry);
+            drop(presence);
+            crate::websocket::broadcast_presence_changed(&info);
+        }
+    }
+}
+
+// 返回是否应当广播本次“正在输入”
+pub fn allow_typing(user_id: &str) -> bool {
+    let mut presence = PRESENCE.lock().unwrap();
+    if let Some(entry) = presence.get_mut(user_id) {
+        let now = Utc::now();
+        let allowed = entry.last_typing
+            .map(|t| now.signed_duration_since(t) >= Duration::seconds(TYPING_INTERVAL_SECONDS))
+            .unwrap_or(true);
+        if allowed {
+            entry.last_typing = Some(now);
+        }
+        return allowed;
+    }
+    false
+}
+
+pub fn online_users() -> Vec<PresenceInfo> {
+    let presence = PRESENCE.lock().unwrap();
+    presence.iter()
+        .filter(|(_, entry)| entry.status != PresenceStatus::Offline)
+        .map(|(user_id, entry)| info(user_id, entry))
+        .collect()
+}
+
+// 定期检查长时间无操作的用户，标记为离开
+pub async fn run_idle_checker() {
+    let mut interval = tokio::time::interval(StdDuration::from_secs(IDLE_CHECK_INTERVAL_SECONDS));
+    loop {
+        interval.tick().await;
+
+        let now = Utc::now();
+        let mut changed = Vec::new();
+        {
+            let mut presence = PRESENCE.lock().unwrap();
+            for (user_id, entry) in presence.iter_mut() {
+                if entry.status == PresenceStatus::Online
+                    && now.signed_duration_since(entry.last_active) >= Duration::minutes(IDLE_AFTER_MINUTES)
+                {
+                    entry.status = PresenceStatus::Idle;
+                    changed.push(info(user_id, entry));
+                }
+            }
+        }
+
+        for info in &changed {
+            crate::websocket::broadcast_presence_changed(info);
+        }
+    }
+}
\ No newline at end of file
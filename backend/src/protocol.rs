@@ -0,0 +1,192 @@
+// WebSocket 协议定义：服务端事件与客户端消息的类型，以及据此生成的 JSON Schema
+use chrono::{DateTime, Utc};
+use schemars::{JsonSchema, schema_for};
+use serde::{Deserialize, Serialize};
+use crate::models::{Attachment, MessageWithUser, PurgeMode, Reactions, UnreadSummary};
+use crate::presence::PresenceInfo;
+
+// 当前协议版本；事件格式发生不兼容变化时递增，并加入 SUPPORTED_PROTOCOL_VERSIONS
+pub const PROTOCOL_VERSION: u32 = 1;
+const SUPPORTED_PROTOCOL_VERSIONS: &[u32] = &[1];
+
+// 服务端推送的全部事件，序列化为 {"event": "...", "data": {...}}
+#[derive(Debug, Clone, Serialize, JsonSchema)]
+#[serde(tag = "event", content = "data", rename_all = "snake_case")]
+pub enum ServerEvent {
+    // 连接建立后的第一条消息
+    Hello {
+        connection_id: String,
+        protocol_version: u32,
+        epoch: String,
+        last_seq: u64,
+    },
+    Ack {
+        request_id: Option<String>,
+        data: serde_json::Value,
+    },
+    Error {
+        request_id: Option<String>,
+        message: String,
+    },
+    ResyncRequired {
+        epoch: String,
+        last_seq: u64,
+    },
+    UserBanned {
+        user_id: String,
+    },
+    UserUnbanned {
+        user_id: String,
+    },
+    // 禁言和解除禁言只发给被禁言的用户和管理员、次管理员
+    UserMuted {
+        user_id: String,
+        muted_until: DateTime<Utc>,
+        reason: Option<String>,
+        muted_by: String,
+    },
+    UserUnmuted {
+        user_id: String,
+        unmuted_by: Option<String>, // None 表示禁言到期自动解除
+    },
+    UserDeleted {
+        user_id: String,
+    },
+    // 角色为角色ID，内置角色为 Admin、DeputyAdmin、Member
+    RoleChanged {
+        user_id: String,
+        old_role: String,
+        new_role: String,
+    },
+    DisplayNameChanged {
+        user_id: String,
+        old_display_name: Option<String>,
+        new_display_name: Option<String>,
+    },
+    AvatarChanged {
+        user_id: String,
+        avatar: Option<String>,
+    },
+    NewMessage(MessageWithUser),
+    #[serde(rename = "message_recalled_with_data")]
+    MessageRecalled(MessageWithUser),
+    // 批量清理某用户的消息，客户端据 mode 标记为已撤回或直接移除
+    MessagesPurged {
+        user_id: String,
+        message_ids: Vec<String>,
+        mode: PurgeMode,
+        purged_by: String,
+    },
+    MessageReacted {
+        message_id: String,
+        reactions: Reactions,
+    },
+    Mentioned(MessageWithUser),
+    // 已随消息发出时发给所有人，否则只发给上传者
+    AttachmentUpdated(Attachment),
+    ReadMarkerUpdated(UnreadSummary),
+    Typing {
+        user_id: String,
+    },
+    PresenceChanged(PresenceInfo),
+    TopicChanged {
+        topic: Option<String>,
+        changed_by: String,
+    },
+    MessagePinned {
+        message_id: String,
+        pinned: bool,
+        changed_by: String,
+    },
+    // 斜杠命令的回复，只发给调用者，不记入事件日志
+    CommandReply {
+        command: String,
+        text: String,
+    },
+    // 发给注册了该命令的机器人，机器人通过 /api/command-reply 回复
+    CommandInvoked {
+        invocation_id: String,
+        command: String,
+        args: String,
+        user_id: String,
+    },
+}
+
+// 实际发送的消息：记入事件日志的事件带有递增的 seq
+#[derive(Serialize, JsonSchema)]
+pub struct ServerFrame<'a> {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub seq: Option<u64>,
+    #[serde(flatten)]
+    pub event: &'a ServerEvent,
+}
+
+impl ServerEvent {
+    // 不带序号的序列化，用于握手、回复和临时事件
+    pub fn to_text(&self) -> String {
+        serde_json::to_string(&ServerFrame { seq: None, event: self }).unwrap()
+    }
+}
+
+// 客户端发来的消息，request_id 由客户端生成，原样带回 ack/error 中
+#[derive(Deserialize, JsonSchema)]
+pub struct ClientFrame {
+    pub request_id: Option<String>,
+    #[serde(flatten)]
+    pub action: ClientAction,
+}
+
+#[derive(Deserialize, JsonSchema)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ClientAction {
+    Send {
+        content: String,
+        #[serde(default)]
+        attachment_ids: Vec<String>,
+    },
+    Recall {
+        message_id: String,
+        #[serde(default)]
+        reason: Option<String>,
+    },
+    React {
+        message_id: String,
+        emoji: String,
+    },
+    Typing,
+    MarkRead {
+        message_id: String,
+    },
+    Resume {
+        last_seq: u64,
+        epoch: Option<String>,
+    },
+}
+
+// 客户端在连接地址上用 ?protocol=1,2 声明支持的版本，取双方都支持的最高版本；
+// 未声明时使用当前版本
+pub fn negotiate(requested: Option<&str>) -> Option<u32> {
+    let requested = match requested {
+        Some(requested) => requested,
+        None => return Some(PROTOCOL_VERSION),
+    };
+
+    requested.split(',')
+        .filter_map(|v| v.trim().parse::<u32>().ok())
+        .filter(|v| SUPPORTED_PROTOCOL_VERSIONS.contains(v))
+        .max()
+}
+
+pub fn supported_versions() -> &'static [u32] {
+    SUPPORTED_PROTOCOL_VERSIONS
+}
+
+// 供机器人和第三方客户端校验消息格式
+pub fn schema() -> serde_json::Value {
+    serde_json::json!({
+        "protocol_version": PROTOCOL_VERSION,
+        "supported_versions": SUPPORTED_PROTOCOL_VERSIONS,
+        "server": schema_for!(ServerFrame),
+        "client": schema_for!(ClientFrame),
+    })
+}
\ No newline at end of file
@@ -0,0 +1,263 @@
+// WebSocket 被代理拦截时的替代通道：SSE 与长轮询，与 /api/ws 共用事件分发和序号
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use actix_web::{web, HttpRequest, HttpResponse, Result};
+use actix_web::web::Bytes;
+use futures_util::stream;
+use lazy_static::lazy_static;
+use serde::Deserialize;
+use crate::APP_STATE;
+use crate::hub::{self, Connection, Outbound};
+use crate::models::*;
+use crate::protocol::{self, ServerEvent};
+
+// 定期发送 SSE 注释行，防止代理因连接空闲而断开
+const SSE_KEEPALIVE_INTERVAL: Duration = Duration::from_secs(15);
+// 长轮询的等待时间（秒）
+const DEFAULT_POLL_TIMEOUT: u64 = 25;
+const MAX_POLL_TIMEOUT: u64 = 30;
+// 超过该时间没有再次轮询时移除长轮询的连接，需要大于 MAX_POLL_TIMEOUT
+const POLL_IDLE_TIMEOUT: Duration = Duration::from_secs(60);
+const POLL_REAP_INTERVAL: Duration = Duration::from_secs(15);
+
+struct PollSession {
+    connection: Connection,
+    last_poll: Instant,
+}
+
+lazy_static! {
+    // connection_id -> 长轮询连接
+    static ref POLL_SESSIONS: Mutex<HashMap<String, PollSession>> = Mutex::new(HashMap::new());
+}
+
+fn session_user_id(req: &HttpRequest) -> Option<String> {
+    let token = crate::auth::request_token(req)?;
+    APP_STATE.lock().unwrap().token_user_id(&token).cloned()
+}
+
+#[derive(Deserialize)]
+struct Sequenced {
+    seq: Option<u64>,
+}
+
+fn event_seq(msg: &str) -> Option<u64> {
+    serde_json::from_str::<Sequenced>(msg).ok().and_then(|event| event.seq)
+}
+
+// 响应流被丢弃（客户端断开或连接被关闭）时移除连接
+struct SseConnection {
+    connection: Connection,
+    keepalive: tokio::time::Interval,
+}
+
+impl Drop for SseConnection {
+    fn drop(&mut self) {
+        hub::unregister(&self.connection);
+    }
+}
+
+// SSE：每个事件作为一条 data 消息，内容与 WebSocket 推送的 JSON 完全相同
+pub async fn event_stream(
+    req: HttpRequest,
+    query: web::Query<EventStreamQuery>,
+) -> Result<HttpResponse> {
+    let user_id = match session_user_id(&req) {
+        Some(user_id) => user_id,
+        None => {
+            return Ok(HttpResponse::Unauthorized().json(ApiResponse::<()>::error(
+                "未登录".to_string()
+            )));
+        }
+    };
+
+    let protocol_version = match protocol::negotiate(query.protocol.as_deref()) {
+        Some(version) => version,
+        None => {
+            return Ok(HttpResponse::BadRequest().json(ApiResponse::<()>::error(format!(
+                "不支持的协议版本，服务端支持：{:?}",
+                protocol::supported_versions()
+            ))));
+        }
+    };
+
+    let connection = hub::register(&user_id);
+    let hello = ServerEvent::Hello {
+        connection_id: connection.id.clone(),
+        protocol_version,
+        epoch: crate::event_log::epoch().to_string(),
+        last_seq: crate::event_log::last_seq(),
+    };
+    hub::send_to_connection(&connection.id, hello.to_text());
+    crate::presence::connected(&user_id);
+
+    // EventSource 自动重连时会在 Last-Event-ID 头中带上最后收到的序号，优先于地址中的 last_seq
+    let last_event_id = req.headers()
+        .get("Last-Event-ID")
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.trim().parse::<u64>().ok());
+    if let Some(last_seq) = last_event_id.or(query.last_seq) {
+        hub::resume(&connection, last_seq, query.epoch.as_deref());
+    }
+
+    let state = SseConnection {
+        connection,
+        keepalive: tokio::time::interval(SSE_KEEPALIVE_INTERVAL),
+    };
+    let body = stream::unfold(state, |mut state| async move {
+        let chunk = tokio::select! {
+            item = state.connection.outbox.next() => match item {
+                // 带序号的事件同时作为 SSE 的 id，浏览器重连时会带回来
+                Some(Outbound::Text(msg)) => match event_seq(&msg) {
+                    Some(seq) => format!("id: {}\ndata: {}\n\n", seq, msg),
+                    None => format!("data: {}\n\n", msg),
+                },
+                Some(Outbound::Close) | None => return None,
+            },
+            _ = state.keepalive.tick() => ": ping\n\n".to_string(),
+        };
+        Some((Ok::<_, actix_web::Error>(Bytes::from(chunk)), state))
+    });
+
+    Ok(HttpResponse::Ok()
+        .content_type("text/event-stream")
+        .insert_header(("Cache-Control", "no-cache"))
+        .insert_header(("X-Accel-Buffering", "no"))
+        .streaming(body))
+}
+
+// 长轮询：每个客户端在两次请求之间保留一个连接，和 WebSocket 一样接收实时事件（包括正在输入、命令回复等临时事件）并计入在线状态。
+// 首次请求不带 connection_id，返回的 hello 事件中带有连接编号，之后的请求带上它；
+// 连接过期后带上 last_seq 重新请求即可补发错过的事件
+pub async fn poll_events(
+    req: HttpRequest,
+    query: web::Query<PollQuery>,
+) -> Result<HttpResponse> {
+    let user_id = match session_user_id(&req) {
+        Some(user_id) => user_id,
+        None => {
+            return Ok(HttpResponse::Unauthorized().json(ApiResponse::<()>::error(
+                "未登录".to_string()
+            )));
+        }
+    };
+
+    let protocol_version = match protocol::negotiate(query.protocol.as_deref()) {
+        Some(version) => version,
+        None => {
+            return Ok(HttpResponse::BadRequest().json(ApiResponse::<()>::error(format!(
+                "不支持的协议版本，服务端支持：{:?}",
+                protocol::supported_versions()
+            ))));
+        }
+    };
+
+    let existing = query.connection_id.as_ref().and_then(|connection_id| {
+        let mut sessions = POLL_SESSIONS.lock().unwrap();
+        sessions.get_mut(connection_id)
+            .filter(|session| session.connection.user_id == user_id)
+            .map(|session| {
+                session.last_poll = Instant::now();
+                session.connection.clone()
+            })
+    });
+    let mut position = query.last_seq.unwrap_or(0);
+    let mut resync_required = false;
+    let connection = match existing {
+        Some(connection) => connection,
+        None => {
+            let connection = hub::register(&user_id);
+            let hello = ServerEvent::Hello {
+                connection_id: connection.id.clone(),
+                protocol_version,
+                epoch: crate::event_log::epoch().to_string(),
+                last_seq: crate::event_log::last_seq(),
+            };
+            hub::send_to_connection(&connection.id, hello.to_text());
+            POLL_SESSIONS.lock().unwrap().insert(connection.id.clone(), PollSession {
+                connection: connection.clone(),
+                last_poll: Instant::now(),
+            });
+            crate::presence::connected(&user_id);
+
+            match query.last_seq {
+                Some(last_seq) => {
+                    let result = hub::resume(&connection, last_seq, query.epoch.as_deref());
+                    resync_required = result["resync_required"].as_bool().unwrap_or(false);
+                }
+                None => position = crate::event_log::last_seq(),
+            }
+            connection
+        }
+    };
+
+    // 等到第一条消息或超时，再取出队列中已有的其余消息一并返回
+    let timeout = Duration::from_secs(query.timeout.unwrap_or(DEFAULT_POLL_TIMEOUT).min(MAX_POLL_TIMEOUT));
+    let mut messages = Vec::new();
+    let mut closed = false;
+    match tokio::time::timeout(timeout, connection.outbox.next()).await {
+        Ok(Some(Outbound::Text(msg))) => messages.push(msg),
+        Ok(Some(Outbound::Close)) | Ok(None) => closed = true,
+        Err(_) => {}
+    }
+    while !closed {
+        match connection.outbox.try_next() {
+            Some(Outbound::Text(msg)) => messages.push(msg),
+            Some(Outbound::Close) => closed = true,
+            None => break,
+        }
+    }
+
+    if closed {
+        POLL_SESSIONS.lock().unwrap().remove(&connection.id);
+        hub::unregister(&connection);
+    } else if let Some(session) = POLL_SESSIONS.lock().unwrap().get_mut(&connection.id) {
+        session.last_poll = Instant::now();
+    }
+
+    let events: Vec<serde_json::Value> = messages.iter()
+        .map(|msg| serde_json::from_str(msg).unwrap())
+        .collect();
+    for event in &events {
+        if let Some(seq) = event.get("seq").and_then(|seq| seq.as_u64()) {
+            position = position.max(seq);
+        }
+        if event.get("event").and_then(|name| name.as_str()) == Some("resync_required") {
+            resync_required = true;
+        }
+    }
+    if resync_required {
+        position = crate::event_log::last_seq();
+    }
+
+    Ok(HttpResponse::Ok().json(ApiResponse::success(PollResponse {
+        connection_id: connection.id.clone(),
+        epoch: crate::event_log::epoch().to_string(),
+        last_seq: position,
+        events,
+        resync_required,
+    })))
+}
+
+// 定期移除超过空闲时间没有再次轮询的长轮询连接
+pub async fn run_poll_reaper() {
+    let mut interval = tokio::time::interval(POLL_REAP_INTERVAL);
+    loop {
+        interval.tick().await;
+
+        let expired: Vec<Connection> = {
+            let mut sessions = POLL_SESSIONS.lock().unwrap();
+            let ids: Vec<String> = sessions.iter()
+                .filter(|(_, session)| session.last_poll.elapsed() >= POLL_IDLE_TIMEOUT)
+                .map(|(id, _)| id.clone())
+                .collect();
+            ids.iter()
+                .filter_map(|id| sessions.remove(id))
+                .map(|session| session.connection)
+                .collect()
+        };
+        for connection in expired {
+            hub::unregister(&connection);
+        }
+    }
+}
\ No newline at end of file
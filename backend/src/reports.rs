@@ -0,0 +1,220 @@
+// 举报：成员举报消息，拥有 review_reports 权限的用户在待处理队列中按消息查看并处理
+use std::collections::HashMap;
+use actix_web::{web, HttpRequest, HttpResponse, Result};
+use chrono::Utc;
+use uuid::Uuid;
+use crate::APP_STATE;
+use crate::models::*;
+
+const MAX_REASON_CHARS: usize = 500;
+// 每个用户同时最多有多少条待处理的举报
+const MAX_OPEN_REPORTS_PER_USER: usize = 20;
+
+fn user_name(state: &AppState, user_id: &str) -> String {
+    state.users.get(user_id)
+        .map(|u| u.display_name.clone().filter(|n| !n.is_empty()).unwrap_or_else(|| u.username.clone()))
+        .unwrap_or_default()
+}
+
+pub async fn report_message(
+    req: HttpRequest,
+    body: web::Json<ReportMessageRequest>,
+) -> Result<HttpResponse> {
+    if let Some(token) = crate::auth::request_token(&req) {
+        let mut state = APP_STATE.lock().unwrap();
+
+        if let Some(user_id) = state.token_user_id(&token).cloned() {
+            let reason = body.reason.trim();
+            if reason.is_empty() || reason.chars().count() > MAX_REASON_CHARS {
+                return Ok(HttpResponse::Ok().json(ApiResponse::<()>::error(
+                    format!("举报原因不能为空且不超过{}个字符", MAX_REASON_CHARS)
+                )));
+            }
+
+            let message = match state.messages.iter().find(|m| m.id == body.message_id && !m.recalled) {
+                Some(message) => message.clone(),
+                None => {
+                    return Ok(HttpResponse::Ok().json(ApiResponse::<()>::error(
+                        "消息不存在或已被撤回".to_string()
+                    )));
+                }
+            };
+            if message.user_id == user_id {
+                return Ok(HttpResponse::Ok().json(ApiResponse::<()>::error(
+                    "不能举报自己的消息".to_string()
+                )));
+            }
+
+            let open_reports: Vec<&MessageReport> = state.reports.iter()
+                .filter(|r| r.reporter_id == user_id && r.resolution.is_none())
+                .collect();
+            if open_reports.iter().any(|r| r.message_id == message.id) {
+                return Ok(HttpResponse::Ok().json(ApiResponse::<()>::error(
+                    "您已举报过这条消息，请等待处理".to_string()
+                )));
+            }
+            if open_reports.len() >= MAX_OPEN_REPORTS_PER_USER {
+                return Ok(HttpResponse::Ok().json(ApiResponse::<()>::error(
+                    "待处理的举报过多，请稍后再试".to_string()
+                )));
+            }
+
+            state.reports.push(MessageReport {
+                id: Uuid::new_v4().to_string(),
+                message_id: message.id,
+                author_id: message.user_id,
+                content: message.content,
+                reporter_id: user_id,
+                reason: reason.to_string(),
+                created_at: Utc::now(),
+                resolution: None,
+                resolved_by: None,
+                resolved_at: None,
+            });
+            state.save_reports();
+
+            return Ok(HttpResponse::Ok().json(ApiResponse::success("举报已提交")));
+        }
+    }
+
+    Ok(HttpResponse::Unauthorized().json(ApiResponse::<()>::error(
+        "未登录".to_string()
+    )))
+}
+
+// 按消息分组，最近被举报的在前；默认只包含待处理的举报
+pub async fn get_moderation_queue(
+    req: HttpRequest,
+    query: web::Query<ModerationQueueQuery>,
+) -> Result<HttpResponse> {
+    if let Some(token) = crate::auth::request_token(&req) {
+        let state = APP_STATE.lock().unwrap();
+
+        if let Some(user_id) = state.token_user_id(&token) {
+            if crate::permissions::has_permission(&state, user_id, Permission::ReviewReports) {
+                let mut groups: HashMap<&str, Vec<&MessageReport>> = HashMap::new();
+                for report in &state.reports {
+                    if query.include_resolved || report.resolution.is_none() {
+                        groups.entry(&report.message_id).or_default().push(report);
+                    }
+                }
+
+                let mut items: Vec<ModerationQueueItem> = groups.into_iter()
+                    .map(|(message_id, reports)| {
+                        let first = reports[0];
+                        let message = state.messages.iter().find(|m| m.id == message_id);
+                        ModerationQueueItem {
+                            message_id,
+                            author_id: &first.author_id,
+                            author_name: user_name(&state, &first.author_id),
+                            content: message
+                                .map(|m| m.original_content.as_deref().unwrap_or(&m.content))
+                                .unwrap_or(&first.content),
+                            recalled: message.map(|m| m.recalled).unwrap_or(false),
+                            deleted: message.is_none(),
+                            timestamp: message.map(|m| m.timestamp),
+                            report_count: reports.len(),
+                            last_reported_at: reports.iter().map(|r| r.created_at).max().unwrap(),
+                            reports,
+                        }
+                    })
+                    .collect();
+                items.sort_by_key(|item| std::cmp::Reverse(item.last_reported_at));
+
+                return Ok(HttpResponse::Ok().json(ApiResponse::success(items)));
+            }
+        }
+    }
+
+    Ok(HttpResponse::Unauthorized().json(ApiResponse::<()>::error(
+        "无权访问".to_string()
+    )))
+}
+
+// 处理一条消息的全部待处理举报；撤回、禁言、封禁沿用对应操作的权限检查
+pub async fn resolve_report(
+    req: HttpRequest,
+    body: web::Json<ResolveReportRequest>,
+) -> Result<HttpResponse> {
+    if let Some(token) = crate::auth::request_token(&req) {
+        let operator_id = {
+            let state = APP_STATE.lock().unwrap();
+            state.token_user_id(&token)
+                .filter(|id| crate::permissions::has_permission(&state, id, Permission::ReviewReports))
+                .cloned()
+        };
+
+        if let Some(operator_id) = operator_id {
+            let reason = match crate::audit::normalize_reason(body.reason.as_deref()) {
+                Ok(reason) => reason,
+                Err(e) => return Ok(HttpResponse::Ok().json(ApiResponse::<()>::error(e))),
+            };
+            let author_id = {
+                let state = APP_STATE.lock().unwrap();
+                state.reports.iter()
+                    .find(|r| r.message_id == body.message_id && r.resolution.is_none())
+                    .map(|r| r.author_id.clone())
+            };
+            let author_id = match author_id {
+                Some(author_id) => author_id,
+                None => {
+                    return Ok(HttpResponse::Ok().json(ApiResponse::<()>::error(
+                        "该消息没有待处理的举报".to_string()
+                    )));
+                }
+            };
+
+            let result = match body.action {
+                ReportResolution::Dismiss => Ok(()),
+                ReportResolution::Recall => {
+                    // 消息已被撤回或删除时直接结案，否则举报会一直无法处理
+                    let pending = APP_STATE.lock().unwrap().messages.iter()
+                        .any(|m| m.id == body.message_id && !m.recalled);
+                    if pending {
+                        crate::chat::recall_message(&operator_id, &body.message_id, true, reason.as_deref())
+                    } else {
+                        Ok(())
+                    }
+                }
+                ReportResolution::MuteAuthor => match body.duration_minutes {
+                    Some(minutes) => {
+                        crate::chat::mute_user(&operator_id, &author_id, minutes, reason.as_deref()).map(|_| ())
+                    }
+                    None => Err("请填写禁言时长".to_string()),
+                },
+                ReportResolution::BanAuthor => {
+                    crate::chat::ban_user(&operator_id, &author_id, body.duration_minutes, reason.as_deref()).map(|_| ())
+                }
+            };
+            if let Err(e) = result {
+                return Ok(HttpResponse::Ok().json(ApiResponse::<()>::error(e)));
+            }
+
+            let mut state = APP_STATE.lock().unwrap();
+            let now = Utc::now();
+            let mut report_ids = Vec::new();
+            for report in state.reports.iter_mut() {
+                if report.message_id == body.message_id && report.resolution.is_none() {
+                    report.resolution = Some(body.action);
+                    report.resolved_by = Some(operator_id.clone());
+                    report.resolved_at = Some(now);
+                    report_ids.push(report.id.clone());
+                }
+            }
+            state.save_reports();
+
+            let author = state.users.get(&author_id).cloned();
+            crate::audit::record(&mut state, &operator_id, AuditAction::ResolveReport, author.as_ref(), serde_json::json!({
+                "message_id": body.message_id,
+                "action": body.action,
+                "report_ids": report_ids,
+            }), reason);
+
+            return Ok(HttpResponse::Ok().json(ApiResponse::success("举报已处理")));
+        }
+    }
+
+    Ok(HttpResponse::Unauthorized().json(ApiResponse::<()>::error(
+        "无权操作".to_string()
+    )))
+}
\ No newline at end of file
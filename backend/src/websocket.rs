@@ -1,208 +1,384 @@
 use actix_ws::{Message as WsMessage, Session};
-use std::collections::HashMap;
-use std::sync::{Arc, Mutex};
-use lazy_static::lazy_static;
-use crate::models::{Message, MessageWithUser, UserRole};
-use serde::{Deserialize, Serialize};
+use std::time::{Duration, Instant};
+use chrono::{DateTime, Utc};
+use crate::models::{Attachment, Message, MessageWithUser, PurgeMode, Reactions, TokenScope, UnreadSummary};
+use crate::presence::PresenceInfo;
+use crate::event_log::Audience;
+use crate::hub::{self, Connection, Outbound};
+use crate::protocol::{ClientAction, ClientFrame, ServerEvent};
 use futures_util::StreamExt;
 
-lazy_static! {
-    static ref CONNECTIONS: Arc<Mutex<HashMap<String, Session>>> = 
-        Arc::new(Mutex::new(HashMap::new()));
-}
+// 服务端定期发送 ping，超过 CLIENT_TIMEOUT 没有收到任何数据则断开
+const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(15);
+const CLIENT_TIMEOUT: Duration = Duration::from_secs(45);
 
 pub async fn handle_websocket(
     user_id: String,
+    protocol_version: u32,
+    scopes: Option<Vec<TokenScope>>, // 通过访问令牌连接时的权限范围
     session: Session,
     mut msg_stream: actix_ws::MessageStream,
 ) {
-    // 添加连接
-    CONNECTIONS.lock().unwrap().insert(user_id.clone(), session.clone());
+    // 添加连接，并启动该连接的写任务
+    let connection = hub::register(&user_id);
+    actix_web::rt::spawn(run_writer(connection.clone(), session.clone()));
+    
+    // 告知客户端当前序号，重连时凭此请求补发
+    let hello = ServerEvent::Hello {
+        connection_id: connection.id.clone(),
+        protocol_version,
+        epoch: crate::event_log::epoch().to_string(),
+        last_seq: crate::event_log::last_seq(),
+    };
+    hub::send_to_connection(&connection.id, hello.to_text());
+    crate::presence::connected(&user_id);
+    
+    let mut session = session;
+    let mut last_heartbeat = Instant::now();
+    let mut interval = tokio::time::interval(HEARTBEAT_INTERVAL);
     
     // 处理消息
-    while let Some(Ok(msg)) = msg_stream.next().await {
-        match msg {
-            WsMessage::Text(text) => {
-                println!("Received from {}: {}", user_id, text);
+    loop {
+        tokio::select! {
+            msg = msg_stream.next() => {
+                let msg = match msg {
+                    Some(Ok(msg)) => msg,
+                    _ => break,
+                };
+                last_heartbeat = Instant::now();
+                
+                match msg {
+                    WsMessage::Text(text) => {
+                        crate::presence::touch(&user_id);
+                        handle_client_frame(&connection, scopes.as_deref(), &text);
+                    }
+                    WsMessage::Ping(bytes) => {
+                        let pong = session.pong(&bytes).await;
+                        if pong.is_err() {
+                            break;
+                        }
+                    }
+                    WsMessage::Close(_) => {
+                        break;
+                    }
+                    _ => {}
+                }
             }
-            WsMessage::Close(_) => {
-                break;
+            _ = interval.tick() => {
+                // 写任务已结束（发送失败或被判定为慢消费者）
+                if connection.outbox.is_closed() {
+                    break;
+                }
+                if Instant::now().duration_since(last_heartbeat) > CLIENT_TIMEOUT {
+                    println!("WebSocket heartbeat timeout: {}", user_id);
+                    break;
+                }
+                if session.ping(b"").await.is_err() {
+                    break;
+                }
             }
-            _ => {}
         }
     }
     
-    // 移除连接
-    CONNECTIONS.lock().unwrap().remove(&user_id);
+    // 移除连接，写任务随之结束并关闭会话
+    hub::unregister(&connection);
 }
 
-#[derive(Serialize, Deserialize)]
-struct WsEvent {
-    event: String,
-    data: serde_json::Value,
+// 按入队顺序把消息写入会话；发送失败或收到关闭指令时结束
+async fn run_writer(connection: Connection, mut session: Session) {
+    while let Some(item) = connection.outbox.next().await {
+        match item {
+            Outbound::Text(msg) => {
+                if session.text(msg.to_string()).await.is_err() {
+                    break;
+                }
+            }
+            Outbound::Close => break,
+        }
+    }
+    
+    let _ = session.close(None).await;
+    hub::unregister(&connection);
 }
 
-pub fn broadcast_recall(message_id: &str) {
-    let event = WsEvent {
-        event: "message_recalled".to_string(),
-        data: serde_json::json!({ "message_id": message_id }),
+// 处理客户端消息，与 HTTP 接口调用相同的逻辑
+fn handle_client_frame(connection: &Connection, scopes: Option<&[TokenScope]>, text: &str) {
+    let user_id = connection.user_id.as_str();
+    let frame = match serde_json::from_str::<ClientFrame>(text) {
+        Ok(frame) => frame,
+        Err(e) => {
+            reply(connection, None, Err(format!("无法解析的消息：{}", e)));
+            return;
+        }
     };
-    
-    let msg = serde_json::to_string(&event).unwrap();
-    let connections = CONNECTIONS.lock().unwrap();
-    
-    for (_user_id, session) in connections.iter() {
-        let mut session = session.clone();
-        let msg = msg.clone();
-        actix_web::rt::spawn(async move {
-            let _ = session.text(msg).await;
-        });
+
+    // 通过访问令牌连接时，除补发外的操作都需要发送消息权限
+    let allows = |scope: TokenScope| scopes.map(|s| s.contains(&scope)).unwrap_or(true);
+    if !matches!(frame.action, ClientAction::Resume { .. }) && !allows(TokenScope::SendMessages) {
+        reply(connection, frame.request_id, Err("访问令牌缺少权限：SendMessages".to_string()));
+        return;
     }
-}
 
-pub fn broadcast_user_banned(banned_user_id: &str) {
-    let event = WsEvent {
-        event: "user_banned".to_string(),
-        data: serde_json::json!({ "user_id": banned_user_id }),
-    };
-    
-    let msg = serde_json::to_string(&event).unwrap();
-    let connections = CONNECTIONS.lock().unwrap();
-    
-    for (user_id, session) in connections.iter() {
-        let mut session = session.clone();
-        let msg = msg.clone();
-        let is_banned = user_id == banned_user_id;
-        
-        actix_web::rt::spawn(async move {
-            let _ = session.text(msg).await;
-            if is_banned {
-                let _ = session.close(None).await;
+    let result = match frame.action {
+        ClientAction::Send { content, attachment_ids } => {
+            match crate::commands::dispatch(user_id, &content, &attachment_ids, allows(TokenScope::Moderate)) {
+                Some(result) => result,
+                None => crate::chat::send_message(user_id, &content, &attachment_ids)
+                    .map(|message| serde_json::to_value(message).unwrap()),
             }
-        });
+        }
+        ClientAction::Recall { message_id, reason } => {
+            crate::chat::recall_message(user_id, &message_id, allows(TokenScope::Moderate), reason.as_deref())
+                .map(|_| serde_json::Value::Null)
+        }
+        ClientAction::React { message_id, emoji } => {
+            crate::chat::react_message(user_id, &message_id, &emoji)
+                .map(|reactions| serde_json::to_value(reactions).unwrap())
+        }
+        ClientAction::Typing => {
+            if crate::presence::allow_typing(user_id) {
+                broadcast_typing(user_id);
+            }
+            Ok(serde_json::Value::Null)
+        }
+        ClientAction::MarkRead { message_id } => {
+            crate::chat::mark_read(user_id, &message_id)
+                .map(|unread| serde_json::to_value(unread).unwrap())
+        }
+        ClientAction::Resume { last_seq, epoch } => {
+            Ok(hub::resume(connection, last_seq, epoch.as_deref()))
+        }
+    };
+
+    reply(connection, frame.request_id, result);
+}
+
+// 只回复给发起请求的连接
+fn reply(connection: &Connection, request_id: Option<String>, result: Result<serde_json::Value, String>) {
+    let event = match result {
+        Ok(data) => ServerEvent::Ack { request_id, data },
+        Err(message) => ServerEvent::Error { request_id, message },
+    };
+
+    hub::send_to_connection(&connection.id, event.to_text());
+}
+
+// 发送给所有连接；closing_user_id 的连接在收到事件后被关闭
+fn send_to_all(event: &ServerEvent, closing_user_id: Option<&str>) {
+    hub::publish(event, Audience::All);
+    if let Some(user_id) = closing_user_id {
+        hub::close_user(user_id);
     }
 }
 
-pub fn broadcast_role_changed(user_id: &str, old_role: &UserRole, new_role: &UserRole) {
-    let event = WsEvent {
-        event: "role_changed".to_string(),
-        data: serde_json::json!({ 
-            "user_id": user_id,
-            "old_role": old_role,
-            "new_role": new_role
-        }),
+// 发送给指定用户的所有连接
+fn send_to_user(user_id: &str, event: &ServerEvent) {
+    hub::publish(event, Audience::User(user_id.to_string()));
+}
+
+pub fn broadcast_user_banned(banned_user_id: &str) {
+    let event = ServerEvent::UserBanned {
+        user_id: banned_user_id.to_string(),
     };
     
-    let msg = serde_json::to_string(&event).unwrap();
-    let connections = CONNECTIONS.lock().unwrap();
+    send_to_all(&event, Some(banned_user_id));
+}
+
+pub fn broadcast_user_unbanned(user_id: &str) {
+    let event = ServerEvent::UserUnbanned {
+        user_id: user_id.to_string(),
+    };
     
-    for (_uid, session) in connections.iter() {
-        let mut session = session.clone();
-        let msg = msg.clone();
-        actix_web::rt::spawn(async move {
-            let _ = session.text(msg).await;
-        });
-    }
+    send_to_all(&event, None);
 }
 
-pub fn broadcast_message_with_user(message: &MessageWithUser) {
-    let event = WsEvent {
-        event: "new_message".to_string(),
-        data: serde_json::to_value(message).unwrap(),
+pub fn broadcast_role_changed(user_id: &str, old_role: &str, new_role: &str) {
+    let event = ServerEvent::RoleChanged {
+        user_id: user_id.to_string(),
+        old_role: old_role.to_string(),
+        new_role: new_role.to_string(),
     };
     
-    let msg = serde_json::to_string(&event).unwrap();
-    let connections = CONNECTIONS.lock().unwrap();
+    send_to_all(&event, None);
+}
+
+pub fn broadcast_message_with_user(message: &MessageWithUser) {
+    let event = ServerEvent::NewMessage(message.clone());
     
-    for (_user_id, session) in connections.iter() {
-        let mut session = session.clone();
-        let msg = msg.clone();
-        actix_web::rt::spawn(async move {
-            let _ = session.text(msg).await;
-        });
-    }
+    send_to_all(&event, None);
 }
 
+#[allow(dead_code)]
 pub fn broadcast_message(message: &Message) {
     // 获取用户信息
     let state = crate::APP_STATE.lock().unwrap();
-    let user = state.users.get(&message.user_id).cloned();
+    let message_with_user = state.message_with_user(message);
     drop(state); // 释放锁
     
-    let message_with_user = MessageWithUser {
-        id: message.id.clone(),
-        user_id: message.user_id.clone(),
-        content: message.content.clone(),
-        timestamp: message.timestamp,
-        recalled: message.recalled,
-        user,
-        original_content: message.original_content.clone(),
-    };
-    
     broadcast_message_with_user(&message_with_user);
 }
 
 pub fn broadcast_recall_with_message(message: &MessageWithUser) {
-    let event = WsEvent {
-        event: "message_recalled_with_data".to_string(),
-        data: serde_json::to_value(message).unwrap(),
+    let event = ServerEvent::MessageRecalled(message.clone());
+    
+    send_to_all(&event, None);
+}
+
+// 新增：批量清理只广播一条事件
+pub fn broadcast_messages_purged(user_id: &str, message_ids: &[String], mode: PurgeMode, purged_by: &str) {
+    let event = ServerEvent::MessagesPurged {
+        user_id: user_id.to_string(),
+        message_ids: message_ids.to_vec(),
+        mode,
+        purged_by: purged_by.to_string(),
     };
     
-    let msg = serde_json::to_string(&event).unwrap();
-    let connections = CONNECTIONS.lock().unwrap();
+    send_to_all(&event, None);
+}
+
+// 新增：广播表情回应变化
+pub fn broadcast_message_reacted(message_id: &str, reactions: &Reactions) {
+    let event = ServerEvent::MessageReacted {
+        message_id: message_id.to_string(),
+        reactions: reactions.clone(),
+    };
     
-    for (_user_id, session) in connections.iter() {
-        let mut session = session.clone();
-        let msg = msg.clone();
-        actix_web::rt::spawn(async move {
-            let _ = session.text(msg).await;
-        });
-    }
+    send_to_all(&event, None);
 }
 
 // 新增：广播用户删除事件
 pub fn broadcast_user_deleted(deleted_user_id: &str) {
-    let event = WsEvent {
-        event: "user_deleted".to_string(),
-        data: serde_json::json!({ "user_id": deleted_user_id }),
-    };
-    
-    let msg = serde_json::to_string(&event).unwrap();
-    let connections = CONNECTIONS.lock().unwrap();
-    
-    for (user_id, session) in connections.iter() {
-        let mut session = session.clone();
-        let msg = msg.clone();
-        let is_deleted = user_id == deleted_user_id;
-        
-        actix_web::rt::spawn(async move {
-            let _ = session.text(msg).await;
-            if is_deleted {
-                let _ = session.close(None).await;
-            }
-        });
-    }
+    let event = ServerEvent::UserDeleted {
+        user_id: deleted_user_id.to_string(),
+    };
+    
+    send_to_all(&event, Some(deleted_user_id));
 }
 
 // 新增：广播昵称变更事件
 pub fn broadcast_display_name_changed(user_id: &str, old_name: &Option<String>, new_name: &Option<String>) {
-    let event = WsEvent {
-        event: "display_name_changed".to_string(),
-        data: serde_json::json!({ 
-            "user_id": user_id,
-            "old_display_name": old_name,
-            "new_display_name": new_name
-        }),
-    };
-    
-    let msg = serde_json::to_string(&event).unwrap();
-    let connections = CONNECTIONS.lock().unwrap();
-    
-    for (_uid, session) in connections.iter() {
-        let mut session = session.clone();
-        let msg = msg.clone();
-        actix_web::rt::spawn(async move {
-            let _ = session.text(msg).await;
-        });
+    let event = ServerEvent::DisplayNameChanged {
+        user_id: user_id.to_string(),
+        old_display_name: old_name.clone(),
+        new_display_name: new_name.clone(),
+    };
+    
+    send_to_all(&event, None);
+}
+
+// 新增：图片附件处理完成；已在消息中发出的附件通知所有人，否则只通知上传者
+pub fn send_attachment_updated(attachment: &Attachment, sent: bool) {
+    let event = ServerEvent::AttachmentUpdated(attachment.clone());
+    
+    if sent {
+        send_to_all(&event, None);
+    } else {
+        send_to_user(&attachment.uploader_id, &event);
     }
+}
+
+// 新增：广播头像变更事件
+pub fn broadcast_avatar_changed(user_id: &str, avatar: &Option<String>) {
+    let event = ServerEvent::AvatarChanged {
+        user_id: user_id.to_string(),
+        avatar: avatar.clone(),
+    };
+    
+    send_to_all(&event, None);
+}
+
+// 新增：只发送给指定用户的@提醒
+pub fn send_mentioned(message: &MessageWithUser) {
+    let event = ServerEvent::Mentioned(message.clone());
+    
+    for user_id in &message.mentions {
+        send_to_user(user_id, &event);
+    }
+}
+
+// 新增：同步已读位置到该用户的所有连接
+pub fn send_read_marker_updated(user_id: &str, unread: &UnreadSummary) {
+    let event = ServerEvent::ReadMarkerUpdated(unread.clone());
+    
+    send_to_user(user_id, &event);
+}
+
+// 新增：广播“正在输入”，不发给输入者本人的任何连接
+fn broadcast_typing(typing_user_id: &str) {
+    let event = ServerEvent::Typing {
+        user_id: typing_user_id.to_string(),
+    };
+    
+    hub::publish_transient(event.to_text(), typing_user_id);
+}
+
+// 新增：禁言通知，recipients 为被禁言的用户和管理员、次管理员
+pub fn send_user_muted(recipients: Vec<String>, user_id: &str, muted_until: DateTime<Utc>, reason: &Option<String>, muted_by: &str) {
+    let event = ServerEvent::UserMuted {
+        user_id: user_id.to_string(),
+        muted_until,
+        reason: reason.clone(),
+        muted_by: muted_by.to_string(),
+    };
+    
+    hub::publish(&event, Audience::Users(recipients));
+}
+
+pub fn send_user_unmuted(recipients: Vec<String>, user_id: &str, unmuted_by: Option<&str>) {
+    let event = ServerEvent::UserUnmuted {
+        user_id: user_id.to_string(),
+        unmuted_by: unmuted_by.map(str::to_string),
+    };
+    
+    hub::publish(&event, Audience::Users(recipients));
+}
+
+// 新增：广播话题变更
+pub fn broadcast_topic_changed(topic: &Option<String>, changed_by: &str) {
+    let event = ServerEvent::TopicChanged {
+        topic: topic.clone(),
+        changed_by: changed_by.to_string(),
+    };
+    
+    send_to_all(&event, None);
+}
+
+// 新增：广播消息置顶或取消置顶
+pub fn broadcast_message_pinned(message_id: &str, pinned: bool, changed_by: &str) {
+    let event = ServerEvent::MessagePinned {
+        message_id: message_id.to_string(),
+        pinned,
+        changed_by: changed_by.to_string(),
+    };
+    
+    send_to_all(&event, None);
+}
+
+// 新增：命令回复只发给调用者本人的连接
+pub fn send_command_reply(user_id: &str, command: &str, text: &str) {
+    let event = ServerEvent::CommandReply {
+        command: command.to_string(),
+        text: text.to_string(),
+    };
+    
+    hub::publish_transient_to_user(event.to_text(), user_id);
+}
+
+// 新增：把命令调用转发给机器人；记入事件日志，机器人断线重连后可以补收
+pub fn send_command_invoked(bot_user_id: &str, invocation_id: &str, command: &str, args: &str, user_id: &str) {
+    let event = ServerEvent::CommandInvoked {
+        invocation_id: invocation_id.to_string(),
+        command: command.to_string(),
+        args: args.to_string(),
+        user_id: user_id.to_string(),
+    };
+    
+    send_to_user(bot_user_id, &event);
+}
+
+// 新增：广播在线状态变化
+pub fn broadcast_presence_changed(info: &PresenceInfo) {
+    let event = ServerEvent::PresenceChanged(info.clone());
+    
+    send_to_all(&event, None);
 }
\ No newline at end of file